@@ -0,0 +1,61 @@
+//! Reading of the plain, most significant bit first, fields in VP8 and VP9
+//! headers.
+
+use {Error, ErrorKind};
+
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    /// In bits.
+    pos: usize,
+}
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            data: data,
+            pos: 0,
+        }
+    }
+
+    /// The number of bits read so far.
+    pub fn position(&self) -> usize { self.pos }
+    /// The number of bytes the bits read so far are in.
+    pub fn bytes_read(&self) -> usize { (self.pos + 7) / 8 }
+    pub fn remaining(&self) -> usize { self.data.len() * 8 - self.pos }
+
+    pub fn read_bit(&mut self) -> Result<bool, Error> {
+        if self.pos >= self.data.len() * 8 {
+            return Err(Error::new(ErrorKind::CorruptFrame,
+                                  "header is truncated".to_string()));
+        }
+        let byte = self.data[self.pos / 8];
+        let bit = byte >> (7 - self.pos % 8) & 1;
+        self.pos += 1;
+        Ok(bit != 0)
+    }
+    /// Read an `n` bit unsigned integer. `n` is at most 32.
+    pub fn read_bits(&mut self, n: u32) -> Result<u32, Error> {
+        assert!(n <= 32);
+        if self.remaining() < n as usize {
+            return Err(Error::new(ErrorKind::CorruptFrame,
+                                  "header is truncated".to_string()));
+        }
+        let mut v = 0u64;
+        for _ in 0..n {
+            v = v << 1 | try!(self.read_bit()) as u64;
+        }
+        Ok(v as u32)
+    }
+    /// Read an `n` bit magnitude followed by a sign bit.
+    pub fn read_signed(&mut self, n: u32) -> Result<i32, Error> {
+        let v = try!(self.read_bits(n)) as i32;
+        if try!(self.read_bit()) { Ok(-v) } else { Ok(v) }
+    }
+    pub fn skip(&mut self, n: usize) -> Result<(), Error> {
+        if self.remaining() < n {
+            return Err(Error::new(ErrorKind::CorruptFrame,
+                                  "header is truncated".to_string()));
+        }
+        self.pos += n;
+        Ok(())
+    }
+}
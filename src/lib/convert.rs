@@ -0,0 +1,291 @@
+//! Conversion between the packed RGB formats and the planar YUV formats the
+//! encoders accept.
+
+use {ColorSpace, Error, ErrorKind, Format, Image};
+
+/// The range of the YUV samples.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Range {
+    /// Luma in 16 to 235, and chroma in 16 to 240 ("TV" or "studio" range).
+    Limited,
+    /// Everything in 0 to 255 ("PC" range).
+    Full,
+}
+
+/// Alignment of the images this module allocates.
+const ALIGN: u32 = 32;
+
+/// The luma weights of red and blue for a color space. Green gets the rest.
+struct Matrix {
+    kr: f32,
+    kb: f32,
+}
+impl Matrix {
+    fn new(cs: ColorSpace) -> Result<Matrix, Error> {
+        let (kr, kb) = match cs {
+            // libvpx treats an unknown color space as BT.601 too.
+            ColorSpace::Unknown | ColorSpace::BT601 |
+            ColorSpace::SMPTE170 => (0.299, 0.114),
+            ColorSpace::BT709 => (0.2126, 0.0722),
+            ColorSpace::SMPTE240 => (0.212, 0.087),
+            ColorSpace::BT2020 => (0.2627, 0.0593),
+            ColorSpace::Reserved | ColorSpace::SRGB => {
+                return Err(Error::new(ErrorKind::InvalidParam,
+                                      format!("{:?} has no YUV matrix", cs)));
+            },
+        };
+        Ok(Matrix {
+            kr: kr,
+            kb: kb,
+        })
+    }
+    fn kg(&self) -> f32 { 1.0 - self.kr - self.kb }
+
+    /// Returns luma in 0 to 1, and chroma in -0.5 to 0.5.
+    fn to_yuv(&self, (r, g, b): (u8, u8, u8)) -> (f32, f32, f32) {
+        let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+        let y = self.kr * r + self.kg() * g + self.kb * b;
+        let u = (b - y) / (2.0 * (1.0 - self.kb));
+        let v = (r - y) / (2.0 * (1.0 - self.kr));
+        (y, u, v)
+    }
+    fn to_rgb(&self, y: f32, u: f32, v: f32) -> (u8, u8, u8) {
+        let r = y + 2.0 * (1.0 - self.kr) * v;
+        let b = y + 2.0 * (1.0 - self.kb) * u;
+        let g = (y - self.kr * r - self.kb * b) / self.kg();
+        (to_u8(r * 255.0), to_u8(g * 255.0), to_u8(b * 255.0))
+    }
+}
+
+fn to_u8(v: f32) -> u8 {
+    let v = v.round();
+    if v < 0.0 {
+        0
+    } else if v > 255.0 {
+        255
+    } else {
+        v as u8
+    }
+}
+
+impl Range {
+    fn encode_luma(&self, y: f32) -> u8 {
+        match *self {
+            Range::Limited => to_u8(16.0 + y * 219.0),
+            Range::Full => to_u8(y * 255.0),
+        }
+    }
+    fn encode_chroma(&self, c: f32) -> u8 {
+        match *self {
+            Range::Limited => to_u8(128.0 + c * 224.0),
+            Range::Full => to_u8(128.0 + c * 255.0),
+        }
+    }
+    fn decode_luma(&self, y: u8) -> f32 {
+        match *self {
+            Range::Limited => (y as f32 - 16.0) / 219.0,
+            Range::Full => y as f32 / 255.0,
+        }
+    }
+    fn decode_chroma(&self, c: u8) -> f32 {
+        match *self {
+            Range::Limited => (c as f32 - 128.0) / 224.0,
+            Range::Full => (c as f32 - 128.0) / 255.0,
+        }
+    }
+}
+
+fn is_rgb(fmt: Format) -> bool {
+    use Format::*;
+    match fmt {
+        RGB24 | BGR24 | RGB32 { .. } | ARGB | BGRA |
+        RGB565 { .. } | RGB555 { .. } => true,
+        _ => false,
+    }
+}
+fn is_yuv(fmt: Format) -> bool {
+    use Format::*;
+    match fmt {
+        YV12_VPX | I420_VPX | YV12 | I420 { hi_bit_depth: false } |
+        I422 { hi_bit_depth: false } | I440 { hi_bit_depth: false } |
+        I444 { hi_bit_depth: false } => true,
+        _ => false,
+    }
+}
+
+/// Expand a 5 or 6 bit component to 8 bits.
+fn expand(v: u16, bits: u32) -> u8 {
+    let v = v as u32;
+    ((v << (8 - bits)) | (v >> (2 * bits - 8))) as u8
+}
+
+fn read_rgb(fmt: Format, px: &[u8]) -> (u8, u8, u8) {
+    use Format::*;
+    match fmt {
+        RGB24 => (px[0], px[1], px[2]),
+        BGR24 => (px[2], px[1], px[0]),
+        // 0RGB and ARGB
+        RGB32 { le: false, } | ARGB => (px[1], px[2], px[3]),
+        // BGR0 and BGRA
+        RGB32 { le: true, } | BGRA => (px[2], px[1], px[0]),
+        RGB565 { le } => {
+            let v = if le {
+                px[0] as u16 | (px[1] as u16) << 8
+            } else {
+                (px[0] as u16) << 8 | px[1] as u16
+            };
+            (expand(v >> 11, 5), expand((v >> 5) & 0x3f, 6), expand(v & 0x1f, 5))
+        },
+        RGB555 { le } => {
+            let v = if le {
+                px[0] as u16 | (px[1] as u16) << 8
+            } else {
+                (px[0] as u16) << 8 | px[1] as u16
+            };
+            (expand((v >> 10) & 0x1f, 5), expand((v >> 5) & 0x1f, 5), expand(v & 0x1f, 5))
+        },
+        _ => unreachable!(),
+    }
+}
+fn write_rgb(fmt: Format, px: &mut [u8], (r, g, b): (u8, u8, u8)) {
+    use Format::*;
+    match fmt {
+        RGB24 => { px[0] = r; px[1] = g; px[2] = b; },
+        BGR24 => { px[0] = b; px[1] = g; px[2] = r; },
+        RGB32 { le: false, } => { px[0] = 0; px[1] = r; px[2] = g; px[3] = b; },
+        ARGB => { px[0] = 255; px[1] = r; px[2] = g; px[3] = b; },
+        RGB32 { le: true, } => { px[0] = b; px[1] = g; px[2] = r; px[3] = 0; },
+        BGRA => { px[0] = b; px[1] = g; px[2] = r; px[3] = 255; },
+        RGB565 { le } | RGB555 { le } => {
+            let v = match fmt {
+                RGB565 { .. } => {
+                    (r as u16 >> 3) << 11 | (g as u16 >> 2) << 5 | b as u16 >> 3
+                },
+                _ => {
+                    (r as u16 >> 3) << 10 | (g as u16 >> 3) << 5 | b as u16 >> 3
+                },
+            };
+            if le {
+                px[0] = v as u8;
+                px[1] = (v >> 8) as u8;
+            } else {
+                px[0] = (v >> 8) as u8;
+                px[1] = v as u8;
+            }
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn unflipped_plane<'a>(image: &'a Image, plane: usize) -> Result<&'a [u8], Error> {
+    image.plane(plane)
+        .ok_or_else(|| {
+            Error::new(ErrorKind::InvalidParam,
+                       "flipped images can't be converted".to_string())
+        })
+}
+
+/// Convert `src`, which must be in one of the packed RGB formats, to `fmt`,
+/// an 8-bit planar YUV format, using the matrix of `cs`. Chroma is
+/// subsampled by averaging.
+pub fn rgb_to_yuv(src: &Image, fmt: Format, cs: ColorSpace,
+                  range: Range) -> Result<Image<'static>, Error> {
+    let src_fmt = src.get_format();
+    if !is_rgb(src_fmt) || !is_yuv(fmt) {
+        return Err(Error::new(ErrorKind::Incapable,
+                              format!("can't convert {:?} to {:?}", src_fmt, fmt)));
+    }
+    let matrix = try!(Matrix::new(cs));
+
+    let (width, height) = (src.display_width() as usize,
+                           src.display_height() as usize);
+    let mut dst = try!(Image::alloc(fmt, cs, width as u32, height as u32, ALIGN));
+
+    let src_plane = try!(unflipped_plane(src, 0));
+    let src_stride = src.stride(0).unwrap() as usize;
+    let bpp = src_fmt.bytes_per_sample();
+    let (x_shift, y_shift) = fmt.chroma_shift();
+    let (chroma_width, chroma_height) = dst.plane_size(1);
+    let (chroma_width, chroma_height) = (chroma_width as usize, chroma_height as usize);
+
+    // Chroma sums for the current row of chroma samples.
+    let mut u_sum = vec![0.0f32; chroma_width];
+    let mut v_sum = vec![0.0f32; chroma_width];
+    let mut count = vec![0u32; chroma_width];
+
+    for cy in 0..chroma_height {
+        for i in 0..chroma_width {
+            u_sum[i] = 0.0;
+            v_sum[i] = 0.0;
+            count[i] = 0;
+        }
+
+        let first_row = cy << y_shift;
+        let last_row = ::std::cmp::min(first_row + (1 << y_shift), height);
+        for y in first_row..last_row {
+            let src_row = &src_plane[y * src_stride..];
+            let dst_stride = dst.stride(0).unwrap() as usize;
+            let luma = dst.plane_mut(0).unwrap();
+            for x in 0..width {
+                let (l, u, v) = matrix.to_yuv(read_rgb(src_fmt, &src_row[x * bpp..]));
+                luma[y * dst_stride + x] = range.encode_luma(l);
+                u_sum[x >> x_shift] += u;
+                v_sum[x >> x_shift] += v;
+                count[x >> x_shift] += 1;
+            }
+        }
+
+        for &(plane, ref sum) in [(1, &u_sum), (2, &v_sum)].iter() {
+            let stride = dst.stride(plane).unwrap() as usize;
+            let dst_plane = dst.plane_mut(plane).unwrap();
+            for cx in 0..chroma_width {
+                dst_plane[cy * stride + cx] =
+                    range.encode_chroma(sum[cx] / count[cx] as f32);
+            }
+        }
+    }
+
+    Ok(dst)
+}
+
+/// Convert `src`, which must be in an 8-bit planar YUV format, to `fmt`, one
+/// of the packed RGB formats, using the matrix of `src`'s color space.
+pub fn yuv_to_rgb(src: &Image, fmt: Format,
+                  range: Range) -> Result<Image<'static>, Error> {
+    let src_fmt = src.get_format();
+    if !is_yuv(src_fmt) || !is_rgb(fmt) {
+        return Err(Error::new(ErrorKind::Incapable,
+                              format!("can't convert {:?} to {:?}", src_fmt, fmt)));
+    }
+    let cs = src.get_color_space();
+    let matrix = try!(Matrix::new(cs));
+
+    let (width, height) = (src.display_width() as usize,
+                           src.display_height() as usize);
+    let mut dst = try!(Image::alloc(fmt, cs, width as u32, height as u32, ALIGN));
+
+    let planes = [try!(unflipped_plane(src, 0)),
+                  try!(unflipped_plane(src, 1)),
+                  try!(unflipped_plane(src, 2))];
+    let strides = [src.stride(0).unwrap() as usize,
+                   src.stride(1).unwrap() as usize,
+                   src.stride(2).unwrap() as usize];
+    let (x_shift, y_shift) = src_fmt.chroma_shift();
+    let bpp = fmt.bytes_per_sample();
+    let dst_stride = dst.stride(0).unwrap() as usize;
+    let dst_plane = dst.plane_mut(0).unwrap();
+
+    for y in 0..height {
+        let cy = y >> y_shift;
+        for x in 0..width {
+            let cx = x >> x_shift;
+            let l = range.decode_luma(planes[0][y * strides[0] + x]);
+            let u = range.decode_chroma(planes[1][cy * strides[1] + cx]);
+            let v = range.decode_chroma(planes[2][cy * strides[2] + cx]);
+            let px = &mut dst_plane[y * dst_stride + x * bpp..];
+            write_rgb(fmt, px, matrix.to_rgb(l, u, v));
+        }
+    }
+
+    Ok(dst)
+}
@@ -0,0 +1,146 @@
+use ffi;
+use super::{ColorSpace, Error, Format};
+
+use libc;
+
+use std::convert::TryFrom;
+
+pub mod vp8;
+pub mod vp9;
+
+pub trait Decoder: InternalDecoder {
+    /// Decode one compressed frame. Any frames this produces can then be
+    /// retrieved with `frames`.
+    fn decode(&mut self, data: &[u8]) -> Result<(), Error> {
+        let res = unsafe {
+            ffi::vpx_codec_decode(self.get_mut_ctx(),
+                                  data.as_ptr(),
+                                  data.len() as libc::c_uint,
+                                  0 as *mut _,
+                                  0)
+        };
+        if res == ffi::VPX_CODEC_OK {
+            Ok(())
+        } else {
+            Err(unsafe { Error::from_ctx(self.get_mut_ctx(), res) })
+        }
+    }
+
+    /// Signal the end of the stream, so any frames the decoder is holding on
+    /// to are made available through `frames`.
+    fn flush(&mut self) -> Result<(), Error> {
+        let res = unsafe {
+            ffi::vpx_codec_decode(self.get_mut_ctx(),
+                                  0 as *const _, 0,
+                                  0 as *mut _, 0)
+        };
+        if res == ffi::VPX_CODEC_OK {
+            Ok(())
+        } else {
+            Err(unsafe { Error::from_ctx(self.get_mut_ctx(), res) })
+        }
+    }
+
+    /// Iterate over the images produced by the last call to `decode`.
+    fn frames<'a>(&'a mut self) -> Frames<'a, Self> where Self: Sized {
+        Frames {
+            ctx: self,
+            iter: 0 as *mut _,
+        }
+    }
+}
+
+#[doc(hidden)]
+pub trait InternalDecoder {
+    fn get_ref_ctx(&self) -> *const ffi::vpx_codec_ctx_t;
+    fn get_mut_ctx(&mut self) -> *mut ffi::vpx_codec_ctx_t;
+}
+
+pub struct Frames<'a, T: Decoder + 'a> {
+    ctx: &'a mut T,
+    iter: ffi::vpx_codec_iter_t,
+}
+impl<'a, T: Decoder + 'a> Iterator for Frames<'a, T> {
+    type Item = Image<'a>;
+    fn next(&mut self) -> Option<Image<'a>> {
+        let img = unsafe {
+            ffi::vpx_codec_get_frame(self.ctx.get_mut_ctx(),
+                                     &mut self.iter as *mut _)
+        };
+        if img.is_null() {
+            None
+        } else {
+            Some(Image(unsafe { &*img }))
+        }
+    }
+}
+
+/// A decoded image. It is owned by the decoder, and is only valid until the
+/// next call to `Decoder::decode`.
+pub struct Image<'a>(&'a ffi::vpx_image_t);
+
+impl<'a> Image<'a> {
+    fn raw(&self) -> &'a ffi::vpx_image_t { self.0 }
+
+    pub fn get_raw_format(&self) -> ffi::vpx_img_fmt_t { self.raw().fmt }
+    pub fn get_raw_color_space(&self) -> ffi::vpx_color_space_t { self.raw().cs }
+    pub fn get_format(&self) -> Result<Format, Error> {
+        TryFrom::try_from(self.raw().fmt)
+    }
+    pub fn get_color_space(&self) -> Result<ColorSpace, Error> {
+        TryFrom::try_from(self.raw().cs)
+    }
+
+    pub fn width(&self) -> u32 { self.raw().w }
+    pub fn height(&self) -> u32 { self.raw().h }
+    pub fn display_width(&self) -> u32 { self.raw().d_w }
+    pub fn display_height(&self) -> u32 { self.raw().d_h }
+    pub fn bit_depth(&self) -> u32 { self.raw().bit_depth }
+    pub fn x_chroma_shift(&self) -> u32 { self.raw().x_chroma_shift }
+    pub fn y_chroma_shift(&self) -> u32 { self.raw().y_chroma_shift }
+
+    /// The stride, in bytes, of `plane` (0 to 3: Y, U, V, alpha), or `None`
+    /// if there's no such plane.
+    pub fn stride(&self, plane: usize) -> Option<usize> {
+        self.raw().stride.get(plane).map(|&stride| stride as usize)
+    }
+    /// Whether the samples are 16 bits wide, ie `bit_depth` is above 8.
+    pub fn is_high_bit_depth(&self) -> bool {
+        self.raw().fmt & ffi::VPX_IMG_FMT_HIGHBITDEPTH != 0
+    }
+
+    /// The rows of `plane` covering the display size, or `None` if the
+    /// format doesn't have that plane.
+    pub fn plane(&self, plane: usize) -> Option<&'a [u8]> {
+        let raw = self.raw();
+        let ptr = match raw.planes.get(plane) {
+            Some(&ptr) if !ptr.is_null() => ptr,
+            _ => return None,
+        };
+
+        let rows = if plane == 1 || plane == 2 {
+            (raw.d_h + raw.y_chroma_shift) >> raw.y_chroma_shift
+        } else {
+            raw.d_h
+        };
+        let len = raw.stride[plane] as usize * rows as usize;
+        Some(unsafe { ::std::slice::from_raw_parts(ptr as *const u8, len) })
+    }
+    /// `plane` in samples, for high bit depth images. The stride is still in
+    /// bytes.
+    pub fn plane_u16(&self, plane: usize) -> Option<&'a [u16]> {
+        if !self.is_high_bit_depth() { return None; }
+        self.plane(plane).and_then(|bytes| {
+            if bytes.as_ptr() as usize % 2 != 0 { return None; }
+            Some(unsafe {
+                ::std::slice::from_raw_parts(bytes.as_ptr() as *const u16,
+                                             bytes.len() / 2)
+            })
+        })
+    }
+}
+impl<'a> AsRef<ffi::vpx_image_t> for Image<'a> {
+    fn as_ref(&self) -> &ffi::vpx_image_t {
+        self.raw()
+    }
+}
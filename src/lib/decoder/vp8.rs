@@ -0,0 +1,162 @@
+use ffi;
+
+use {InternalInterface, Error, Kind};
+
+use libc;
+
+use std::ops::{Deref, DerefMut};
+
+#[derive(Copy, Clone)]
+pub struct Cfg(ffi::vpx_codec_dec_cfg_t);
+impl Default for Cfg {
+    fn default() -> Cfg {
+        Cfg(Default::default())
+    }
+}
+impl AsRef<ffi::vpx_codec_dec_cfg_t> for Cfg {
+    fn as_ref(&self) -> &ffi::vpx_codec_dec_cfg_t {
+        &self.0
+    }
+}
+impl AsMut<ffi::vpx_codec_dec_cfg_t> for Cfg {
+    fn as_mut(&mut self) -> &mut ffi::vpx_codec_dec_cfg_t {
+        &mut self.0
+    }
+}
+impl Deref for Cfg {
+    type Target = ffi::vpx_codec_dec_cfg_t;
+    fn deref(&self) -> &ffi::vpx_codec_dec_cfg_t {
+        &self.0
+    }
+}
+impl DerefMut for Cfg {
+    fn deref_mut(&mut self) -> &mut ffi::vpx_codec_dec_cfg_t {
+        &mut self.0
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Interface;
+impl Default for Interface {
+    fn default() -> Interface {
+        Interface
+    }
+}
+impl ::Interface for Interface {
+    type Context = Context;
+    type Cfg = Cfg;
+    fn kind(&self) -> Kind { Kind::Decoder }
+
+    fn create(&self, cfg: <Self as ::Interface>::Cfg,
+              flags: ffi::vpx_codec_flags_t) ->
+        Result<<Self as ::Interface>::Context, Error>
+    {
+        let mut ctx: ffi::vpx_codec_ctx_t = Default::default();
+        let err = unsafe {
+            ffi::vpx_codec_dec_init_ver(&mut ctx as *mut _,
+                                        self.iface(),
+                                        &cfg.0 as *const _,
+                                        flags,
+                                        ffi::VPX_DECODER_ABI_VERSION as i32)
+        };
+        if err != ffi::VPX_CODEC_OK {
+            Err(unsafe { Error::from_ctx(&mut ctx as *mut _, err) })
+        } else {
+            Ok(Context(ctx))
+        }
+    }
+}
+impl InternalInterface for Interface {
+    fn iface(&self) -> *mut ffi::vpx_codec_iface_t {
+        unsafe { &mut ffi::vpx_codec_vp8_dx_algo as *mut _ }
+    }
+}
+
+pub struct Context(ffi::vpx_codec_ctx_t);
+unsafe impl Send for Context {}
+impl Drop for Context {
+    fn drop(&mut self) {
+        unsafe { ffi::vpx_codec_destroy(&mut self.0 as *mut _); }
+    }
+}
+impl super::InternalDecoder for Context {
+    fn get_ref_ctx(&self) -> *const ffi::vpx_codec_ctx_t {
+        &self.0 as *const _
+    }
+    fn get_mut_ctx(&mut self) -> *mut ffi::vpx_codec_ctx_t {
+        &mut self.0 as *mut _
+    }
+}
+impl super::Decoder for Context {}
+impl Context {
+    fn get_int_control(&mut self, id: ffi::Enum_vp8_dec_control_id) -> Result<libc::c_int, Error> {
+        let mut out: libc::c_int = 0;
+        let res = unsafe {
+            ffi::vpx_codec_control_(&mut self.0 as *mut _,
+                                    id as libc::c_int,
+                                    &mut out as *mut libc::c_int)
+        };
+        if res == ffi::VPX_CODEC_OK {
+            Ok(out)
+        } else {
+            Err(unsafe { Error::from_ctx(&mut self.0 as *mut _, res) })
+        }
+    }
+
+    /// Whether the last decoded frame was corrupt, ie the decoder had to
+    /// conceal errors in it or a reference it used was itself corrupt.
+    pub fn is_frame_corrupted(&mut self) -> Result<bool, Error> {
+        self.get_int_control(ffi::VP8D_GET_FRAME_CORRUPTED)
+            .map(|v| v != 0)
+    }
+    /// The reference buffers the last decoded frame updated.
+    pub fn last_ref_updates(&mut self) -> Result<RefFrames, Error> {
+        self.get_int_control(ffi::VP8D_GET_LAST_REF_UPDATES)
+            .map(From::from)
+    }
+    /// The reference buffers the last decoded frame was predicted from.
+    pub fn last_ref_used(&mut self) -> Result<RefFrames, Error> {
+        self.get_int_control(ffi::VP8D_GET_LAST_REF_USED)
+            .map(From::from)
+    }
+
+    /// Query everything about the last decoded frame at once. Call after
+    /// `Decoder::decode`.
+    pub fn frame_info(&mut self) -> Result<FrameInfo, Error> {
+        Ok(FrameInfo {
+            corrupted: try!(self.is_frame_corrupted()),
+            ref_updates: try!(self.last_ref_updates()),
+            ref_used: try!(self.last_ref_used()),
+        })
+    }
+}
+
+/// A set of VP8 reference buffers.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct RefFrames {
+    pub last: bool,
+    pub golden: bool,
+    pub altref: bool,
+}
+impl RefFrames {
+    pub fn is_empty(&self) -> bool {
+        !(self.last || self.golden || self.altref)
+    }
+}
+impl From<libc::c_int> for RefFrames {
+    fn from(v: libc::c_int) -> RefFrames {
+        let v = v as ffi::vpx_ref_frame_type_t;
+        RefFrames {
+            last: v & ffi::VP8_LAST_FRAME != 0,
+            golden: v & ffi::VP8_GOLD_FRAME != 0,
+            altref: v & ffi::VP8_ALTR_FRAME != 0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct FrameInfo {
+    pub corrupted: bool,
+    pub ref_updates: RefFrames,
+    pub ref_used: RefFrames,
+}
@@ -0,0 +1,107 @@
+use ffi;
+
+use {BitDepth, InternalInterface, Error, Kind};
+
+use libc;
+
+use std::convert::TryFrom;
+use std::ops::{Deref, DerefMut};
+
+#[derive(Copy, Clone)]
+pub struct Cfg(ffi::vpx_codec_dec_cfg_t);
+impl Default for Cfg {
+    fn default() -> Cfg {
+        Cfg(Default::default())
+    }
+}
+impl AsRef<ffi::vpx_codec_dec_cfg_t> for Cfg {
+    fn as_ref(&self) -> &ffi::vpx_codec_dec_cfg_t {
+        &self.0
+    }
+}
+impl AsMut<ffi::vpx_codec_dec_cfg_t> for Cfg {
+    fn as_mut(&mut self) -> &mut ffi::vpx_codec_dec_cfg_t {
+        &mut self.0
+    }
+}
+impl Deref for Cfg {
+    type Target = ffi::vpx_codec_dec_cfg_t;
+    fn deref(&self) -> &ffi::vpx_codec_dec_cfg_t {
+        &self.0
+    }
+}
+impl DerefMut for Cfg {
+    fn deref_mut(&mut self) -> &mut ffi::vpx_codec_dec_cfg_t {
+        &mut self.0
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Interface;
+impl Default for Interface {
+    fn default() -> Interface {
+        Interface
+    }
+}
+impl ::Interface for Interface {
+    type Context = Context;
+    type Cfg = Cfg;
+    fn kind(&self) -> Kind { Kind::Decoder }
+
+    fn create(&self, cfg: <Self as ::Interface>::Cfg,
+              flags: ffi::vpx_codec_flags_t) ->
+        Result<<Self as ::Interface>::Context, Error>
+    {
+        let mut ctx: ffi::vpx_codec_ctx_t = Default::default();
+        let err = unsafe {
+            ffi::vpx_codec_dec_init_ver(&mut ctx as *mut _,
+                                        self.iface(),
+                                        &cfg.0 as *const _,
+                                        flags,
+                                        ffi::VPX_DECODER_ABI_VERSION as i32)
+        };
+        if err != ffi::VPX_CODEC_OK {
+            Err(unsafe { Error::from_ctx(&mut ctx as *mut _, err) })
+        } else {
+            Ok(Context(ctx))
+        }
+    }
+}
+impl InternalInterface for Interface {
+    fn iface(&self) -> *mut ffi::vpx_codec_iface_t {
+        unsafe { &mut ffi::vpx_codec_vp9_dx_algo as *mut _ }
+    }
+}
+
+pub struct Context(ffi::vpx_codec_ctx_t);
+unsafe impl Send for Context {}
+impl Drop for Context {
+    fn drop(&mut self) {
+        unsafe { ffi::vpx_codec_destroy(&mut self.0 as *mut _); }
+    }
+}
+impl super::InternalDecoder for Context {
+    fn get_ref_ctx(&self) -> *const ffi::vpx_codec_ctx_t {
+        &self.0 as *const _
+    }
+    fn get_mut_ctx(&mut self) -> *mut ffi::vpx_codec_ctx_t {
+        &mut self.0 as *mut _
+    }
+}
+impl super::Decoder for Context {}
+impl Context {
+    /// The bit depth of the stream, as of the last decoded frame.
+    pub fn bit_depth(&mut self) -> Result<BitDepth, Error> {
+        let mut out: libc::c_uint = 0;
+        let res = unsafe {
+            ffi::vpx_codec_control_(&mut self.0 as *mut _,
+                                    ffi::VP9D_GET_BIT_DEPTH as libc::c_int,
+                                    &mut out as *mut libc::c_uint)
+        };
+        if res == ffi::VPX_CODEC_OK {
+            BitDepth::try_from(out)
+        } else {
+            Err(unsafe { Error::from_ctx(&mut self.0 as *mut _, res) })
+        }
+    }
+}
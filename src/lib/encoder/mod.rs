@@ -1,22 +1,49 @@
 use ffi;
-use super::{Error, Frame, Image};
+use super::{Error, ErrorKind, Frame, Image, OwnedFrame};
+pub use super::BitDepth;
 
 use libc;
 
+use std::collections::VecDeque;
+use std::fmt::Display;
+
+pub mod two_pass;
+pub mod vp8;
 pub mod vp9;
 
 pub const DL_REALTIME: u64 = 1;
 pub const DL_GOOD_QUALITY: u64 = 1000000;
 pub const DL_BEST_QUALITY: u64 = 0;
 
-#[derive(Copy, Clone, Eq, PartialEq)]
+/// Per-frame encoding flags. All but `keyframe` control how the frame uses
+/// and updates the reference buffers, which allows for custom reference
+/// structures, eg for temporal scalability or loss recovery.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
 pub struct FrameFlags {
     keyframe: bool,
+    no_ref_last: bool,
+    no_ref_golden: bool,
+    no_ref_altref: bool,
+    no_update_last: bool,
+    no_update_golden: bool,
+    no_update_altref: bool,
+    force_golden: bool,
+    force_altref: bool,
+    no_update_entropy: bool,
 }
 impl Default for FrameFlags {
     fn default() -> FrameFlags {
         FrameFlags {
             keyframe: false,
+            no_ref_last: false,
+            no_ref_golden: false,
+            no_ref_altref: false,
+            no_update_last: false,
+            no_update_golden: false,
+            no_update_altref: false,
+            force_golden: false,
+            force_altref: false,
+            no_update_entropy: false,
         }
     }
 }
@@ -24,32 +51,515 @@ impl Default for FrameFlags {
 impl Into<ffi::vpx_enc_frame_flags_t> for FrameFlags {
     fn into(self) -> ffi::vpx_enc_frame_flags_t {
         let mut flags: ffi::vpx_enc_frame_flags_t = 0;
-        if self.keyframe { flags |= 1 << 0; }
+        if self.keyframe { flags |= ffi::VPX_EFLAG_FORCE_KF; }
+        if self.no_ref_last { flags |= ffi::VP8_EFLAG_NO_REF_LAST; }
+        if self.no_ref_golden { flags |= ffi::VP8_EFLAG_NO_REF_GF; }
+        if self.no_ref_altref { flags |= ffi::VP8_EFLAG_NO_REF_ARF; }
+        if self.no_update_last { flags |= ffi::VP8_EFLAG_NO_UPD_LAST; }
+        if self.no_update_golden { flags |= ffi::VP8_EFLAG_NO_UPD_GF; }
+        if self.no_update_altref { flags |= ffi::VP8_EFLAG_NO_UPD_ARF; }
+        if self.force_golden { flags |= ffi::VP8_EFLAG_FORCE_GF; }
+        if self.force_altref { flags |= ffi::VP8_EFLAG_FORCE_ARF; }
+        if self.no_update_entropy { flags |= ffi::VP8_EFLAG_NO_UPD_ENTROPY; }
         return flags;
     }
 }
 impl FrameFlags {
     pub fn new() -> FrameFlags { Default::default() }
 
+    /// Force this frame to be a keyframe.
     pub fn keyframe(mut self, keyframe: bool) -> FrameFlags {
         self.keyframe = keyframe;
         self
     }
+
+    /// Don't predict from the last frame.
+    pub fn no_ref_last(mut self, no_ref_last: bool) -> FrameFlags {
+        self.no_ref_last = no_ref_last;
+        self
+    }
+
+    /// Don't predict from the golden frame.
+    pub fn no_ref_golden(mut self, no_ref_golden: bool) -> FrameFlags {
+        self.no_ref_golden = no_ref_golden;
+        self
+    }
+
+    /// Don't predict from the altref frame.
+    pub fn no_ref_altref(mut self, no_ref_altref: bool) -> FrameFlags {
+        self.no_ref_altref = no_ref_altref;
+        self
+    }
+
+    /// Don't update the last frame buffer with this frame.
+    pub fn no_update_last(mut self, no_update_last: bool) -> FrameFlags {
+        self.no_update_last = no_update_last;
+        self
+    }
+
+    /// Don't update the golden frame buffer with this frame.
+    pub fn no_update_golden(mut self, no_update_golden: bool) -> FrameFlags {
+        self.no_update_golden = no_update_golden;
+        self
+    }
+
+    /// Don't update the altref frame buffer with this frame.
+    pub fn no_update_altref(mut self, no_update_altref: bool) -> FrameFlags {
+        self.no_update_altref = no_update_altref;
+        self
+    }
+
+    /// Update the golden frame buffer with this frame.
+    pub fn force_golden(mut self, force_golden: bool) -> FrameFlags {
+        self.force_golden = force_golden;
+        self
+    }
+
+    /// Update the altref frame buffer with this frame.
+    pub fn force_altref(mut self, force_altref: bool) -> FrameFlags {
+        self.force_altref = force_altref;
+        self
+    }
+
+    /// Don't update the entropy contexts with this frame, so the next frame
+    /// doesn't depend on this one's probabilities.
+    pub fn no_update_entropy(mut self, no_update_entropy: bool) -> FrameFlags {
+        self.no_update_entropy = no_update_entropy;
+        self
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum RateControl {
+    /// Variable bitrate.
+    Vbr,
+    /// Constant bitrate.
+    Cbr,
+    /// Constrained quality: variable bitrate, but holding quality at
+    /// `Encoder::set_cq_level`.
+    Cq,
+    /// Constant quality, at `Encoder::set_cq_level`.
+    Q,
+}
+impl Into<ffi::Enum_vpx_rc_mode> for RateControl {
+    fn into(self) -> ffi::Enum_vpx_rc_mode {
+        match self {
+            RateControl::Vbr => ffi::VPX_VBR,
+            RateControl::Cbr => ffi::VPX_CBR,
+            RateControl::Cq => ffi::VPX_CQ,
+            RateControl::Q => ffi::VPX_Q,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Pass {
+    One,
+    /// The first of a two-pass encode, which only produces stats.
+    First,
+    /// The second of a two-pass encode, which consumes the first pass' stats.
+    Last,
+}
+impl Into<ffi::Enum_vpx_enc_pass> for Pass {
+    fn into(self) -> ffi::Enum_vpx_enc_pass {
+        match self {
+            Pass::One => ffi::VPX_RC_ONE_PASS,
+            Pass::First => ffi::VPX_RC_FIRST_PASS,
+            Pass::Last => ffi::VPX_RC_LAST_PASS,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum KeyframeMode {
+    /// Place keyframes automatically, between `min_dist` and `max_dist`
+    /// frames apart.
+    Auto {
+        min_dist: u32,
+        max_dist: u32,
+    },
+    /// Only emit keyframes when requested with `FrameFlags::keyframe`.
+    Disabled,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct ErrorResilient {
+    frames: bool,
+    partitions: bool,
+}
+impl Default for ErrorResilient {
+    fn default() -> ErrorResilient {
+        ErrorResilient {
+            frames: false,
+            partitions: false,
+        }
+    }
+}
+#[doc(hidden)]
+impl Into<ffi::vpx_codec_er_flags_t> for ErrorResilient {
+    fn into(self) -> ffi::vpx_codec_er_flags_t {
+        let mut flags: ffi::vpx_codec_er_flags_t = 0;
+        if self.frames { flags |= 0x1; }
+        if self.partitions { flags |= 0x2; }
+        flags
+    }
+}
+impl ErrorResilient {
+    pub fn new() -> ErrorResilient { Default::default() }
+
+    /// Improve resiliency against losses of whole frames
+    /// (`VPX_ERROR_RESILIENT_DEFAULT`).
+    pub fn frames(mut self, frames: bool) -> ErrorResilient {
+        self.frames = frames;
+        self
+    }
+    /// Make each partition decodable on its own, so the loss of one doesn't
+    /// take out the whole frame. VP8 only.
+    pub fn partitions(mut self, partitions: bool) -> ErrorResilient {
+        self.partitions = partitions;
+        self
+    }
+}
+
+/// The most temporal layers the presets have.
+const MAX_TEMPORAL_LAYERS: u32 = 3;
+
+/// How many frames' layers a `TemporalState` keeps, which is more than
+/// `g_lag_in_frames` allows.
+const LAYER_HISTORY: usize = 64;
+
+/// The layering mode of `layers` temporal layers, the layer of each frame in
+/// the pattern, and the cumulative share of the bitrate of each layer, in
+/// percent.
+fn temporal_pattern(layers: u32) -> (ffi::VP9E_TEMPORAL_LAYERING_MODE, &'static [u8], &'static [u32]) {
+    match layers {
+        2 => (ffi::VP9E_TEMPORAL_LAYERING_MODE_0101, &[0, 1], &[60, 100]),
+        3 => (ffi::VP9E_TEMPORAL_LAYERING_MODE_0212, &[0, 2, 1, 2], &[50, 70, 100]),
+        _ => (ffi::VP9E_TEMPORAL_LAYERING_MODE_NOLAYERING, &[0], &[100]),
+    }
+}
+
+/// A temporal scalability preset, where the frames of each layer only
+/// reference frames of the same or lower layers, so the upper layers can be
+/// dropped, eg by an SFU.
+///
+/// Two layers follow the 0101 pattern: every other frame is in layer 1, and
+/// nothing references them. Three follow 0212: the frame between two base
+/// layer frames is in layer 1, and the frames around it are in layer 2 and
+/// aren't referenced.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct TemporalLayering {
+    layers: u32,
+    bypass: bool,
+    bitrates: Option<Vec<u32>>,
+}
+impl TemporalLayering {
+    /// 1 to 3 layers.
+    pub fn new(layers: u32) -> TemporalLayering {
+        TemporalLayering {
+            layers: layers,
+            bypass: false,
+            bitrates: None,
+        }
+    }
+
+    /// Choose each frame's layer and references here, rather than in libvpx
+    /// (`VP9E_TEMPORAL_LAYERING_MODE_BYPASS`). The pattern restarts at each
+    /// forced keyframe. VP8 has no layering modes of its own, so its layers
+    /// and references always come from here; bypass only adds the restart.
+    pub fn bypass(mut self, bypass: bool) -> TemporalLayering {
+        self.bypass = bypass;
+        self
+    }
+    /// The bitrate of each layer including the layers below it, lowest
+    /// first, in kilobits per second, as libvpx takes them. This replaces
+    /// `target_bitrate` with the last. By default, `target_bitrate` is split
+    /// 60/40 between 2 layers, and 50/20/30 between 3.
+    pub fn bitrates(mut self, kbps: &[u32]) -> TemporalLayering {
+        self.bitrates = Some(kbps.to_vec());
+        self
+    }
+
+    pub fn get_layers(&self) -> u32 { self.layers }
+    pub fn get_bypass(&self) -> bool { self.bypass }
+    /// The layer of each frame in the pattern.
+    pub fn pattern(&self) -> &'static [u8] { temporal_pattern(self.layers).1 }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        fn invalid(detail: &str) -> Result<(), Error> {
+            Err(Error::new(ErrorKind::InvalidParam, detail.to_string()))
+        }
+
+        try!(check_range("temporal layers", self.layers, 1, MAX_TEMPORAL_LAYERS));
+        match self.bitrates {
+            Some(ref kbps) if kbps.len() != self.layers as usize => {
+                invalid("temporal layer bitrates must be given for every layer")
+            },
+            Some(ref kbps) if kbps.windows(2).any(|pair| pair[0] > pair[1]) => {
+                invalid("temporal layer bitrates are cumulative, so mustn't decrease")
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// VP9's `temporal_layering_mode`, which VP8's config doesn't have.
+    fn mode(&self) -> libc::c_int {
+        let (mode, pattern, _) = temporal_pattern(self.layers);
+        if self.bypass && pattern.len() > 1 {
+            ffi::VP9E_TEMPORAL_LAYERING_MODE_BYPASS as libc::c_int
+        } else {
+            mode as libc::c_int
+        }
+    }
+
+    fn apply(&self, cfg: &mut ffi::vpx_codec_enc_cfg_t) {
+        let (_, pattern, shares) = temporal_pattern(self.layers);
+        let layers = shares.len();
+        cfg.ts_number_layers = layers as libc::c_uint;
+        cfg.ts_periodicity = pattern.len() as libc::c_uint;
+        for (i, &id) in pattern.iter().enumerate() {
+            cfg.ts_layer_id[i] = id as libc::c_uint;
+        }
+
+        let kbps = match self.bitrates {
+            Some(ref kbps) if kbps.len() == layers => Some(kbps),
+            _ => None,
+        };
+        let total = kbps.map_or(cfg.rc_target_bitrate, |kbps| kbps[layers - 1]);
+        for t in 0..layers {
+            cfg.ts_rate_decimator[t] = 1 << (layers - 1 - t);
+            let cumulative = kbps.map_or(total * shares[t] / 100, |kbps| kbps[t]);
+            cfg.ts_target_bitrate[t] = cumulative;
+            cfg.layer_target_bitrate[t] = cumulative;
+        }
+        cfg.rc_target_bitrate = total;
+    }
+}
+
+/// Where a context is in its `TemporalLayering` pattern, for the frames
+/// whose references it chooses.
+#[derive(Clone, Debug)]
+struct TemporalState {
+    pattern: &'static [u8],
+    index: usize,
+    /// Whether the pattern restarts at forced keyframes.
+    bypass: bool,
+    /// The pts and layer of the latest frames, since with
+    /// `g_lag_in_frames` the packets come out later.
+    layers: VecDeque<(ffi::vpx_codec_pts_t, u8)>,
+}
+impl TemporalState {
+    fn new(layering: &TemporalLayering) -> TemporalState {
+        TemporalState {
+            pattern: layering.pattern(),
+            index: 0,
+            bypass: layering.bypass,
+            layers: VecDeque::new(),
+        }
+    }
+
+    /// The layer of the next frame, given with `pts`, and `flags` with its
+    /// references added.
+    fn next(&mut self, pts: ffi::vpx_codec_pts_t, flags: FrameFlags) -> (u8, FrameFlags) {
+        if flags.keyframe && self.bypass {
+            self.index = 0;
+        }
+        let layers = *self.pattern.iter().max().unwrap() + 1;
+        let index = self.index % self.pattern.len();
+        let layer = self.pattern[index];
+        self.index = index + 1;
+        if self.layers.len() == LAYER_HISTORY {
+            self.layers.pop_front();
+        }
+        self.layers.push_back((pts, layer));
+        if flags.keyframe {
+            return (layer, flags);
+        }
+
+        // Only the base layer updates `last`, and only the middle layer of
+        // 0212 updates `golden`, for the top layer frame after it.
+        let flags = flags.no_ref_altref(true).no_update_altref(true);
+        let flags = match (layer, layers) {
+            (0, _) => flags.no_ref_golden(true).no_update_golden(true),
+            (1, 3) => flags.no_ref_golden(true).no_update_last(true),
+            (2, 3) if index == 3 => flags.no_update_last(true).no_update_golden(true),
+            _ => flags.no_ref_golden(true).no_update_last(true).no_update_golden(true),
+        };
+        // Dropping an upper layer frame mustn't change the probabilities of
+        // the frames after it.
+        let flags = if layer > 0 { flags.no_update_entropy(true) } else { flags };
+        (layer, flags)
+    }
+
+    /// The layer of the frame given with `pts`.
+    fn layer(&self, pts: ffi::vpx_codec_pts_t) -> Option<u8> {
+        self.layers.iter().rev().find(|&&(p, _)| p == pts).map(|&(_, layer)| layer)
+    }
+}
+
+/// Typed setters for the codecs' `Cfg`s. `validate` is run before the
+/// config is handed to libvpx.
+pub trait CfgBuilder: AsRef<ffi::vpx_codec_enc_cfg_t> +
+    AsMut<ffi::vpx_codec_enc_cfg_t> + Sized
+{
+    fn threads(mut self, threads: u32) -> Self {
+        self.as_mut().g_threads = threads;
+        self
+    }
+    fn profile(mut self, profile: u32) -> Self {
+        self.as_mut().g_profile = profile;
+        self
+    }
+    fn size(mut self, width: u32, height: u32) -> Self {
+        {
+            let cfg = self.as_mut();
+            cfg.g_w = width;
+            cfg.g_h = height;
+        }
+        self
+    }
+    fn bit_depth(mut self, depth: BitDepth) -> Self {
+        self.as_mut().g_bit_depth = depth.into();
+        self
+    }
+    /// The bit depth of the images which will be passed to `encode`.
+    fn input_bit_depth(mut self, depth: BitDepth) -> Self {
+        self.as_mut().g_input_bit_depth = depth.bits();
+        self
+    }
+    /// The units of `pts` and `duration`, in seconds.
+    fn timebase(mut self, num: i32, den: i32) -> Self {
+        self.as_mut().g_timebase = ffi::vpx_rational_t {
+            num: num,
+            den: den,
+        };
+        self
+    }
+    fn error_resilient(mut self, flags: ErrorResilient) -> Self {
+        self.as_mut().g_error_resilient = flags.into();
+        self
+    }
+    fn pass(mut self, pass: Pass) -> Self {
+        self.as_mut().g_pass = pass.into();
+        self
+    }
+    /// How many frames the encoder may hold on to before it has to output
+    /// one. Required for altref frames.
+    fn lag_in_frames(mut self, frames: u32) -> Self {
+        self.as_mut().g_lag_in_frames = frames;
+        self
+    }
+    /// Drop frames when the buffer falls below this percentage full. 0
+    /// disables dropping.
+    fn dropframe_thresh(mut self, pct: u32) -> Self {
+        self.as_mut().rc_dropframe_thresh = pct;
+        self
+    }
+    fn rate_control(mut self, mode: RateControl) -> Self {
+        self.as_mut().rc_end_usage = mode.into();
+        self
+    }
+    /// In kilobits per second.
+    fn target_bitrate(mut self, kbps: u32) -> Self {
+        self.as_mut().rc_target_bitrate = kbps;
+        self
+    }
+    /// Both are from 0 to 63.
+    fn quantizers(mut self, min: u32, max: u32) -> Self {
+        {
+            let cfg = self.as_mut();
+            cfg.rc_min_quantizer = min;
+            cfg.rc_max_quantizer = max;
+        }
+        self
+    }
+    fn undershoot_pct(mut self, pct: u32) -> Self {
+        self.as_mut().rc_undershoot_pct = pct;
+        self
+    }
+    fn overshoot_pct(mut self, pct: u32) -> Self {
+        self.as_mut().rc_overshoot_pct = pct;
+        self
+    }
+    /// The decoder buffer model, in milliseconds.
+    fn buffer(mut self, size: u32, initial: u32, optimal: u32) -> Self {
+        {
+            let cfg = self.as_mut();
+            cfg.rc_buf_sz = size;
+            cfg.rc_buf_initial_sz = initial;
+            cfg.rc_buf_optimal_sz = optimal;
+        }
+        self
+    }
+    fn keyframe_mode(mut self, mode: KeyframeMode) -> Self {
+        {
+            let cfg = self.as_mut();
+            match mode {
+                KeyframeMode::Auto { min_dist, max_dist, } => {
+                    cfg.kf_mode = ffi::VPX_KF_AUTO;
+                    cfg.kf_min_dist = min_dist;
+                    cfg.kf_max_dist = max_dist;
+                },
+                KeyframeMode::Disabled => {
+                    cfg.kf_mode = ffi::VPX_KF_DISABLED;
+                },
+            }
+        }
+        self
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        validate_cfg(self.as_ref())
+    }
+}
+
+fn validate_cfg(cfg: &ffi::vpx_codec_enc_cfg_t) -> Result<(), Error> {
+    fn invalid(detail: &str) -> Result<(), Error> {
+        Err(Error::new(ErrorKind::InvalidParam, detail.to_string()))
+    }
+
+    if cfg.g_w == 0 || cfg.g_h == 0 {
+        return invalid("g_w and g_h must be non-zero");
+    }
+    if cfg.g_timebase.num <= 0 || cfg.g_timebase.den <= 0 {
+        return invalid("g_timebase must be positive");
+    }
+    if cfg.rc_max_quantizer > 63 {
+        return invalid("rc_max_quantizer out of range [0..63]");
+    }
+    if cfg.rc_min_quantizer > cfg.rc_max_quantizer {
+        return invalid("rc_min_quantizer must be <= rc_max_quantizer");
+    }
+    if cfg.kf_mode == ffi::VPX_KF_AUTO && cfg.kf_min_dist > cfg.kf_max_dist {
+        return invalid("kf_min_dist must be <= kf_max_dist");
+    }
+    if cfg.g_input_bit_depth > 8 && cfg.g_bit_depth == ffi::VPX_BITS_8 {
+        return invalid("g_input_bit_depth > 8 requires g_bit_depth > 8");
+    }
+    Ok(())
 }
 
 pub trait Encoder: InternalEncoder
-    where <Self as Encoder>::Cfg: AsRef<ffi::vpx_codec_enc_cfg_t>,
+    where <Self as Encoder>::Cfg: CfgBuilder,
 {
     type Cfg;
     fn set_cfg(&mut self, cfg: Self::Cfg) -> Result<(), Error> {
+        try!(cfg.validate());
+        let mut cfg = *cfg.as_ref();
+        if cfg.g_pass == ffi::VPX_RC_LAST_PASS && cfg.rc_twopass_stats_in.buf.is_null() {
+            // Keep using the stats this context was created with.
+            if let Some(stats) = self.get_two_pass_stats() {
+                stats.apply(&mut cfg);
+            }
+        }
         let res = unsafe {
             ffi::vpx_codec_enc_config_set(self.get_mut_ctx(),
-                                          cfg.as_ref() as *const _)
+                                          &cfg as *const _)
         };
         if res == ffi::VPX_CODEC_OK {
             Ok(())
         } else {
-            Err(From::from(res))
+            Err(unsafe { Error::from_ctx(self.get_mut_ctx(), res) })
         }
     }
 
@@ -59,6 +569,18 @@ pub trait Encoder: InternalEncoder
               duration: u64,
               flags: FrameFlags,
               deadline: u64) -> Result<(), Error> {
+        let high_bit_depth = unsafe {
+            (*self.get_ref_ctx()).init_flags & ffi::VPX_CODEC_USE_HIGHBITDEPTH != 0
+        };
+        if image.get_format().is_high_bit_depth() != high_bit_depth {
+            let detail = if high_bit_depth {
+                "this encoder only takes high bit depth images"
+            } else {
+                "high bit depth images need an encoder with g_bit_depth > 8"
+            };
+            return Err(Error::new(ErrorKind::InvalidParam, detail.to_string()));
+        }
+        let flags = try!(self.frame_flags(pts, flags));
         let res = unsafe {
             ffi::vpx_codec_encode(self.get_mut_ctx(),
                                   &image.0 as *const _,
@@ -68,7 +590,7 @@ pub trait Encoder: InternalEncoder
                                   deadline as libc::c_ulong)
         };
         if res != ffi::VPX_CODEC_OK {
-            Err(From::from(res))
+            Err(unsafe { Error::from_ctx(self.get_mut_ctx(), res) })
         } else {
             Ok(())
         }
@@ -78,19 +600,75 @@ pub trait Encoder: InternalEncoder
     fn flush(&mut self,
              pts: ffi::vpx_codec_pts_t,
              duration: u64,
-             flags: ffi::vpx_enc_frame_flags_t,
+             flags: FrameFlags,
              deadline: u64) -> Result<(), Error>
     {
         let res = unsafe {
             ffi::vpx_codec_encode(self.get_mut_ctx(),
                                   0 as *const _,
                                   pts, duration as libc::c_ulong,
-                                  flags, deadline as libc::c_ulong)
+                                  flags.into(), deadline as libc::c_ulong)
         };
         if res == ffi::VPX_CODEC_OK {
             Ok(())
         } else {
-            Err(From::from(res))
+            Err(unsafe { Error::from_ctx(self.get_mut_ctx(), res) })
+        }
+    }
+
+    /// Sharpness of the loop filter, 0 to 7.
+    fn set_sharpness(&mut self, sharpness: u32) -> Result<(), Error> {
+        try!(check_range("sharpness", sharpness, 0, 7));
+        control_uint(self, ffi::VP8E_SET_SHARPNESS, sharpness)
+    }
+    /// Skip encoding macroblocks whose difference from the previous frame
+    /// is below this threshold.
+    fn set_static_threshold(&mut self, threshold: u32) -> Result<(), Error> {
+        control_uint(self, ffi::VP8E_SET_STATIC_THRESHOLD, threshold)
+    }
+    fn set_tuning(&mut self, tuning: Tuning) -> Result<(), Error> {
+        control_int(self, ffi::VP8E_SET_TUNING, tuning.into())
+    }
+    /// The quality level to target in `VPX_CQ` rate control mode, 0 to 63.
+    fn set_cq_level(&mut self, level: u32) -> Result<(), Error> {
+        try!(check_range("cq_level", level, 0, 63));
+        control_uint(self, ffi::VP8E_SET_CQ_LEVEL, level)
+    }
+    /// Caps the size of keyframes, as a percentage of the average per-frame
+    /// bitrate. 0 means unlimited.
+    fn set_max_intra_bitrate_pct(&mut self, pct: u32) -> Result<(), Error> {
+        control_uint(self, ffi::VP8E_SET_MAX_INTRA_BITRATE_PCT, pct)
+    }
+    fn set_auto_alt_ref(&mut self, enable: bool) -> Result<(), Error> {
+        control_uint(self, ffi::VP8E_SET_ENABLEAUTOALTREF, enable as u32)
+    }
+    /// The number of frames used to build the altref frame, 0 to 15.
+    fn set_arnr_max_frames(&mut self, frames: u32) -> Result<(), Error> {
+        try!(check_range("arnr_max_frames", frames, 0, 15));
+        control_uint(self, ffi::VP8E_SET_ARNR_MAXFRAMES, frames)
+    }
+    /// The strength of the altref temporal filter, 0 to 6.
+    fn set_arnr_strength(&mut self, strength: u32) -> Result<(), Error> {
+        try!(check_range("arnr_strength", strength, 0, 6));
+        control_uint(self, ffi::VP8E_SET_ARNR_STRENGTH, strength)
+    }
+
+    /// The quantizer of the last encoded frame, in the codec's internal
+    /// scale.
+    fn last_quantizer(&mut self) -> Result<i32, Error> {
+        control_get_int(self, ffi::VP8E_GET_LAST_QUANTIZER)
+    }
+    /// The quantizer of the last encoded frame, on the 0 to 63 scale used by
+    /// `rc_min_quantizer`/`rc_max_quantizer`.
+    fn last_quantizer_64(&mut self) -> Result<i32, Error> {
+        control_get_int(self, ffi::VP8E_GET_LAST_QUANTIZER_64)
+    }
+
+    /// Like `packets`, but pulls owned copies of the packets one at a time.
+    fn owned_packets<'a>(&'a mut self) -> Packets<'a, Self> where Self: Sized {
+        Packets {
+            ctx: self,
+            iter: 0 as *mut _,
         }
     }
 
@@ -108,7 +686,10 @@ pub trait Encoder: InternalEncoder
                 match pkt.kind {
                     ffi::VPX_CODEC_CX_FRAME_PKT => {
                         let frame: &ffi::Struct_Unnamed6 = transmute(pkt.data.frame_ref());
-                        let frame: Frame = From::from(frame);
+                        let mut frame: Frame = From::from(frame);
+                        let (spatial_layer, temporal_layer) = self.layer_ids(frame.pts as i64);
+                        frame.spatial_layer = spatial_layer;
+                        frame.temporal_layer = temporal_layer;
                         try!(dest.write_frame(&frame));
                     },
                     ffi::VPX_CODEC_STATS_PKT => {
@@ -117,9 +698,9 @@ pub trait Encoder: InternalEncoder
                         try!(dest.write_two_pass_stats(buf));
                     },
                     ffi::VPX_CODEC_FPMB_STATS_PKT => {
-                        let buf: &ffi::vpx_fixed_buf_t = transmute(pkt.data.twopass_stats_ref());
+                        let buf: &ffi::vpx_fixed_buf_t = transmute(pkt.data.firstpass_mb_stats_ref());
                         let buf = from_raw_parts(buf.buf as *const u8, buf.sz as usize);
-                        try!(dest.write_two_pass_stats(buf));
+                        try!(dest.write_first_pass_mb_stats(buf));
                     },
                     ffi::VPX_CODEC_PSNR_PKT => {
                         let psnr: &ffi::Struct_vpx_psnr_pkt = transmute(pkt.data.psnr_ref());
@@ -138,6 +719,205 @@ pub trait Encoder: InternalEncoder
 pub trait InternalEncoder {
     fn get_ref_ctx(&self) -> *const ffi::vpx_codec_ctx_t;
     fn get_mut_ctx(&mut self) -> *mut ffi::vpx_codec_ctx_t;
+
+    /// The context has to keep the first pass stats alive for as long as
+    /// libvpx may read them.
+    fn get_two_pass_stats(&self) -> Option<&two_pass::Stats>;
+    fn set_two_pass_stats(&mut self, stats: Option<two_pass::Stats>);
+
+    /// The spatial and temporal layers of the frame packet with `pts`, just
+    /// taken from the encoder, for encoders which use them.
+    fn layer_ids(&mut self, _pts: ffi::vpx_codec_pts_t) -> (Option<u8>, Option<u8>) {
+        (None, None)
+    }
+    /// Adjust the flags of the next frame, given with `pts`, eg to follow a
+    /// temporal layering pattern.
+    fn frame_flags(&mut self, _pts: ffi::vpx_codec_pts_t,
+                   flags: FrameFlags) -> Result<FrameFlags, Error> {
+        Ok(flags)
+    }
+}
+
+fn check_range<T>(name: &str, v: T, min: T, max: T) -> Result<(), Error>
+    where T: PartialOrd + Display,
+{
+    if v < min || v > max {
+        Err(Error::new(ErrorKind::InvalidParam,
+                       format!("{} out of range [{}..{}]", name, min, max)))
+    } else {
+        Ok(())
+    }
+}
+
+fn control_result<E>(enc: &mut E, res: ffi::vpx_codec_err_t) -> Result<(), Error>
+    where E: InternalEncoder + ?Sized,
+{
+    if res == ffi::VPX_CODEC_OK {
+        Ok(())
+    } else {
+        Err(unsafe { Error::from_ctx(enc.get_mut_ctx(), res) })
+    }
+}
+fn control_int<E>(enc: &mut E, id: ffi::Enum_vp8e_enc_control_id,
+                  v: i32) -> Result<(), Error>
+    where E: InternalEncoder + ?Sized,
+{
+    let res = unsafe {
+        ffi::vpx_codec_control_(enc.get_mut_ctx(), id as libc::c_int,
+                                v as libc::c_int)
+    };
+    control_result(enc, res)
+}
+fn control_uint<E>(enc: &mut E, id: ffi::Enum_vp8e_enc_control_id,
+                   v: u32) -> Result<(), Error>
+    where E: InternalEncoder + ?Sized,
+{
+    let res = unsafe {
+        ffi::vpx_codec_control_(enc.get_mut_ctx(), id as libc::c_int,
+                                v as libc::c_uint)
+    };
+    control_result(enc, res)
+}
+fn control_get_int<E>(enc: &mut E, id: ffi::Enum_vp8e_enc_control_id) -> Result<i32, Error>
+    where E: InternalEncoder + ?Sized,
+{
+    let mut out: libc::c_int = 0;
+    let res = unsafe {
+        ffi::vpx_codec_control_(enc.get_mut_ctx(), id as libc::c_int,
+                                &mut out as *mut libc::c_int)
+    };
+    control_result(enc, res).map(|()| out as i32)
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Tuning {
+    Psnr,
+    Ssim,
+}
+impl Into<i32> for Tuning {
+    fn into(self) -> i32 {
+        match self {
+            Tuning::Psnr => ffi::VP8_TUNE_PSNR as i32,
+            Tuning::Ssim => ffi::VP8_TUNE_SSIM as i32,
+        }
+    }
+}
+
+unsafe fn buf_to_vec(buf: *const ffi::vpx_fixed_buf_t) -> Vec<u8> {
+    use std::slice::from_raw_parts;
+    let buf = &*buf;
+    if buf.buf.is_null() {
+        Vec::new()
+    } else {
+        from_raw_parts(buf.buf as *const u8, buf.sz as usize).to_vec()
+    }
+}
+
+/// An owned encoder output packet.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Packet {
+    Frame(OwnedFrame),
+    TwoPassStats(Vec<u8>),
+    FirstPassMbStats(Vec<u8>),
+    Psnr {
+        samples: [u32; 4],
+        sse: [u64; 4],
+        psnr: [f64; 4],
+    },
+    Custom {
+        kind: ffi::Enum_vpx_codec_cx_pkt_kind,
+        data: Vec<u8>,
+    },
+}
+impl Packet {
+    unsafe fn from_raw(pkt: &ffi::vpx_codec_cx_pkt_t) -> Packet {
+        match pkt.kind {
+            ffi::VPX_CODEC_CX_FRAME_PKT => {
+                let frame: Frame = From::from(&*pkt.data.frame_ref());
+                Packet::Frame(From::from(&frame))
+            },
+            ffi::VPX_CODEC_STATS_PKT => {
+                Packet::TwoPassStats(buf_to_vec(pkt.data.twopass_stats_ref()))
+            },
+            ffi::VPX_CODEC_FPMB_STATS_PKT => {
+                Packet::FirstPassMbStats(buf_to_vec(pkt.data.firstpass_mb_stats_ref()))
+            },
+            ffi::VPX_CODEC_PSNR_PKT => {
+                let psnr = &*pkt.data.psnr_ref();
+                Packet::Psnr {
+                    samples: psnr.samples,
+                    sse: psnr.sse,
+                    psnr: psnr.psnr,
+                }
+            },
+            kind => {
+                Packet::Custom {
+                    kind: kind,
+                    data: buf_to_vec(pkt.data.raw_ref()),
+                }
+            },
+        }
+    }
+}
+
+/// Collects packets, for when `Encoder::packets` is more convenient than
+/// `Encoder::owned_packets`.
+impl PacketWriter for Vec<Packet> {
+    fn write_frame<'a>(&mut self, frame: &Frame<'a>) -> Result<(), ::std::io::Error> {
+        self.push(Packet::Frame(From::from(frame)));
+        Ok(())
+    }
+    fn write_two_pass_stats(&mut self, stats: &[u8]) -> Result<(), ::std::io::Error> {
+        self.push(Packet::TwoPassStats(stats.to_vec()));
+        Ok(())
+    }
+    fn write_first_pass_mb_stats(&mut self, stats: &[u8]) -> Result<(), ::std::io::Error> {
+        self.push(Packet::FirstPassMbStats(stats.to_vec()));
+        Ok(())
+    }
+    fn write_psnr(&mut self, samples: &[u32; 4], sse: &[u64; 4],
+                  psnr: &[f64; 4]) -> Result<(), ::std::io::Error> {
+        self.push(Packet::Psnr {
+            samples: *samples,
+            sse: *sse,
+            psnr: *psnr,
+        });
+        Ok(())
+    }
+    fn write_custom(&mut self,
+                    kind: ffi::Enum_vpx_codec_cx_pkt_kind,
+                    data: &ffi::Union_Unnamed5) -> Result<(), ::std::io::Error> {
+        self.push(Packet::Custom {
+            kind: kind,
+            data: unsafe { buf_to_vec(data.raw_ref()) },
+        });
+        Ok(())
+    }
+}
+
+pub struct Packets<'a, T: InternalEncoder + 'a> {
+    ctx: &'a mut T,
+    iter: ffi::vpx_codec_iter_t,
+}
+impl<'a, T: InternalEncoder + 'a> Iterator for Packets<'a, T> {
+    type Item = Packet;
+    fn next(&mut self) -> Option<Packet> {
+        unsafe {
+            let pkt = ffi::vpx_codec_get_cx_data(self.ctx.get_mut_ctx(),
+                                                 &mut self.iter as *mut _);
+            if pkt.is_null() {
+                None
+            } else {
+                let mut pkt = Packet::from_raw(&*pkt);
+                if let Packet::Frame(ref mut frame) = pkt {
+                    let (spatial_layer, temporal_layer) = self.ctx.layer_ids(frame.pts as i64);
+                    frame.spatial_layer = spatial_layer;
+                    frame.temporal_layer = temporal_layer;
+                }
+                Some(pkt)
+            }
+        }
+    }
 }
 
 pub trait PacketWriter {
@@ -150,3 +930,127 @@ pub trait PacketWriter {
                     _kind: ffi::Enum_vpx_codec_cx_pkt_kind,
                     _data: &ffi::Union_Unnamed5) -> Result<(), ::std::io::Error> { Ok(()) }
 }
+
+#[cfg(test)]
+mod tests {
+    use ffi;
+    use super::{FrameFlags, Packet, PacketWriter, TemporalLayering, TemporalState};
+
+    const KEY: ffi::vpx_enc_frame_flags_t = ffi::VPX_EFLAG_FORCE_KF;
+    /// Base layer frames only reference and update `last`.
+    const BASE: ffi::vpx_enc_frame_flags_t = ffi::VP8_EFLAG_NO_REF_GF | ffi::VP8_EFLAG_NO_REF_ARF |
+        ffi::VP8_EFLAG_NO_UPD_GF | ffi::VP8_EFLAG_NO_UPD_ARF;
+    /// Frames nothing references.
+    const TOP: ffi::vpx_enc_frame_flags_t = ffi::VP8_EFLAG_NO_REF_GF | ffi::VP8_EFLAG_NO_REF_ARF |
+        ffi::VP8_EFLAG_NO_UPD_LAST | ffi::VP8_EFLAG_NO_UPD_GF | ffi::VP8_EFLAG_NO_UPD_ARF |
+        ffi::VP8_EFLAG_NO_UPD_ENTROPY;
+    /// The middle layer of 0212, which updates `golden`.
+    const MIDDLE: ffi::vpx_enc_frame_flags_t = ffi::VP8_EFLAG_NO_REF_GF | ffi::VP8_EFLAG_NO_REF_ARF |
+        ffi::VP8_EFLAG_NO_UPD_LAST | ffi::VP8_EFLAG_NO_UPD_ARF | ffi::VP8_EFLAG_NO_UPD_ENTROPY;
+    /// The top layer frame of 0212 after the middle one, which references it.
+    const AFTER_MIDDLE: ffi::vpx_enc_frame_flags_t = ffi::VP8_EFLAG_NO_REF_ARF |
+        ffi::VP8_EFLAG_NO_UPD_LAST | ffi::VP8_EFLAG_NO_UPD_GF | ffi::VP8_EFLAG_NO_UPD_ARF |
+        ffi::VP8_EFLAG_NO_UPD_ENTROPY;
+
+    /// The layer and flags of each frame, forcing a keyframe where `keyframes`
+    /// is set.
+    fn run(layering: TemporalLayering, keyframes: &[bool])
+           -> Vec<(u8, ffi::vpx_enc_frame_flags_t)> {
+        let mut state = TemporalState::new(&layering);
+        keyframes.iter().enumerate().map(|(pts, &keyframe)| {
+            let (layer, flags) = state.next(pts as i64, FrameFlags::new().keyframe(keyframe));
+            assert_eq!(state.layer(pts as i64), Some(layer));
+            (layer, flags.into())
+        }).collect()
+    }
+
+    #[test]
+    fn layer_bitrates() {
+        assert!(TemporalLayering::new(3).bitrates(&[100, 150, 250]).validate().is_ok());
+        assert!(TemporalLayering::new(3).bitrates(&[100, 100, 250]).validate().is_ok());
+        assert!(TemporalLayering::new(3).bitrates(&[100, 50, 250]).validate().is_err());
+        assert!(TemporalLayering::new(3).bitrates(&[100, 150]).validate().is_err());
+        assert!(TemporalLayering::new(2).bitrates(&[100, 150, 250]).validate().is_err());
+
+        let mut cfg: ffi::vpx_codec_enc_cfg_t = Default::default();
+        TemporalLayering::new(3).bitrates(&[100, 150, 250]).apply(&mut cfg);
+        assert_eq!(&cfg.ts_target_bitrate[..3], &[100, 150, 250]);
+        assert_eq!(&cfg.layer_target_bitrate[..3], &[100, 150, 250]);
+        assert_eq!(cfg.rc_target_bitrate, 250);
+    }
+
+    #[test]
+    fn lagged_layers() {
+        // With lag, the packets of earlier frames come out after later
+        // frames were given, so each is looked up by its own pts.
+        let mut state = TemporalState::new(&TemporalLayering::new(3));
+        for pts in 0..10 {
+            state.next(pts * 2, FrameFlags::new());
+        }
+        let layers: Vec<_> = (0..10).map(|pts| state.layer(pts * 2).unwrap()).collect();
+        assert_eq!(layers, [0, 2, 1, 2, 0, 2, 1, 2, 0, 2]);
+        assert_eq!(state.layer(1), None);
+
+        for pts in 10..100 {
+            state.next(pts * 2, FrameFlags::new());
+        }
+        assert_eq!(state.layer(0), None);
+        assert_eq!(state.layer(99 * 2), Some(2));
+        assert_eq!(state.layers.len(), super::LAYER_HISTORY);
+    }
+
+    #[test]
+    fn two_layers() {
+        let frames = run(TemporalLayering::new(2), &[true, false, false, false, false]);
+        assert_eq!(frames, [(0, KEY), (1, TOP), (0, BASE), (1, TOP), (0, BASE)]);
+    }
+
+    #[test]
+    fn three_layers() {
+        let frames = run(TemporalLayering::new(3), &[true, false, false, false, false,
+                                                     false, false, false, false]);
+        assert_eq!(frames, [(0, KEY), (2, TOP), (1, MIDDLE), (2, AFTER_MIDDLE),
+                            (0, BASE), (2, TOP), (1, MIDDLE), (2, AFTER_MIDDLE),
+                            (0, BASE)]);
+    }
+
+    #[test]
+    fn forced_keyframe() {
+        let keyframes = [true, false, false, true, false, false, false];
+        // Bypass starts the pattern again at the keyframe.
+        let frames = run(TemporalLayering::new(3).bypass(true), &keyframes);
+        assert_eq!(frames, [(0, KEY), (2, TOP), (1, MIDDLE),
+                            (0, KEY), (2, TOP), (1, MIDDLE), (2, AFTER_MIDDLE)]);
+        let frames = run(TemporalLayering::new(2).bypass(true), &[true, false, true, false]);
+        assert_eq!(frames, [(0, KEY), (1, TOP), (0, KEY), (1, TOP)]);
+
+        // Otherwise it carries on, as libvpx's own modes do.
+        let frames = run(TemporalLayering::new(3), &keyframes);
+        assert_eq!(frames, [(0, KEY), (2, TOP), (1, MIDDLE),
+                            (2, KEY), (0, BASE), (2, TOP), (1, MIDDLE)]);
+    }
+
+    #[test]
+    fn custom_packets() {
+        let mut data = [1u8, 2, 3];
+        let mut pkt = ffi::vpx_codec_cx_pkt_t {
+            kind: ffi::VPX_CODEC_CUSTOM_PKT,
+            data: Default::default(),
+        };
+        unsafe {
+            *pkt.data.raw_mut() = ffi::vpx_fixed_buf_t {
+                buf: data.as_mut_ptr() as *mut _,
+                sz: data.len() as ffi::size_t,
+            };
+        }
+
+        // Collected into a `Vec` as `owned_packets` would return them.
+        let mut packets: Vec<Packet> = Vec::new();
+        packets.write_custom(pkt.kind, &pkt.data).unwrap();
+        assert_eq!(packets, [unsafe { Packet::from_raw(&pkt) }]);
+        assert_eq!(packets[0], Packet::Custom {
+            kind: ffi::VPX_CODEC_CUSTOM_PKT,
+            data: vec![1, 2, 3],
+        });
+    }
+}
@@ -0,0 +1,163 @@
+//! Two-pass encoding. The first pass analyses the whole source and only
+//! produces stats; the second pass uses those to spread the bitrate over the
+//! stream, which is what offline encodes want for VBR.
+
+use ffi;
+use {Error, ErrorKind, Image, Interface};
+use super::{CfgBuilder, Encoder, FrameFlags, InternalEncoder, Packet, PacketWriter, Pass};
+
+use libc;
+
+/// The stats produced by a first pass.
+#[derive(Clone, Default, Debug, Eq, PartialEq)]
+pub struct Stats {
+    two_pass: Vec<u8>,
+    first_pass_mb: Vec<u8>,
+}
+impl Stats {
+    pub fn new() -> Stats { Default::default() }
+    /// Reconstitute stats saved from an earlier first pass.
+    pub fn from_parts(two_pass: Vec<u8>, first_pass_mb: Vec<u8>) -> Stats {
+        Stats {
+            two_pass: two_pass,
+            first_pass_mb: first_pass_mb,
+        }
+    }
+
+    pub fn two_pass(&self) -> &[u8] { &self.two_pass[..] }
+    /// Only produced if libvpx was built with `--enable-fp-mb-stats`.
+    pub fn first_pass_mb(&self) -> &[u8] { &self.first_pass_mb[..] }
+    pub fn is_empty(&self) -> bool { self.two_pass.is_empty() }
+
+    /// Point `cfg` at these stats. `self` must outlive any encoder created
+    /// with `cfg`.
+    #[doc(hidden)]
+    pub fn apply(&self, cfg: &mut ffi::vpx_codec_enc_cfg_t) {
+        cfg.rc_twopass_stats_in = ffi::vpx_fixed_buf_t {
+            buf: self.two_pass.as_ptr() as *mut _,
+            sz: self.two_pass.len() as libc::size_t,
+        };
+        if !self.first_pass_mb.is_empty() {
+            cfg.rc_firstpass_mb_stats_in = ffi::vpx_fixed_buf_t {
+                buf: self.first_pass_mb.as_ptr() as *mut _,
+                sz: self.first_pass_mb.len() as libc::size_t,
+            };
+        }
+    }
+}
+impl PacketWriter for Stats {
+    fn write_two_pass_stats(&mut self, stats: &[u8]) -> Result<(), ::std::io::Error> {
+        self.two_pass.extend_from_slice(stats);
+        Ok(())
+    }
+    fn write_first_pass_mb_stats(&mut self, stats: &[u8]) -> Result<(), ::std::io::Error> {
+        self.first_pass_mb.extend_from_slice(stats);
+        Ok(())
+    }
+}
+
+/// A frame to encode, along with the arguments to `Encoder::encode`.
+pub struct SourceFrame<'a> {
+    pub image: Image<'a>,
+    pub pts: ffi::vpx_codec_pts_t,
+    pub duration: u64,
+    pub flags: FrameFlags,
+}
+
+/// An encoder running the first pass.
+pub struct FirstPass<C> {
+    ctx: C,
+    stats: Stats,
+    deadline: u64,
+}
+impl<C> FirstPass<C>
+    where C: Encoder,
+          <C as Encoder>::Cfg: CfgBuilder,
+{
+    pub fn new<I>(iface: &I, cfg: I::Cfg, flags: ffi::vpx_codec_flags_t,
+                  deadline: u64) -> Result<FirstPass<C>, Error>
+        where I: Interface<Context = C, Cfg = <C as Encoder>::Cfg>,
+    {
+        let ctx = try!(iface.create(cfg.pass(Pass::First), flags));
+        Ok(FirstPass {
+            ctx: ctx,
+            stats: Stats::new(),
+            deadline: deadline,
+        })
+    }
+
+    pub fn encode(&mut self, image: &Image,
+                  pts: ffi::vpx_codec_pts_t,
+                  duration: u64,
+                  flags: FrameFlags) -> Result<(), Error> {
+        try!(self.ctx.encode(image, pts, duration, flags, self.deadline));
+        self.collect();
+        Ok(())
+    }
+
+    /// Returns true if there were any packets.
+    fn collect(&mut self) -> bool {
+        let mut any = false;
+        for packet in self.ctx.owned_packets() {
+            any = true;
+            match packet {
+                Packet::TwoPassStats(stats) => {
+                    self.stats.two_pass.extend(stats);
+                },
+                Packet::FirstPassMbStats(stats) => {
+                    self.stats.first_pass_mb.extend(stats);
+                },
+                _ => {},
+            }
+        }
+        any
+    }
+
+    /// Flush the encoder and return the complete stats.
+    pub fn finish(mut self) -> Result<Stats, Error> {
+        loop {
+            try!(self.ctx.flush(0, 1, FrameFlags::new(), self.deadline));
+            if !self.collect() { break; }
+        }
+        Ok(self.stats)
+    }
+}
+
+/// Run the first pass over all of `source`.
+pub fn first_pass<'a, I, S>(iface: &I, cfg: I::Cfg,
+                            flags: ffi::vpx_codec_flags_t,
+                            deadline: u64,
+                            source: S) -> Result<Stats, Error>
+    where I: Interface,
+          I::Context: Encoder<Cfg = I::Cfg>,
+          I::Cfg: CfgBuilder,
+          S: IntoIterator<Item = SourceFrame<'a>>,
+{
+    let mut pass = try!(FirstPass::new(iface, cfg, flags, deadline));
+    for frame in source {
+        try!(pass.encode(&frame.image, frame.pts, frame.duration, frame.flags));
+    }
+    pass.finish()
+}
+
+/// Create the second pass encoder. It takes ownership of `stats`, keeping
+/// them alive for as long as it exists; `Encoder::set_cfg` will reuse them
+/// for any last pass config which doesn't set its own.
+pub fn second_pass<I>(iface: &I, cfg: I::Cfg,
+                      flags: ffi::vpx_codec_flags_t,
+                      stats: Stats) -> Result<I::Context, Error>
+    where I: Interface,
+          I::Context: Encoder<Cfg = I::Cfg>,
+          I::Cfg: CfgBuilder,
+{
+    if stats.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidParam,
+                              "second pass requires first pass stats".to_string()));
+    }
+
+    let mut cfg = cfg.pass(Pass::Last);
+    stats.apply(cfg.as_mut());
+    let mut ctx = try!(iface.create(cfg, flags));
+    ctx.set_two_pass_stats(Some(stats));
+    Ok(ctx)
+}
@@ -0,0 +1,218 @@
+use ffi;
+
+use {InternalInterface, Error, ErrorKind, Kind};
+use super::{check_range, control_int, control_uint, validate_cfg, CfgBuilder, FrameFlags};
+use super::{TemporalLayering, TemporalState};
+use super::two_pass::Stats;
+
+/// The config, and the temporal layering the context follows.
+#[derive(Debug)]
+pub struct Cfg(ffi::vpx_codec_enc_cfg_t, Option<TemporalLayering>);
+impl Default for Cfg {
+    fn default() -> Cfg {
+        let interface: Interface = Default::default();
+        let mut cfg: ffi::vpx_codec_enc_cfg_t = Default::default();
+        let err = unsafe {
+            ffi::vpx_codec_enc_config_default(interface.iface(),
+                                              &mut cfg as *mut _,
+                                              0)
+        };
+        assert_eq!(err, ffi::VPX_CODEC_OK);
+        Cfg(cfg, None)
+    }
+}
+impl AsRef<ffi::vpx_codec_enc_cfg_t> for Cfg {
+    fn as_ref(&self) -> &ffi::vpx_codec_enc_cfg_t {
+        &self.0
+    }
+}
+impl AsMut<ffi::vpx_codec_enc_cfg_t> for Cfg {
+    fn as_mut(&mut self) -> &mut ffi::vpx_codec_enc_cfg_t {
+        &mut self.0
+    }
+}
+impl super::CfgBuilder for Cfg {
+    fn validate(&self) -> Result<(), Error> {
+        try!(validate_cfg(&self.0));
+        check_range("g_profile", self.0.g_profile, 0, 3)
+    }
+}
+impl Cfg {
+    /// Encode in temporal layers. Without `TemporalLayering::bitrates`, this
+    /// splits `target_bitrate`, so set that first.
+    pub fn temporal_layering(mut self, layering: TemporalLayering) -> Cfg {
+        layering.apply(&mut self.0);
+        self.1 = Some(layering);
+        self
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Interface;
+impl Default for Interface {
+    fn default() -> Interface {
+        Interface
+    }
+}
+impl ::Interface for Interface {
+    type Context = Context;
+    type Cfg = Cfg;
+    fn kind(&self) -> Kind { Kind::Encoder }
+
+    fn create(&self, cfg: <Self as ::Interface>::Cfg,
+              flags: ffi::vpx_codec_flags_t) ->
+        Result<<Self as ::Interface>::Context, Error>
+    {
+        try!(cfg.validate());
+        if let Some(ref layering) = cfg.1 {
+            try!(layering.validate());
+        }
+        if cfg.0.g_bit_depth != ffi::VPX_BITS_8 ||
+            flags & ffi::VPX_CODEC_USE_HIGHBITDEPTH != 0
+        {
+            return Err(Error::new(ErrorKind::Incapable,
+                                  "VP8 only supports 8-bit encoding".to_string()));
+        }
+        let mut ctx: ffi::vpx_codec_ctx_t = Default::default();
+        let err = unsafe {
+            ffi::vpx_codec_enc_init_ver(&mut ctx as *mut _,
+                                        self.iface(),
+                                        &cfg.0 as *const _,
+                                        flags,
+                                        ffi::VPX_ENCODER_ABI_VERSION as i32)
+        };
+        if err != ffi::VPX_CODEC_OK {
+            Err(unsafe { Error::from_ctx(&mut ctx as *mut _, err) })
+        } else {
+            let state = match cfg.1 {
+                Some(ref layering) if layering.get_layers() > 1 => {
+                    Some(TemporalState::new(layering))
+                },
+                _ => None,
+            };
+            Ok(Context(ctx, None, state))
+        }
+    }
+}
+impl InternalInterface for Interface {
+    fn iface(&self) -> *mut ffi::vpx_codec_iface_t {
+        unsafe { &mut ffi::vpx_codec_vp8_cx_algo as *mut _ }
+    }
+}
+
+pub struct Context(ffi::vpx_codec_ctx_t, Option<Stats>, Option<TemporalState>);
+unsafe impl Send for Context {}
+impl Drop for Context {
+    fn drop(&mut self) {
+        unsafe { ffi::vpx_codec_destroy(&mut self.0 as *mut _); }
+    }
+}
+impl super::InternalEncoder for Context {
+    fn get_ref_ctx(&self) -> *const ffi::vpx_codec_ctx_t {
+        &self.0 as *const _
+    }
+    fn get_mut_ctx(&mut self) -> *mut ffi::vpx_codec_ctx_t {
+        &mut self.0 as *mut _
+    }
+    fn get_two_pass_stats(&self) -> Option<&Stats> {
+        self.1.as_ref()
+    }
+    fn set_two_pass_stats(&mut self, stats: Option<Stats>) {
+        self.1 = stats;
+    }
+    fn layer_ids(&mut self, pts: ffi::vpx_codec_pts_t) -> (Option<u8>, Option<u8>) {
+        (None, self.2.as_ref().and_then(|state| state.layer(pts)))
+    }
+    fn frame_flags(&mut self, pts: ffi::vpx_codec_pts_t,
+                   flags: FrameFlags) -> Result<FrameFlags, Error> {
+        let (layer, flags) = match self.2 {
+            Some(ref mut state) => state.next(pts, flags),
+            None => return Ok(flags),
+        };
+        // libvpx keeps its own place in the pattern, which drifts from ours
+        // once frames are dropped or a keyframe is forced, so always say.
+        try!(control_int(self, ffi::VP8E_SET_TEMPORAL_LAYER_ID, layer as i32));
+        Ok(flags)
+    }
+}
+impl super::Encoder for Context {
+    type Cfg = Cfg;
+}
+impl Context {
+    /// Trade quality for speed, -16 to 16. Negative values pick the absolute
+    /// value, but without adapting it to the deadline.
+    pub fn set_cpu_used(&mut self, cpu_used: i32) -> Result<(), Error> {
+        try!(check_range("cpu_used", cpu_used, -16, 16));
+        control_int(self, ffi::VP8E_SET_CPUUSED, cpu_used)
+    }
+    /// Strength of the temporal denoiser, 0 (off) to 6.
+    pub fn set_noise_sensitivity(&mut self, level: u32) -> Result<(), Error> {
+        try!(check_range("noise_sensitivity", level, 0, 6));
+        control_uint(self, ffi::VP8E_SET_NOISE_SENSITIVITY, level)
+    }
+    pub fn set_token_partitions(&mut self, partitions: TokenPartitions) -> Result<(), Error> {
+        control_int(self, ffi::VP8E_SET_TOKEN_PARTITIONS, partitions.into())
+    }
+    pub fn set_arnr_type(&mut self, ty: ArnrType) -> Result<(), Error> {
+        control_int(self, ffi::VP8E_SET_ARNR_TYPE, ty.into())
+    }
+    /// 0 is off, 1 is on, and 2 is on with more aggressive rate control.
+    pub fn set_screen_content_mode(&mut self, mode: u32) -> Result<(), Error> {
+        try!(check_range("screen_content_mode", mode, 0, 2));
+        control_uint(self, ffi::VP8E_SET_SCREEN_CONTENT_MODE, mode)
+    }
+}
+
+/// The number of partitions the DCT tokens are split into, so they can be
+/// decoded in parallel.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum TokenPartitions {
+    One,
+    Two,
+    Four,
+    Eight,
+}
+impl Into<i32> for TokenPartitions {
+    fn into(self) -> i32 {
+        let v = match self {
+            TokenPartitions::One => ffi::VP8_ONE_TOKENPARTITION,
+            TokenPartitions::Two => ffi::VP8_TWO_TOKENPARTITION,
+            TokenPartitions::Four => ffi::VP8_FOUR_TOKENPARTITION,
+            TokenPartitions::Eight => ffi::VP8_EIGHT_TOKENPARTITION,
+        };
+        v as i32
+    }
+}
+
+/// Which neighbouring frames the altref temporal filter uses.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum ArnrType {
+    Backward,
+    Forward,
+    Centered,
+}
+impl Into<i32> for ArnrType {
+    fn into(self) -> i32 {
+        match self {
+            ArnrType::Backward => 1,
+            ArnrType::Forward => 2,
+            ArnrType::Centered => 3,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use encoder::CfgBuilder;
+    use super::Cfg;
+
+    #[test]
+    fn profiles() {
+        let cfg = || Cfg(Default::default(), None).size(64, 64).timebase(1, 30);
+        // Unlike VP9's, VP8's profiles aren't tied to the bit depth.
+        for profile in 0..4 {
+            assert!(cfg().profile(profile).validate().is_ok());
+        }
+        assert!(cfg().profile(4).validate().is_err());
+    }
+}
@@ -1,11 +1,23 @@
 use ffi;
 
-use {InternalInterface, Error, Kind};
+use {InternalInterface, BitDepth, Error, ErrorKind, Format, Image, Kind, ColorSpace};
+use super::{check_range, control_int, control_result, control_uint, validate_cfg, CfgBuilder,
+            FrameFlags};
+use super::{temporal_pattern, TemporalLayering, TemporalState, MAX_TEMPORAL_LAYERS};
+use super::two_pass::Stats;
 
-use std::ops::{Deref, DerefMut};
+use libc;
 
+/// `VPX_SS_MAX_LAYERS`.
+const MAX_SPATIAL_LAYERS: usize = 5;
+/// `VPX_MAX_LAYERS`, the size of the config's arrays with an entry per
+/// spatial and temporal layer.
+const MAX_LAYERS: usize = 12;
+
+/// The config, and the SVC or temporal layers to enable once the context is
+/// created.
 #[derive(Debug)]
-pub struct Cfg(ffi::vpx_codec_enc_cfg_t);
+pub struct Cfg(ffi::vpx_codec_enc_cfg_t, Option<Svc>, Option<TemporalLayering>);
 impl Default for Cfg {
     fn default() -> Cfg {
         let interface: Interface = Default::default();
@@ -16,7 +28,7 @@ impl Default for Cfg {
                                               0)
         };
         assert_eq!(err, ffi::VPX_CODEC_OK);
-        Cfg(cfg)
+        Cfg(cfg, None, None)
     }
 }
 impl AsRef<ffi::vpx_codec_enc_cfg_t> for Cfg {
@@ -29,15 +41,54 @@ impl AsMut<ffi::vpx_codec_enc_cfg_t> for Cfg {
         &mut self.0
     }
 }
-impl Deref for Cfg {
-    type Target = ffi::vpx_codec_enc_cfg_t;
-    fn deref(&self) -> &ffi::vpx_codec_enc_cfg_t {
-        &self.0
+impl super::CfgBuilder for Cfg {
+    fn validate(&self) -> Result<(), Error> {
+        try!(validate_cfg(&self.0));
+        try!(check_range("g_profile", self.0.g_profile, 0, 3));
+        if self.0.g_bit_depth != ffi::VPX_BITS_8 && self.0.g_profile < 2 {
+            return Err(Error::new(ErrorKind::InvalidParam,
+                                  "g_bit_depth > 8 requires g_profile >= 2".to_string()));
+        }
+        Ok(())
     }
 }
-impl DerefMut for Cfg {
-    fn deref_mut(&mut self) -> &mut ffi::vpx_codec_enc_cfg_t {
-        &mut self.0
+impl Cfg {
+    /// Pick the profile and bit depths for encoding images of `fmt` with
+    /// `depth` bits per sample: profile 0 for 8-bit 4:2:0, 1 for 8-bit
+    /// 4:2:2, 4:4:0 and 4:4:4, and 2 and 3 for the same at 10 or 12 bits.
+    pub fn format(mut self, fmt: Format, depth: BitDepth) -> Cfg {
+        let subsampled = fmt.chroma_shift() == (1, 1);
+        self.0.g_profile = match (depth == BitDepth::Eight, subsampled) {
+            (true, true) => 0,
+            (true, false) => 1,
+            (false, true) => 2,
+            (false, false) => 3,
+        };
+        self.0.g_bit_depth = depth.into();
+        self.0.g_input_bit_depth = depth.bits();
+        self
+    }
+    /// Set the size, profile and bit depths to match `image`.
+    pub fn for_image(self, image: &Image) -> Cfg {
+        self.size(image.display_width(), image.display_height())
+            .format(image.get_format(), image.bit_depth())
+    }
+    /// Encode with these spatial and temporal layers. This sets the bitrate,
+    /// replacing `target_bitrate`.
+    pub fn svc(mut self, svc: Svc) -> Result<Cfg, Error> {
+        try!(svc.validate());
+        svc.apply(&mut self.0);
+        self.1 = Some(svc);
+        Ok(self)
+    }
+    /// Encode in temporal layers, without spatial layers. Without
+    /// `TemporalLayering::bitrates`, this splits `target_bitrate`, so set
+    /// that first.
+    pub fn temporal_layering(mut self, layering: TemporalLayering) -> Cfg {
+        layering.apply(&mut self.0);
+        self.0.temporal_layering_mode = layering.mode();
+        self.2 = Some(layering);
+        self
     }
 }
 
@@ -57,6 +108,19 @@ impl ::Interface for Interface {
               flags: ffi::vpx_codec_flags_t) ->
         Result<<Self as ::Interface>::Context, Error>
     {
+        try!(cfg.validate());
+        if let Some(ref layering) = cfg.2 {
+            if cfg.1.is_some() {
+                return Err(Error::new(ErrorKind::InvalidParam,
+                                      "use Svc::temporal_layers with spatial layers".to_string()));
+            }
+            try!(layering.validate());
+        }
+        let flags = if cfg.0.g_bit_depth != ffi::VPX_BITS_8 {
+            flags | ffi::VPX_CODEC_USE_HIGHBITDEPTH
+        } else {
+            flags
+        };
         let mut ctx: ffi::vpx_codec_ctx_t = Default::default();
         let err = unsafe {
             ffi::vpx_codec_enc_init_ver(&mut ctx as *mut _,
@@ -66,9 +130,23 @@ impl ::Interface for Interface {
                                         ffi::VPX_ENCODER_ABI_VERSION as i32)
         };
         if err != ffi::VPX_CODEC_OK {
-            Err(From::from(err))
+            Err(unsafe { Error::from_ctx(&mut ctx as *mut _, err) })
         } else {
-            Ok(Context(ctx))
+            let mut ctx = Context(ctx, None, false, None);
+            if let Some(ref svc) = cfg.1 {
+                try!(ctx.set_svc(svc));
+            }
+            match cfg.2 {
+                Some(ref layering) if layering.get_layers() > 1 => {
+                    try!(control_int(&mut ctx, ffi::VP9E_SET_SVC, 1));
+                    ctx.2 = true;
+                    if layering.get_bypass() {
+                        ctx.3 = Some(TemporalState::new(layering));
+                    }
+                },
+                _ => (),
+            }
+            Ok(ctx)
         }
     }
 }
@@ -78,8 +156,15 @@ impl InternalInterface for Interface {
     }
 }
 
-pub struct Context(ffi::vpx_codec_ctx_t);
+/// The third field is whether SVC is enabled, and the last is the pattern of
+/// temporal layers in bypass mode.
+pub struct Context(ffi::vpx_codec_ctx_t, Option<Stats>, bool, Option<TemporalState>);
 unsafe impl Send for Context {}
+impl Drop for Context {
+    fn drop(&mut self) {
+        unsafe { ffi::vpx_codec_destroy(&mut self.0 as *mut _); }
+    }
+}
 impl super::InternalEncoder for Context {
     fn get_ref_ctx(&self) -> *const ffi::vpx_codec_ctx_t {
         &self.0 as *const _
@@ -87,7 +172,351 @@ impl super::InternalEncoder for Context {
     fn get_mut_ctx(&mut self) -> *mut ffi::vpx_codec_ctx_t {
         &mut self.0 as *mut _
     }
+    fn get_two_pass_stats(&self) -> Option<&Stats> {
+        self.1.as_ref()
+    }
+    fn set_two_pass_stats(&mut self, stats: Option<Stats>) {
+        self.1 = stats;
+    }
+    fn layer_ids(&mut self, pts: ffi::vpx_codec_pts_t) -> (Option<u8>, Option<u8>) {
+        if !self.2 {
+            return (None, None);
+        }
+        // Read for each frame packet, as it's taken, rather than once for
+        // all of them.
+        let (spatial, temporal) = match self.svc_layer_id() {
+            Ok((spatial, temporal)) => (Some(spatial), Some(temporal)),
+            Err(_) => (None, None),
+        };
+        // When we choose the layers, we know which frame had which.
+        match self.3.as_ref().and_then(|state| state.layer(pts)) {
+            Some(layer) => (Some(0), Some(layer)),
+            None => (spatial, temporal),
+        }
+    }
+    fn frame_flags(&mut self, pts: ffi::vpx_codec_pts_t,
+                   flags: FrameFlags) -> Result<FrameFlags, Error> {
+        let (layer, flags) = match self.3 {
+            Some(ref mut state) => state.next(pts, flags),
+            None => return Ok(flags),
+        };
+        try!(self.set_svc_layer_id(0, layer));
+        Ok(flags)
+    }
 }
 impl super::Encoder for Context {
     type Cfg = Cfg;
 }
+impl Context {
+    /// Trade quality for speed, -8 to 8. Negative values pick the absolute
+    /// value, but without adapting it to the deadline.
+    pub fn set_cpu_used(&mut self, cpu_used: i32) -> Result<(), Error> {
+        try!(check_range("cpu_used", cpu_used, -8, 8));
+        control_int(self, ffi::VP8E_SET_CPUUSED, cpu_used)
+    }
+    /// Strength of the temporal denoiser, 0 (off) to 6.
+    pub fn set_noise_sensitivity(&mut self, level: u32) -> Result<(), Error> {
+        try!(check_range("noise_sensitivity", level, 0, 6));
+        control_uint(self, ffi::VP9E_SET_NOISE_SENSITIVITY, level)
+    }
+    pub fn set_lossless(&mut self, lossless: bool) -> Result<(), Error> {
+        control_uint(self, ffi::VP9E_SET_LOSSLESS, lossless as u32)
+    }
+    /// The log2 of the number of tile columns, 0 to 6. libvpx will clamp
+    /// this to what the frame width allows.
+    pub fn set_tile_columns(&mut self, log2: u32) -> Result<(), Error> {
+        try!(check_range("tile_columns", log2, 0, 6));
+        control_uint(self, ffi::VP9E_SET_TILE_COLUMNS, log2)
+    }
+    /// The log2 of the number of tile rows, 0 to 2.
+    pub fn set_tile_rows(&mut self, log2: u32) -> Result<(), Error> {
+        try!(check_range("tile_rows", log2, 0, 2));
+        control_uint(self, ffi::VP9E_SET_TILE_ROWS, log2)
+    }
+    pub fn set_frame_parallel_decoding(&mut self, enable: bool) -> Result<(), Error> {
+        control_uint(self, ffi::VP9E_SET_FRAME_PARALLEL_DECODING, enable as u32)
+    }
+    pub fn set_aq_mode(&mut self, mode: AqMode) -> Result<(), Error> {
+        control_uint(self, ffi::VP9E_SET_AQ_MODE, mode.into())
+    }
+    pub fn set_frame_periodic_boost(&mut self, enable: bool) -> Result<(), Error> {
+        control_uint(self, ffi::VP9E_SET_FRAME_PERIODIC_BOOST, enable as u32)
+    }
+    pub fn set_tune_content(&mut self, content: TuneContent) -> Result<(), Error> {
+        control_int(self, ffi::VP9E_SET_TUNE_CONTENT, content.into())
+    }
+    pub fn set_color_space(&mut self, cs: ColorSpace) -> Result<(), Error> {
+        let cs: ffi::vpx_color_space_t = cs.into();
+        control_int(self, ffi::VP9E_SET_COLOR_SPACE, cs as i32)
+    }
+    /// The minimum distance between golden frames, 0 to 24. 0 lets the
+    /// encoder choose.
+    pub fn set_min_gf_interval(&mut self, interval: u32) -> Result<(), Error> {
+        try!(check_range("min_gf_interval", interval, 0, 24));
+        control_uint(self, ffi::VP9E_SET_MIN_GF_INTERVAL, interval)
+    }
+    /// The maximum distance between golden frames, 0 to 24. 0 lets the
+    /// encoder choose.
+    pub fn set_max_gf_interval(&mut self, interval: u32) -> Result<(), Error> {
+        try!(check_range("max_gf_interval", interval, 0, 24));
+        control_uint(self, ffi::VP9E_SET_MAX_GF_INTERVAL, interval)
+    }
+    /// Caps the size of inter frames, as a percentage of the average
+    /// per-frame bitrate. 0 means unlimited.
+    pub fn set_max_inter_bitrate_pct(&mut self, pct: u32) -> Result<(), Error> {
+        control_uint(self, ffi::VP9E_SET_MAX_INTER_BITRATE_PCT, pct)
+    }
+    /// Extra bits given to golden frames in CBR mode, as a percentage of the
+    /// average per-frame bitrate.
+    pub fn set_gf_cbr_boost_pct(&mut self, pct: u32) -> Result<(), Error> {
+        control_uint(self, ffi::VP9E_SET_GF_CBR_BOOST_PCT, pct)
+    }
+
+    /// Enable SVC, with the scaling and quantizers of `svc`'s spatial
+    /// layers. `Cfg::svc` does this when the context is created; the number
+    /// of layers and their bitrates can only be changed through the config.
+    pub fn set_svc(&mut self, svc: &Svc) -> Result<(), Error> {
+        try!(svc.validate());
+        try!(control_int(self, ffi::VP9E_SET_SVC, 1));
+        let mut params = svc.parameters();
+        let res = unsafe {
+            ffi::vpx_codec_control_(&mut self.0 as *mut _,
+                                    ffi::VP9E_SET_SVC_PARAMETERS as libc::c_int,
+                                    &mut params as *mut ffi::vpx_svc_extra_cfg_t)
+        };
+        try!(control_result(self, res));
+        self.2 = true;
+        Ok(())
+    }
+    /// Choose the layers of the next frame, when the encoder isn't choosing
+    /// them itself.
+    pub fn set_svc_layer_id(&mut self, spatial: u8, temporal: u8) -> Result<(), Error> {
+        let mut id = ffi::vpx_svc_layer_id_t {
+            spatial_layer_id: spatial as libc::c_int,
+            temporal_layer_id: temporal as libc::c_int,
+        };
+        let res = unsafe {
+            ffi::vpx_codec_control_(&mut self.0 as *mut _,
+                                    ffi::VP9E_SET_SVC_LAYER_ID as libc::c_int,
+                                    &mut id as *mut ffi::vpx_svc_layer_id_t)
+        };
+        control_result(self, res)
+    }
+    /// The spatial and temporal layers of the last encoded frame. With more
+    /// than one spatial layer, it's the highest one.
+    pub fn svc_layer_id(&mut self) -> Result<(u8, u8), Error> {
+        let mut id: ffi::vpx_svc_layer_id_t = Default::default();
+        let res = unsafe {
+            ffi::vpx_codec_control_(&mut self.0 as *mut _,
+                                    ffi::VP9E_GET_SVC_LAYER_ID as libc::c_int,
+                                    &mut id as *mut ffi::vpx_svc_layer_id_t)
+        };
+        try!(control_result(self, res));
+        Ok((id.spatial_layer_id as u8, id.temporal_layer_id as u8))
+    }
+}
+
+/// One spatial layer of an SVC encode.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct SpatialLayer {
+    /// The size of the layer, as a fraction of the config's size.
+    pub scaling_num: u32,
+    pub scaling_den: u32,
+    /// Both are from 0 to 63.
+    pub min_quantizer: u32,
+    pub max_quantizer: u32,
+    /// The bitrate of this layer, in kilobits per second.
+    pub target_bitrate: u32,
+}
+impl SpatialLayer {
+    /// A layer `num / den` of the full size, with the full range of
+    /// quantizers.
+    pub fn new(scaling_num: u32, scaling_den: u32, kbps: u32) -> SpatialLayer {
+        SpatialLayer {
+            scaling_num: scaling_num,
+            scaling_den: scaling_den,
+            min_quantizer: 0,
+            max_quantizer: 63,
+            target_bitrate: kbps,
+        }
+    }
+}
+
+/// The layers of a scalable encode: spatial layers, each of which is split
+/// into the same temporal layers.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Svc {
+    spatial_layers: Vec<SpatialLayer>,
+    temporal_layers: u32,
+}
+impl Default for Svc {
+    fn default() -> Svc {
+        Svc {
+            spatial_layers: Vec::new(),
+            temporal_layers: 1,
+        }
+    }
+}
+impl Svc {
+    pub fn new() -> Svc { Default::default() }
+
+    /// Add a spatial layer, above the ones already added. Up to 5, or 4
+    /// with 3 temporal layers.
+    pub fn spatial_layer(mut self, layer: SpatialLayer) -> Svc {
+        self.spatial_layers.push(layer);
+        self
+    }
+    /// 1 to 3 temporal layers, in the 0101 or 0212 pattern. Each spatial
+    /// layer's bitrate is split 60/40 between 2 layers, and 50/20/30
+    /// between 3.
+    pub fn temporal_layers(mut self, layers: u32) -> Svc {
+        self.temporal_layers = layers;
+        self
+    }
+
+    pub fn get_spatial_layers(&self) -> &[SpatialLayer] { &self.spatial_layers[..] }
+    pub fn get_temporal_layers(&self) -> u32 { self.temporal_layers }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        fn invalid(detail: &str) -> Result<(), Error> {
+            Err(Error::new(ErrorKind::InvalidParam, detail.to_string()))
+        }
+
+        if self.spatial_layers.is_empty() || self.spatial_layers.len() > MAX_SPATIAL_LAYERS {
+            return invalid("SVC needs 1 to 5 spatial layers");
+        }
+        try!(check_range("temporal_layers", self.temporal_layers, 1, MAX_TEMPORAL_LAYERS));
+        if self.spatial_layers.len() * self.temporal_layers as usize > MAX_LAYERS {
+            return invalid("SVC has at most 12 layers, spatial times temporal");
+        }
+        for layer in self.spatial_layers.iter() {
+            if layer.scaling_num == 0 || layer.scaling_num > layer.scaling_den {
+                return invalid("spatial layer scaling must be in (0..1]");
+            }
+            if layer.max_quantizer > 63 || layer.min_quantizer > layer.max_quantizer {
+                return invalid("spatial layer quantizers out of range");
+            }
+        }
+        Ok(())
+    }
+
+    fn apply(&self, cfg: &mut ffi::vpx_codec_enc_cfg_t) {
+        let (mode, pattern, shares) = temporal_pattern(self.temporal_layers);
+        let ts = shares.len();
+        cfg.ss_number_layers = self.spatial_layers.len() as libc::c_uint;
+        cfg.ts_number_layers = ts as libc::c_uint;
+        cfg.temporal_layering_mode = mode as libc::c_int;
+        cfg.ts_periodicity = pattern.len() as libc::c_uint;
+        for (i, &id) in pattern.iter().enumerate() {
+            cfg.ts_layer_id[i] = id as libc::c_uint;
+        }
+        for t in 0..ts {
+            cfg.ts_rate_decimator[t] = 1 << (ts - 1 - t);
+            cfg.ts_target_bitrate[t] = 0;
+        }
+
+        cfg.rc_target_bitrate = 0;
+        for (s, layer) in self.spatial_layers.iter().enumerate() {
+            if s >= MAX_SPATIAL_LAYERS {
+                break;
+            }
+            cfg.ss_target_bitrate[s] = layer.target_bitrate;
+            for (t, share) in shares.iter().enumerate() {
+                let kbps = layer.target_bitrate * share / 100;
+                cfg.layer_target_bitrate[s * ts + t] = kbps;
+                cfg.ts_target_bitrate[t] += kbps;
+            }
+            cfg.rc_target_bitrate += layer.target_bitrate;
+        }
+    }
+
+    fn parameters(&self) -> ffi::vpx_svc_extra_cfg_t {
+        let (mode, _, shares) = temporal_pattern(self.temporal_layers);
+        let ts = shares.len();
+        let mut params = ffi::vpx_svc_extra_cfg_t {
+            temporal_layering_mode: mode as libc::c_int,
+            ..Default::default()
+        };
+        for (s, layer) in self.spatial_layers.iter().enumerate() {
+            params.scaling_factor_num[s] = layer.scaling_num as libc::c_int;
+            params.scaling_factor_den[s] = layer.scaling_den as libc::c_int;
+            for t in 0..ts {
+                params.min_quantizers[s * ts + t] = layer.min_quantizer as libc::c_int;
+                params.max_quantizers[s * ts + t] = layer.max_quantizer as libc::c_int;
+            }
+        }
+        params
+    }
+}
+
+/// Adaptive quantization mode.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum AqMode {
+    None,
+    Variance,
+    Complexity,
+    CyclicRefresh,
+}
+impl Into<u32> for AqMode {
+    fn into(self) -> u32 {
+        match self {
+            AqMode::None => 0,
+            AqMode::Variance => 1,
+            AqMode::Complexity => 2,
+            AqMode::CyclicRefresh => 3,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum TuneContent {
+    Default,
+    Screen,
+}
+impl Into<i32> for TuneContent {
+    fn into(self) -> i32 {
+        match self {
+            TuneContent::Default => ffi::VP9E_CONTENT_DEFAULT as i32,
+            TuneContent::Screen => ffi::VP9E_CONTENT_SCREEN as i32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use BitDepth;
+    use encoder::CfgBuilder;
+    use super::{Cfg, SpatialLayer, Svc};
+
+    fn svc(spatial: usize, temporal: u32) -> Svc {
+        (0..spatial).fold(Svc::new().temporal_layers(temporal), |svc, s| {
+            svc.spatial_layer(SpatialLayer::new(1, 1 << (spatial - 1 - s), 100))
+        })
+    }
+
+    #[test]
+    fn svc_layer_limit() {
+        assert!(svc(5, 2).validate().is_ok());
+        assert!(svc(4, 3).validate().is_ok());
+        assert!(svc(5, 3).validate().is_err());
+
+        let cfg = Cfg(Default::default(), None, None).svc(svc(4, 3)).unwrap();
+        assert_eq!((cfg.0.ss_number_layers, cfg.0.ts_number_layers), (4, 3));
+        // The last entry: the top temporal layer, which has all of its
+        // spatial layer's bitrate.
+        assert_eq!(cfg.0.layer_target_bitrate[11], 100);
+        assert!(svc(4, 3).parameters().max_quantizers[..].iter().all(|&q| q == 63));
+
+        // Rejected before it would overrun the config's arrays.
+        assert!(Cfg(Default::default(), None, None).svc(svc(5, 3)).is_err());
+    }
+
+    #[test]
+    fn profiles() {
+        let cfg = || Cfg(Default::default(), None, None).size(64, 64).timebase(1, 30);
+        assert!(cfg().bit_depth(BitDepth::Eight).profile(1).validate().is_ok());
+        assert!(cfg().bit_depth(BitDepth::Ten).profile(1).validate().is_err());
+        assert!(cfg().bit_depth(BitDepth::Ten).profile(2).validate().is_ok());
+        assert!(cfg().bit_depth(BitDepth::Twelve).profile(4).validate().is_err());
+    }
+}
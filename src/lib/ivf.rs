@@ -0,0 +1,267 @@
+//! The IVF container: a 32-byte file header, then each frame prefixed with
+//! its size and timestamp. It's what libvpx's own tools read and write.
+
+use ffi;
+use encoder::PacketWriter;
+use Codec;
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+const SIGNATURE: &'static [u8; 4] = b"DKIF";
+const HEADER_SIZE: usize = 32;
+const FRAME_HEADER_SIZE: usize = 12;
+/// Offset of the frame count in the file header.
+const FRAME_COUNT_OFFSET: u64 = 24;
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Header {
+    /// The codec of the frames, as a fourcc (`VP80` or `VP90`).
+    pub fourcc: [u8; 4],
+    pub width: u16,
+    pub height: u16,
+    /// The units of the frames' timestamps, in seconds, as `num / den`.
+    pub timebase_num: u32,
+    pub timebase_den: u32,
+    /// Some writers leave this as 0, so don't rely on it.
+    pub frame_count: u32,
+}
+impl Header {
+    /// `None` if the fourcc isn't one of libvpx's.
+    pub fn codec(&self) -> Option<Codec> { Codec::from_fourcc(&self.fourcc) }
+
+    fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut buf = [0u8; HEADER_SIZE];
+        buf[0..4].copy_from_slice(SIGNATURE);
+        put_u16(&mut buf[4..], 0);
+        put_u16(&mut buf[6..], HEADER_SIZE as u16);
+        buf[8..12].copy_from_slice(&self.fourcc);
+        put_u16(&mut buf[12..], self.width);
+        put_u16(&mut buf[14..], self.height);
+        put_u32(&mut buf[16..], self.timebase_den);
+        put_u32(&mut buf[20..], self.timebase_num);
+        put_u32(&mut buf[24..], self.frame_count);
+        out.write_all(&buf)
+    }
+    fn read<R: Read>(input: &mut R) -> io::Result<Header> {
+        let mut buf = [0u8; HEADER_SIZE];
+        try!(input.read_exact(&mut buf));
+        if &buf[0..4] != SIGNATURE {
+            return Err(invalid_data("not an IVF file"));
+        }
+        let version = get_u16(&buf[4..]);
+        if version != 0 {
+            return Err(invalid_data("unsupported IVF version"));
+        }
+        // The header may be longer than what we understand.
+        let size = get_u16(&buf[6..]) as usize;
+        if size < HEADER_SIZE {
+            return Err(invalid_data("IVF header too short"));
+        }
+        try!(skip(input, (size - HEADER_SIZE) as u64));
+
+        let mut fourcc = [0u8; 4];
+        fourcc.copy_from_slice(&buf[8..12]);
+        Ok(Header {
+            fourcc: fourcc,
+            width: get_u16(&buf[12..]),
+            height: get_u16(&buf[14..]),
+            timebase_den: get_u32(&buf[16..]),
+            timebase_num: get_u32(&buf[20..]),
+            frame_count: get_u32(&buf[24..]),
+        })
+    }
+}
+
+fn put_u16(buf: &mut [u8], v: u16) {
+    buf[0] = v as u8;
+    buf[1] = (v >> 8) as u8;
+}
+fn put_u32(buf: &mut [u8], v: u32) {
+    put_u16(buf, v as u16);
+    put_u16(&mut buf[2..], (v >> 16) as u16);
+}
+fn put_u64(buf: &mut [u8], v: u64) {
+    put_u32(buf, v as u32);
+    put_u32(&mut buf[4..], (v >> 32) as u32);
+}
+fn get_u16(buf: &[u8]) -> u16 {
+    buf[0] as u16 | (buf[1] as u16) << 8
+}
+fn get_u32(buf: &[u8]) -> u32 {
+    get_u16(buf) as u32 | (get_u16(&buf[2..]) as u32) << 16
+}
+fn get_u64(buf: &[u8]) -> u64 {
+    get_u32(buf) as u64 | (get_u32(&buf[4..]) as u64) << 32
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+fn skip<R: Read>(input: &mut R, len: u64) -> io::Result<()> {
+    let skipped = try!(io::copy(&mut input.take(len), &mut io::sink()));
+    if skipped < len {
+        Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated IVF file"))
+    } else {
+        Ok(())
+    }
+}
+
+/// Writes the frames from `Encoder::packets` to an IVF file. Call `finish`
+/// once done, to fill in the frame count.
+pub struct Writer<W: Write + Seek> {
+    out: W,
+    start: u64,
+    frame_count: u32,
+    /// The partitions of a frame being output with
+    /// `VPX_CODEC_USE_OUTPUT_PARTITION`; they're joined into one IVF frame.
+    fragments: Vec<u8>,
+    fragments_pts: u64,
+}
+impl<W: Write + Seek> Writer<W> {
+    /// Write the file header, taking the size and timebase from `cfg`.
+    pub fn new<C>(mut out: W, codec: Codec, cfg: &C) -> io::Result<Writer<W>>
+        where C: AsRef<ffi::vpx_codec_enc_cfg_t>,
+    {
+        let cfg = cfg.as_ref();
+        if cfg.g_w > u16::max_value() as u32 || cfg.g_h > u16::max_value() as u32 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "IVF frame size is limited to 65535x65535"));
+        }
+        let header = Header {
+            fourcc: *codec.fourcc(),
+            width: cfg.g_w as u16,
+            height: cfg.g_h as u16,
+            timebase_num: cfg.g_timebase.num as u32,
+            timebase_den: cfg.g_timebase.den as u32,
+            frame_count: 0,
+        };
+
+        let start = try!(out.seek(SeekFrom::Current(0)));
+        try!(header.write(&mut out));
+        Ok(Writer {
+            out: out,
+            start: start,
+            frame_count: 0,
+            fragments: Vec::new(),
+            fragments_pts: 0,
+        })
+    }
+
+    pub fn frame_count(&self) -> u32 { self.frame_count }
+
+    /// Write one frame. `data` is a whole frame, not a partition.
+    pub fn write_raw_frame(&mut self, pts: u64, data: &[u8]) -> io::Result<()> {
+        let mut buf = [0u8; FRAME_HEADER_SIZE];
+        put_u32(&mut buf, data.len() as u32);
+        put_u64(&mut buf[4..], pts);
+        try!(self.out.write_all(&buf));
+        try!(self.out.write_all(data));
+        self.frame_count += 1;
+        Ok(())
+    }
+
+    /// Fill in the frame count, and return the output.
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.fragments.is_empty() {
+            let fragments = ::std::mem::replace(&mut self.fragments, Vec::new());
+            let pts = self.fragments_pts;
+            try!(self.write_raw_frame(pts, &fragments[..]));
+        }
+
+        let end = try!(self.out.seek(SeekFrom::Current(0)));
+        try!(self.out.seek(SeekFrom::Start(self.start + FRAME_COUNT_OFFSET)));
+        let mut buf = [0u8; 4];
+        put_u32(&mut buf, self.frame_count);
+        try!(self.out.write_all(&buf));
+        try!(self.out.seek(SeekFrom::Start(end)));
+        try!(self.out.flush());
+        Ok(self.out)
+    }
+}
+impl<W: Write + Seek> PacketWriter for Writer<W> {
+    fn write_frame<'a>(&mut self, frame: &::Frame<'a>) -> io::Result<()> {
+        if frame.is_fragment() || !self.fragments.is_empty() {
+            if self.fragments.is_empty() {
+                self.fragments_pts = frame.pts;
+            }
+            self.fragments.extend_from_slice(frame.data());
+            // The last partition of a frame doesn't have the flag.
+            if frame.is_fragment() {
+                return Ok(());
+            }
+            let fragments = ::std::mem::replace(&mut self.fragments, Vec::new());
+            let pts = self.fragments_pts;
+            return self.write_raw_frame(pts, &fragments[..]);
+        }
+        self.write_raw_frame(frame.pts, frame.data())
+    }
+}
+
+/// A frame read from an IVF file, ready for `Decoder::decode`.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Frame {
+    /// In units of the header's timebase.
+    pub pts: u64,
+    pub data: Vec<u8>,
+}
+
+/// Reads the frames of an IVF file.
+pub struct Reader<R: Read> {
+    input: R,
+    header: Header,
+}
+impl<R: Read> Reader<R> {
+    /// Read the file header.
+    pub fn new(mut input: R) -> io::Result<Reader<R>> {
+        let header = try!(Header::read(&mut input));
+        Ok(Reader {
+            input: input,
+            header: header,
+        })
+    }
+
+    pub fn header(&self) -> &Header { &self.header }
+    pub fn into_inner(self) -> R { self.input }
+
+    /// `None` at the end of the file.
+    pub fn read_frame(&mut self) -> io::Result<Option<Frame>> {
+        let mut buf = [0u8; FRAME_HEADER_SIZE];
+        // A clean end of file is only allowed between frames.
+        let mut read = 0;
+        while read < buf.len() {
+            match self.input.read(&mut buf[read..]) {
+                Ok(0) if read == 0 => return Ok(None),
+                Ok(0) => {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                              "truncated IVF frame header"));
+                },
+                Ok(n) => read += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e),
+            }
+        }
+
+        let size = get_u32(&buf) as u64;
+        let pts = get_u64(&buf[4..]);
+        let mut data = Vec::new();
+        let read = try!((&mut self.input).take(size).read_to_end(&mut data));
+        if (read as u64) < size {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                      "truncated IVF frame"));
+        }
+        Ok(Some(Frame {
+            pts: pts,
+            data: data,
+        }))
+    }
+}
+impl<R: Read> Iterator for Reader<R> {
+    type Item = io::Result<Frame>;
+    fn next(&mut self) -> Option<io::Result<Frame>> {
+        match self.read_frame() {
+            Ok(Some(frame)) => Some(Ok(frame)),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
@@ -1,14 +1,26 @@
 use std::borrow::Cow;
+use std::convert::TryFrom;
 use std::ffi::{CStr};
 use std::mem::transmute;
 
 extern crate vpx_sys as ffi;
 extern crate libc;
 
+pub mod bits;
+pub mod convert;
+pub mod decoder;
 pub mod encoder;
+pub mod ivf;
+pub mod rtp;
+pub mod vp8;
+pub mod vp9;
+pub mod webm;
+
+pub use ffi::{VPX_CODEC_ABI_VERSION, VPX_DECODER_ABI_VERSION, VPX_ENCODER_ABI_VERSION,
+              VPX_IMAGE_ABI_VERSION};
 
 #[derive(Clone, Copy, Eq, PartialEq, Debug)]
-pub enum Error {
+pub enum ErrorKind {
     Generic(u32),
     Mem,
     AbiMismatch,
@@ -22,40 +34,119 @@ pub enum Error {
     InvalidParam,
     ListEnd,
 }
-impl From<u32> for Error {
-    fn from(v: u32) -> Error {
+impl From<u32> for ErrorKind {
+    fn from(v: u32) -> ErrorKind {
         match v {
-            ffi::VPX_CODEC_MEM_ERROR => Error::Mem,
-            ffi::VPX_CODEC_ABI_MISMATCH => Error::AbiMismatch,
-            ffi::VPX_CODEC_INCAPABLE => Error::Incapable,
-            ffi::VPX_CODEC_UNSUP_BITSTREAM => Error::UnsupportedBitstream,
-            ffi::VPX_CODEC_UNSUP_FEATURE => Error::UnsupportedFrame,
-            ffi::VPX_CODEC_CORRUPT_FRAME => Error::CorruptFrame,
-            ffi::VPX_CODEC_INVALID_PARAM => Error::InvalidParam,
-            ffi::VPX_CODEC_LIST_END => Error::ListEnd,
-            n => Error::Generic(n),
+            ffi::VPX_CODEC_MEM_ERROR => ErrorKind::Mem,
+            ffi::VPX_CODEC_ABI_MISMATCH => ErrorKind::AbiMismatch,
+            ffi::VPX_CODEC_INCAPABLE => ErrorKind::Incapable,
+            ffi::VPX_CODEC_UNSUP_BITSTREAM => ErrorKind::UnsupportedBitstream,
+            ffi::VPX_CODEC_UNSUP_FEATURE => ErrorKind::UnsupportedFrame,
+            ffi::VPX_CODEC_CORRUPT_FRAME => ErrorKind::CorruptFrame,
+            ffi::VPX_CODEC_INVALID_PARAM => ErrorKind::InvalidParam,
+            ffi::VPX_CODEC_LIST_END => ErrorKind::ListEnd,
+            n => ErrorKind::Generic(n),
+        }
+    }
+}
+impl ErrorKind {
+    pub fn description(&self) -> &'static str {
+        match *self {
+            ErrorKind::Generic(_) => "Unspecified error",
+            ErrorKind::Mem => "Memory operation failed",
+            ErrorKind::AbiMismatch => "ABI version mismatch",
+            ErrorKind::Incapable => "Algorithm does not have required capability",
+            ErrorKind::UnsupportedBitstream => "The given bitstream is not supported",
+            ErrorKind::UnsupportedFrame => "Encoded bitstream uses an unsupported feature",
+            ErrorKind::CorruptFrame => "The coded data for this stream is corrupt or incomplete",
+            ErrorKind::InvalidParam => "An application-supplied parameter is not valid",
+            ErrorKind::ListEnd => "An iterator reached the end of list",
+        }
+    }
+}
+
+/// An error, along with whatever explanation libvpx (or this crate) could
+/// give for it.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    /// The `vpx_codec_error` string.
+    message: Option<String>,
+    /// The `vpx_codec_error_detail` string.
+    detail: Option<String>,
+}
+impl Error {
+    pub fn new(kind: ErrorKind, detail: String) -> Error {
+        Error {
+            kind: kind,
+            message: None,
+            detail: Some(detail),
+        }
+    }
+
+    /// Capture the error strings of `ctx` for `err`, which was just returned
+    /// by a call on `ctx`.
+    #[doc(hidden)]
+    pub unsafe fn from_ctx(ctx: *mut ffi::vpx_codec_ctx_t,
+                           err: ffi::vpx_codec_err_t) -> Error {
+        unsafe fn to_string(s: *const libc::c_char) -> Option<String> {
+            if s.is_null() {
+                None
+            } else {
+                Some(CStr::from_ptr(s).to_string_lossy().into_owned())
+            }
+        }
+
+        let detail = if (*ctx).iface.is_null() {
+            // A failed init has already torn `ctx` down, which resets its
+            // error, but libvpx leaves the detail in the public struct.
+            (*ctx).err_detail
+        } else {
+            ffi::vpx_codec_error_detail(ctx)
+        };
+
+        Error {
+            kind: From::from(err),
+            message: to_string(ffi::vpx_codec_err_to_string(err)),
+            detail: to_string(detail),
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind { self.kind }
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_ref().map(|s| &s[..])
+    }
+    pub fn detail(&self) -> Option<&str> {
+        self.detail.as_ref().map(|s| &s[..])
+    }
+}
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Error {
+        Error {
+            kind: kind,
+            message: None,
+            detail: None,
         }
     }
 }
+impl From<u32> for Error {
+    fn from(v: u32) -> Error {
+        From::<ErrorKind>::from(From::from(v))
+    }
+}
 
 impl std::fmt::Display for Error {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
-        <Self as std::fmt::Debug>::fmt(self,fmt)
+        try!(fmt.write_str(self.message().unwrap_or(self.kind.description())));
+        if let Some(detail) = self.detail() {
+            try!(write!(fmt, ": {}", detail));
+        }
+        Ok(())
     }
 }
 impl std::error::Error for Error {
     fn description(&self) -> &str {
-        match *self {
-            Error::Generic(_) => "Unspecified error",
-            Error::Mem => "Memory operation failed",
-            Error::AbiMismatch => "ABI version mismatch",
-            Error::Incapable => "Algorithm does not have required capability",
-            Error::UnsupportedBitstream => "The given bitstream is not supported",
-            Error::UnsupportedFrame => "Encoded bitstream uses an unsupported feature",
-            Error::CorruptFrame => "The coded data for this stream is corrupt or incomplete",
-            Error::InvalidParam => "An application-supplied parameter is not valid",
-            Error::ListEnd => "An iterator reached the end of list",
-        }
+        self.kind.description()
     }
 }
 
@@ -89,6 +180,56 @@ pub enum Format {
     /// Should be named `444A`.
     I444A,
 }
+impl Format {
+    /// Whether each component is stored in its own plane.
+    pub fn is_planar(&self) -> bool {
+        use Format::*;
+        match *self {
+            YV12_VPX | I420_VPX | YV12 | I420 { .. } | I422 { .. } |
+            I440 { .. } | I444 { .. } | I444A => true,
+            _ => false,
+        }
+    }
+    pub fn is_high_bit_depth(&self) -> bool {
+        use Format::*;
+        match *self {
+            I420 { hi_bit_depth } | I422 { hi_bit_depth } |
+            I440 { hi_bit_depth } | I444 { hi_bit_depth } => hi_bit_depth,
+            _ => false,
+        }
+    }
+    /// The size of one sample of a planar format, or of one pixel of a
+    /// packed format, in bytes.
+    pub fn bytes_per_sample(&self) -> usize {
+        use Format::*;
+        match *self {
+            RGB24 | BGR24 => 3,
+            RGB32 { .. } | ARGB | BGRA => 4,
+            RGB565 { .. } | RGB555 { .. } | UYVY | YUY2 | YVYU => 2,
+            _ if self.is_high_bit_depth() => 2,
+            _ => 1,
+        }
+    }
+    /// The number of planes: 1 for packed formats, 3 for planar YUV, and 4
+    /// with alpha.
+    pub fn num_planes(&self) -> usize {
+        match *self {
+            Format::I444A => 4,
+            _ if self.is_planar() => 3,
+            _ => 1,
+        }
+    }
+    /// The log2 of the horizontal and vertical chroma subsampling.
+    pub fn chroma_shift(&self) -> (u32, u32) {
+        use Format::*;
+        match *self {
+            YV12_VPX | I420_VPX | YV12 | I420 { .. } => (1, 1),
+            I422 { .. } | UYVY | YUY2 | YVYU => (1, 0),
+            I440 { .. } => (0, 1),
+            _ => (0, 0),
+        }
+    }
+}
 impl Into<ffi::vpx_img_fmt_t> for Format {
     fn into(self) -> ffi::vpx_img_fmt_t {
         use Format::*;
@@ -117,13 +258,13 @@ impl Into<ffi::vpx_img_fmt_t> for Format {
 
             I420 { hi_bit_depth: false } => VPX_IMG_FMT_I420,
             I422 { hi_bit_depth: false } => VPX_IMG_FMT_I422,
-            I440 { hi_bit_depth: false } => VPX_IMG_FMT_I444,
-            I444 { hi_bit_depth: false } => VPX_IMG_FMT_I440,
+            I440 { hi_bit_depth: false } => VPX_IMG_FMT_I440,
+            I444 { hi_bit_depth: false } => VPX_IMG_FMT_I444,
 
             I420 { hi_bit_depth: true } => VPX_IMG_FMT_I42016,
             I422 { hi_bit_depth: true } => VPX_IMG_FMT_I42216,
-            I440 { hi_bit_depth: true } => VPX_IMG_FMT_I44416,
-            I444 { hi_bit_depth: true } => VPX_IMG_FMT_I44016,
+            I440 { hi_bit_depth: true } => VPX_IMG_FMT_I44016,
+            I444 { hi_bit_depth: true } => VPX_IMG_FMT_I44416,
 
             /// Should be named `444A`.
             I444A => VPX_IMG_FMT_444A,
@@ -131,51 +272,369 @@ impl Into<ffi::vpx_img_fmt_t> for Format {
     }
 }
 
+impl TryFrom<ffi::vpx_img_fmt_t> for Format {
+    type Error = Error;
+    fn try_from(v: ffi::vpx_img_fmt_t) -> Result<Format, Error> {
+        use Format::*;
+        use ffi::*;
+
+        let fmt = match v {
+            VPX_IMG_FMT_RGB24 => RGB24,
+            VPX_IMG_FMT_RGB32 => RGB32 { le: false, },
+            VPX_IMG_FMT_RGB32_LE => RGB32 { le: true, },
+            VPX_IMG_FMT_RGB565 => RGB565 { le: false, },
+            VPX_IMG_FMT_RGB565_LE => RGB565 { le: true, },
+            VPX_IMG_FMT_RGB555 => RGB555 { le: false, },
+            VPX_IMG_FMT_RGB555_LE => RGB555 { le: true, },
+
+            VPX_IMG_FMT_UYVY => UYVY,
+            VPX_IMG_FMT_YUY2 => YUY2,
+            VPX_IMG_FMT_YVYU => YVYU,
+            VPX_IMG_FMT_BGR24 => BGR24,
+            VPX_IMG_FMT_ARGB => ARGB,
+            VPX_IMG_FMT_ARGB_LE => BGRA,
+
+            VPX_IMG_FMT_VPXYV12 => YV12_VPX,
+            VPX_IMG_FMT_VPXI420 => I420_VPX,
+
+            VPX_IMG_FMT_YV12 => YV12,
+
+            VPX_IMG_FMT_I420 => I420 { hi_bit_depth: false },
+            VPX_IMG_FMT_I422 => I422 { hi_bit_depth: false },
+            VPX_IMG_FMT_I440 => I440 { hi_bit_depth: false },
+            VPX_IMG_FMT_I444 => I444 { hi_bit_depth: false },
+
+            VPX_IMG_FMT_I42016 => I420 { hi_bit_depth: true },
+            VPX_IMG_FMT_I42216 => I422 { hi_bit_depth: true },
+            VPX_IMG_FMT_I44016 => I440 { hi_bit_depth: true },
+            VPX_IMG_FMT_I44416 => I444 { hi_bit_depth: true },
+
+            VPX_IMG_FMT_444A => I444A,
+
+            v => {
+                return Err(Error::new(ErrorKind::InvalidParam,
+                                      format!("unknown image format {}", v)));
+            },
+        };
+        Ok(fmt)
+    }
+}
+
 #[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
 #[allow(non_camel_case_types)]
 pub enum ColorSpace {
+    Unknown,
     BT601,
     BT709,
     SMPTE170,
     SMPTE240,
     BT2020,
+    Reserved,
     SRGB,
 }
 impl Into<ffi::vpx_color_space_t> for ColorSpace {
     fn into(self) -> ffi::vpx_color_space_t {
         match self {
+            ColorSpace::Unknown => ffi::VPX_CS_UNKNOWN,
             ColorSpace::BT601 => ffi::VPX_CS_BT_601,
             ColorSpace::BT709 => ffi::VPX_CS_BT_709,
             ColorSpace::SMPTE170 => ffi::VPX_CS_SMPTE_170,
             ColorSpace::SMPTE240 => ffi::VPX_CS_SMPTE_240,
             ColorSpace::BT2020 => ffi::VPX_CS_BT_2020,
+            ColorSpace::Reserved => ffi::VPX_CS_RESERVED,
             ColorSpace::SRGB => ffi::VPX_CS_SRGB,
         }
     }
 }
+impl TryFrom<ffi::vpx_color_space_t> for ColorSpace {
+    type Error = Error;
+    fn try_from(v: ffi::vpx_color_space_t) -> Result<ColorSpace, Error> {
+        let cs = match v {
+            ffi::VPX_CS_UNKNOWN => ColorSpace::Unknown,
+            ffi::VPX_CS_BT_601 => ColorSpace::BT601,
+            ffi::VPX_CS_BT_709 => ColorSpace::BT709,
+            ffi::VPX_CS_SMPTE_170 => ColorSpace::SMPTE170,
+            ffi::VPX_CS_SMPTE_240 => ColorSpace::SMPTE240,
+            ffi::VPX_CS_BT_2020 => ColorSpace::BT2020,
+            ffi::VPX_CS_RESERVED => ColorSpace::Reserved,
+            ffi::VPX_CS_SRGB => ColorSpace::SRGB,
+            v => {
+                return Err(Error::new(ErrorKind::InvalidParam,
+                                      format!("unknown color space {}", v)));
+            },
+        };
+        Ok(cs)
+    }
+}
+
+/// The number of bits per sample. Anything above 8 is stored in 16-bit
+/// samples, using one of the `hi_bit_depth` formats.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum BitDepth {
+    Eight,
+    Ten,
+    Twelve,
+}
+impl BitDepth {
+    pub fn bits(&self) -> u32 {
+        match *self {
+            BitDepth::Eight => 8,
+            BitDepth::Ten => 10,
+            BitDepth::Twelve => 12,
+        }
+    }
+}
+impl Into<ffi::vpx_bit_depth_t> for BitDepth {
+    fn into(self) -> ffi::vpx_bit_depth_t {
+        match self {
+            BitDepth::Eight => ffi::VPX_BITS_8,
+            BitDepth::Ten => ffi::VPX_BITS_10,
+            BitDepth::Twelve => ffi::VPX_BITS_12,
+        }
+    }
+}
+impl TryFrom<u32> for BitDepth {
+    type Error = Error;
+    fn try_from(bits: u32) -> Result<BitDepth, Error> {
+        match bits {
+            8 => Ok(BitDepth::Eight),
+            10 => Ok(BitDepth::Ten),
+            12 => Ok(BitDepth::Twelve),
+            _ => Err(Error::new(ErrorKind::Incapable,
+                                format!("unsupported bit depth {}", bits))),
+        }
+    }
+}
 
 pub struct Image<'a>(ffi::vpx_image_t, Format, Cow<'a, [u8]>);
 
 impl<'a> Image<'a> {
-    /// XXX this function doesn't check that `data` is long enough for the
-    /// format or view size.
+    /// Wrap `data`, whose rows are padded to a multiple of `stride_align`
+    /// bytes (as with `vpx_img_wrap`, which this uses). Fails if `data` is
+    /// shorter than `Image::required_size`; it may be longer.
     pub fn new(data: Cow<'a, [u8]>, fmt: Format,
                color_space: ColorSpace,
                width: u32, height: u32,
-               stride: u32) -> Image
+               stride_align: u32) -> Result<Image<'a>, Error>
     {
-        let mut t: ffi::vpx_image_t = Default::default();
-        unsafe {
-            ffi::vpx_img_wrap(&mut t as *mut _,
-                              fmt.into(), width,
-                              height, stride,
-                              data.as_ptr() as *mut _);
+        if width == 0 || height == 0 {
+            return Err(Error::new(ErrorKind::InvalidParam,
+                                  format!("{}x{} image is empty", width, height)));
+        }
+        let required = match Image::required_size(fmt, width, height,
+                                                  stride_align) {
+            Some(required) => required,
+            None => {
+                return Err(Error::new(ErrorKind::InvalidParam,
+                                      format!("stride alignment {} isn't a power of two",
+                                              stride_align)));
+            },
         };
+        if data.len() < required {
+            return Err(Error::new(ErrorKind::InvalidParam,
+                                  format!("{:?} image of {}x{} with stride alignment {} \
+                                           needs {} bytes, but got {}",
+                                          fmt, width, height, stride_align,
+                                          required, data.len())));
+        }
+
+        let image = unsafe {
+            Image::new_unchecked(data, fmt, color_space, width, height,
+                                 stride_align)
+        };
+        if image.0.img_data.is_null() {
+            Err(Error::new(ErrorKind::InvalidParam,
+                           format!("libvpx can't wrap {:?} images", fmt)))
+        } else {
+            Ok(image)
+        }
+    }
+    /// Like `new`, but doesn't check that `data` is long enough for the
+    /// format and size, leaving the encoder to read out of bounds if it
+    /// isn't.
+    pub unsafe fn new_unchecked(data: Cow<'a, [u8]>, fmt: Format,
+                                color_space: ColorSpace,
+                                width: u32, height: u32,
+                                stride_align: u32) -> Image<'a>
+    {
+        let mut t: ffi::vpx_image_t = Default::default();
+        ffi::vpx_img_wrap(&mut t as *mut _,
+                          fmt.into(), width,
+                          height, stride_align,
+                          data.as_ptr() as *mut _);
         t.cs = color_space.into();
+        t.bit_depth = Image::default_bit_depth(fmt).bits();
         Image(t, fmt, data)
     }
 
+    /// The number of bytes `vpx_img_wrap` will use for an image, laid out
+    /// with each row padded to a multiple of `stride_align` bytes and the
+    /// planes one after the other. `None` if `stride_align` isn't a power of
+    /// two (0 is treated as 1).
+    pub fn required_size(fmt: Format, width: u32, height: u32,
+                         stride_align: u32) -> Option<usize> {
+        let stride_align = if stride_align == 0 { 1 } else { stride_align as usize };
+        if !stride_align.is_power_of_two() { return None; }
+
+        // The plane dimensions, including the luma plane's and so the
+        // stride, are rounded up to whole chroma samples, as in
+        // `vpx_img_wrap`. Otherwise odd widths would leave the chroma
+        // stride a sample short.
+        let (x_shift, y_shift) = if fmt.is_planar() {
+            fmt.chroma_shift()
+        } else {
+            (0, 0)
+        };
+        let width = ((width as usize + (1 << x_shift) - 1) >> x_shift) << x_shift;
+        let height = ((height as usize + (1 << y_shift) - 1) >> y_shift) << y_shift;
+
+        let row = width * fmt.bytes_per_sample();
+        let stride = if fmt.is_high_bit_depth() {
+            // libvpx aligns the stride in samples, then doubles it.
+            ((width + stride_align - 1) & !(stride_align - 1)) * 2
+        } else {
+            (row + stride_align - 1) & !(stride_align - 1)
+        };
+
+        if !fmt.is_planar() {
+            return Some(stride * height);
+        }
+
+        let luma = stride * height;
+        let chroma = (stride >> x_shift) * (height >> y_shift);
+        // The alpha plane, if any, is the size of the luma plane.
+        Some(luma * (fmt.num_planes() - 2) + chroma * 2)
+    }
+
+    /// Allocate an image whose planes' strides are aligned to `align`
+    /// bytes, which must be a power of two.
+    pub fn alloc(fmt: Format, color_space: ColorSpace,
+                 width: u32, height: u32,
+                 align: u32) -> Result<Image<'static>, Error>
+    {
+        if align == 0 || !align.is_power_of_two() {
+            return Err(Error::new(ErrorKind::InvalidParam,
+                                  format!("alignment {} isn't a power of two", align)));
+        }
+
+        let mut t: ffi::vpx_image_t = Default::default();
+        let res = unsafe {
+            ffi::vpx_img_alloc(&mut t as *mut _,
+                               fmt.into(), width,
+                               height, align)
+        };
+        if res.is_null() {
+            return Err(From::from(ErrorKind::Mem));
+        }
+        t.cs = color_space.into();
+        t.bit_depth = Image::default_bit_depth(fmt).bits();
+        Ok(Image(t, fmt, Cow::Borrowed(&[])))
+    }
+
     pub fn get_format(&self) -> Format { self.1.clone() }
+    /// 8 for the formats with 8-bit samples. High bit depth images start out
+    /// as 10-bit; use `set_bit_depth` if they're 12-bit.
+    pub fn bit_depth(&self) -> BitDepth {
+        BitDepth::try_from(self.0.bit_depth)
+            .unwrap_or(Image::default_bit_depth(self.1))
+    }
+    /// Fails if `depth` doesn't fit the format's sample size.
+    pub fn set_bit_depth(&mut self, depth: BitDepth) -> Result<(), Error> {
+        if self.1.is_high_bit_depth() == (depth == BitDepth::Eight) {
+            return Err(Error::new(ErrorKind::InvalidParam,
+                                  format!("{:?} images can't be {} bit",
+                                          self.1, depth.bits())));
+        }
+        self.0.bit_depth = depth.bits();
+        Ok(())
+    }
+    fn default_bit_depth(fmt: Format) -> BitDepth {
+        if fmt.is_high_bit_depth() { BitDepth::Ten } else { BitDepth::Eight }
+    }
+    pub fn get_color_space(&self) -> ColorSpace {
+        ColorSpace::try_from(self.0.cs).unwrap_or(ColorSpace::Unknown)
+    }
+
+    pub fn width(&self) -> u32 { self.0.w }
+    pub fn height(&self) -> u32 { self.0.h }
+    pub fn display_width(&self) -> u32 { self.0.d_w }
+    pub fn display_height(&self) -> u32 { self.0.d_h }
+
+    /// The stride, in bytes, of `plane`. `plane` is 0 for packed formats,
+    /// and 0 to 2 (Y, U, V) or 3 (alpha) for planar formats. `None` if the
+    /// format doesn't have that plane.
+    pub fn stride(&self, plane: usize) -> Option<isize> {
+        if plane >= self.1.num_planes() { return None; }
+        Some(self.0.stride[plane] as isize)
+    }
+    /// The width and height, in samples, of `plane`.
+    pub fn plane_size(&self, plane: usize) -> (u32, u32) {
+        let (x_shift, y_shift) = self.1.chroma_shift();
+        if self.1.is_planar() && (plane == 1 || plane == 2) {
+            ((self.0.d_w + x_shift) >> x_shift,
+             (self.0.d_h + y_shift) >> y_shift)
+        } else {
+            (self.0.d_w, self.0.d_h)
+        }
+    }
+
+    /// The span of `plane`'s memory, in bytes. `None` if the format doesn't
+    /// have that plane, or the image has been flipped.
+    fn plane_len(&self, plane: usize) -> Option<usize> {
+        if plane >= self.1.num_planes() || self.0.planes[plane].is_null() ||
+            self.0.stride[plane] < 0
+        {
+            return None;
+        }
+
+        let (width, height) = self.plane_size(plane);
+        if height == 0 { return Some(0); }
+        let row = width as usize * self.1.bytes_per_sample();
+        Some(self.0.stride[plane] as usize * (height as usize - 1) + row)
+    }
+    /// Whether the planes may be mutated, ie this image wasn't created from
+    /// borrowed data.
+    pub fn is_writable(&self) -> bool {
+        self.0.img_data_owner != 0 || match self.2 {
+            Cow::Owned(_) => true,
+            Cow::Borrowed(_) => false,
+        }
+    }
+
+    /// The rows of `plane`, `stride` bytes apart. The last row doesn't
+    /// include the padding past the image's width.
+    pub fn plane(&self, plane: usize) -> Option<&[u8]> {
+        self.plane_len(plane).map(|len| unsafe {
+            ::std::slice::from_raw_parts(self.0.planes[plane] as *const u8, len)
+        })
+    }
+    /// Like `plane`, but `None` if `!self.is_writable()`.
+    pub fn plane_mut(&mut self, plane: usize) -> Option<&mut [u8]> {
+        if !self.is_writable() { return None; }
+        self.plane_len(plane).map(|len| unsafe {
+            ::std::slice::from_raw_parts_mut(self.0.planes[plane], len)
+        })
+    }
+    /// `plane` of a high bit depth format, in samples. The stride is still in
+    /// bytes. `None` if the format isn't high bit depth, or the plane isn't
+    /// 2-byte aligned.
+    pub fn plane_u16(&self, plane: usize) -> Option<&[u16]> {
+        if !self.1.is_high_bit_depth() { return None; }
+        self.plane_len(plane).and_then(|len| {
+            let ptr = self.0.planes[plane];
+            if ptr as usize % 2 != 0 { return None; }
+            Some(unsafe { ::std::slice::from_raw_parts(ptr as *const u16, len / 2) })
+        })
+    }
+    /// Like `plane_u16`, but `None` if `!self.is_writable()`.
+    pub fn plane_u16_mut(&mut self, plane: usize) -> Option<&mut [u16]> {
+        if !self.is_writable() || !self.1.is_high_bit_depth() { return None; }
+        self.plane_len(plane).and_then(|len| {
+            let ptr = self.0.planes[plane];
+            if ptr as usize % 2 != 0 { return None; }
+            Some(unsafe { ::std::slice::from_raw_parts_mut(ptr as *mut u16, len / 2) })
+        })
+    }
 
     pub fn set_rect(&mut self, rect: Rect) -> Result<(), ()> {
         let res = unsafe {
@@ -207,6 +666,11 @@ pub struct Frame<'a> {
     pub duration: u64,
     pub flags: ffi::vpx_codec_frame_flags_t,
     pub partition_id: i32,
+    /// The highest spatial layer in the frame, with SVC. The frame is a
+    /// superframe with a frame for each spatial layer up to this one.
+    pub spatial_layer: Option<u8>,
+    /// The temporal layer of the frame, if the encoder uses them.
+    pub temporal_layer: Option<u8>,
 }
 pub const FRAME_IS_KEY: u32 = 0x1;
 pub const FRAME_IS_DROPPABLE: u32 = 0x2;
@@ -240,6 +704,85 @@ impl<'a> From<&'a ffi::Struct_Unnamed6> for Frame<'a> {
             duration: v.duration as u64,
             flags: v.flags,
             partition_id: v.partition_id,
+            spatial_layer: None,
+            temporal_layer: None,
+        }
+    }
+}
+
+/// A `Frame` which owns its data.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct OwnedFrame {
+    data: Vec<u8>,
+    pub pts: u64,
+    pub duration: u64,
+    pub flags: ffi::vpx_codec_frame_flags_t,
+    pub partition_id: i32,
+    pub spatial_layer: Option<u8>,
+    pub temporal_layer: Option<u8>,
+}
+impl OwnedFrame {
+    pub fn data(&self) -> &[u8] { &self.data[..] }
+    pub fn into_data(self) -> Vec<u8> { self.data }
+
+    /// Borrow this as a `Frame`, eg for a `PacketWriter`.
+    pub fn as_frame<'a>(&'a self) -> Frame<'a> {
+        Frame {
+            data: &self.data[..],
+            pts: self.pts,
+            duration: self.duration,
+            flags: self.flags,
+            partition_id: self.partition_id,
+            spatial_layer: self.spatial_layer,
+            temporal_layer: self.temporal_layer,
+        }
+    }
+
+    pub fn is_keyframe(&self) -> bool {
+        self.flags & FRAME_IS_KEY != 0
+    }
+    pub fn is_droppable(&self) -> bool {
+        self.flags & FRAME_IS_DROPPABLE != 0
+    }
+    pub fn is_invisible(&self) -> bool {
+        self.flags & FRAME_IS_INVISIBLE != 0
+    }
+    pub fn is_fragment(&self) -> bool {
+        self.flags & FRAME_IS_FRAGMENT != 0
+    }
+}
+impl<'a, 'b> From<&'b Frame<'a>> for OwnedFrame {
+    fn from(v: &'b Frame<'a>) -> OwnedFrame {
+        OwnedFrame {
+            data: v.data.to_vec(),
+            pts: v.pts,
+            duration: v.duration,
+            flags: v.flags,
+            partition_id: v.partition_id,
+            spatial_layer: v.spatial_layer,
+            temporal_layer: v.temporal_layer,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Codec {
+    VP8,
+    VP9,
+}
+impl Codec {
+    /// The fourcc used by IVF and other containers.
+    pub fn fourcc(&self) -> &'static [u8; 4] {
+        match *self {
+            Codec::VP8 => b"VP80",
+            Codec::VP9 => b"VP90",
+        }
+    }
+    pub fn from_fourcc(fourcc: &[u8; 4]) -> Option<Codec> {
+        match fourcc {
+            b"VP80" => Some(Codec::VP8),
+            b"VP90" => Some(Codec::VP9),
+            _ => None,
         }
     }
 }
@@ -268,35 +811,3 @@ pub trait InternalInterface {
     fn iface(&self) -> *mut ffi::vpx_codec_iface_t;
 }
 
-
-/*#[derive(Copy, Clone)]
-pub struct VP8DecoderInterface;
-impl Interface for VP8DecoderInterface {
-    fn kind(&self) -> Kind { Kind::Decoder }
-}
-impl InternalInterface for VP8DecoderInterface {
-    fn iface(&self) -> *mut ffi::vpx_codec_iface_t {
-        &mut ffi::vpx_codec_vp8_dx_algo as *mut _
-    }
-}
-#[derive(Copy, Clone)]
-pub struct VP9DecoderInterface;
-impl Interface for VP9DecoderInterface {
-    fn kind(&self) -> Kind { Kind::Decoder }
-}
-impl InternalInterface for VP9DecoderInterface {
-    fn iface(&self) -> *mut ffi::vpx_codec_iface_t {
-        &mut ffi::vpx_codec_vp9_dx_algo as *mut _
-    }
-}
-
-#[derive(Copy, Clone)]
-pub struct VP8EncoderInterface;
-impl Interface for VP8EncoderInterface {
-    fn kind(&self) -> Kind { Kind::Decoder }
-}
-impl InternalInterface for VP8EncoderInterface {
-    fn iface(&self) -> *mut ffi::vpx_codec_iface_t {
-        &mut ffi::vpx_codec_vp8_cx_algo as *mut _
-    }
-}*/
@@ -0,0 +1,281 @@
+//! A jitter buffer for received packets: it puts them back in order, asks
+//! for missing ones, and only returns frames which the decoder has the
+//! references of, asking for a keyframe when it can't recover otherwise.
+//!
+//! It doesn't keep time; the caller decides how long to wait for a missing
+//! packet, with `JitterBuffer::max_reorder` or `JitterBuffer::skip_missing`.
+
+use Error;
+use super::{sequence_diff, Packet, PictureId};
+
+use std::collections::{BTreeMap, VecDeque};
+
+/// How many newer packets to wait for before giving up on a missing one.
+const DEFAULT_MAX_REORDER: u64 = 32;
+/// How many decoded picture IDs to remember, for frames which reference
+/// them explicitly.
+const DECODED_HISTORY: usize = 128;
+
+/// What the jitter buffer needs to know about a frame to tell whether it
+/// can be decoded.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct References {
+    pub keyframe: bool,
+    /// Packets were lost before this frame.
+    pub after_loss: bool,
+    /// No other frame references this one.
+    pub non_reference: bool,
+    pub picture_id: Option<PictureId>,
+    pub tl0_pic_idx: Option<u8>,
+    pub temporal_id: Option<u8>,
+    /// The frame only references base temporal layer frames.
+    pub layer_sync: bool,
+    /// The picture IDs of the frames this one references, if the payload
+    /// format says.
+    pub references: Option<Vec<u16>>,
+    /// The picture IDs of non-reference frames which were lost since the
+    /// previous frame. A gap in picture IDs of only these doesn't need
+    /// recovering from.
+    ///
+    /// Only frames with at least one packet received can be known to be
+    /// non-reference frames, so one lost entirely still leaves a gap which
+    /// needs a keyframe. With `temporal_id` and `tl0_pic_idx`, the gap is
+    /// judged by those instead, and this isn't needed.
+    pub lost_non_reference: Vec<u16>,
+}
+
+/// A payload format's depacketizer.
+pub trait Depacketize {
+    type Frame;
+    /// Give the next packet, in sequence number order.
+    fn push(&mut self, packet: &Packet) -> Result<Option<Self::Frame>, Error>;
+    fn references(frame: &Self::Frame) -> References;
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Event<F> {
+    /// The next frame to decode.
+    Frame(F),
+    /// Ask the sender to resend these packets, eg with an RTCP generic NACK.
+    Nack(Vec<u16>),
+    /// Ask the sender for a keyframe, eg with an RTCP PLI, since the frames
+    /// until the next one can't be decoded.
+    KeyframeRequest,
+}
+
+pub struct JitterBuffer<D: Depacketize> {
+    depacketizer: D,
+    max_reorder: u64,
+    /// Packets waiting for a missing one, by extended sequence number.
+    buffer: BTreeMap<u64, Packet>,
+    /// The extended sequence number of the next packet for the depacketizer.
+    next: Option<u64>,
+    highest: u64,
+
+    have_keyframe: bool,
+    /// Whether every reference frame since the last keyframe was decoded.
+    chain_intact: bool,
+    keyframe_requested: bool,
+    last_picture_id: Option<PictureId>,
+    last_tl0_pic_idx: Option<u8>,
+    /// Whether an upper temporal layer frame was lost since the last base
+    /// layer frame.
+    loss_since_tl0: bool,
+    decoded: VecDeque<u16>,
+}
+impl<D: Depacketize> JitterBuffer<D> {
+    pub fn new(depacketizer: D) -> JitterBuffer<D> {
+        JitterBuffer {
+            depacketizer: depacketizer,
+            max_reorder: DEFAULT_MAX_REORDER,
+            buffer: BTreeMap::new(),
+            next: None,
+            highest: 0,
+            have_keyframe: false,
+            chain_intact: false,
+            keyframe_requested: false,
+            last_picture_id: None,
+            last_tl0_pic_idx: None,
+            loss_since_tl0: false,
+            decoded: VecDeque::new(),
+        }
+    }
+
+    /// How many packets after a missing one to wait for it. Defaults to 32.
+    pub fn max_reorder(mut self, packets: u16) -> JitterBuffer<D> {
+        self.max_reorder = packets as u64;
+        self
+    }
+
+    pub fn depacketizer(&self) -> &D { &self.depacketizer }
+    /// The number of packets waiting for a missing one.
+    pub fn buffered(&self) -> usize { self.buffer.len() }
+
+    /// Take a received packet. Returns, in order, requests for the packets
+    /// this shows are missing, and the frames which are now ready.
+    pub fn push(&mut self, packet: Packet) -> Vec<Event<D::Frame>> {
+        let mut events = Vec::new();
+
+        let seq = match self.next {
+            None => {
+                // Leave room for packets before the first one.
+                let seq = (1 << 32) + packet.sequence as u64;
+                self.next = Some(seq);
+                self.highest = seq;
+                seq
+            },
+            Some(_) => {
+                let diff = sequence_diff(packet.sequence, self.highest as u16);
+                (self.highest as i64 + diff as i64) as u64
+            },
+        };
+        if seq < self.next.unwrap() {
+            // Late, or a duplicate.
+            return events;
+        }
+        if seq > self.highest {
+            let first = ::std::cmp::max(self.highest + 1, seq.saturating_sub(self.max_reorder));
+            let missing: Vec<u16> = (first..seq).map(|s| s as u16).collect();
+            if !missing.is_empty() {
+                events.push(Event::Nack(missing));
+            }
+            self.highest = seq;
+        }
+        self.buffer.entry(seq).or_insert(packet);
+
+        self.drain(&mut events, false);
+        events
+    }
+
+    /// Give up on the missing packets the buffered ones are waiting for,
+    /// eg after a timeout.
+    pub fn skip_missing(&mut self) -> Vec<Event<D::Frame>> {
+        let mut events = Vec::new();
+        self.drain(&mut events, true);
+        events
+    }
+
+    fn drain(&mut self, events: &mut Vec<Event<D::Frame>>, skip: bool) {
+        loop {
+            let next = match self.next {
+                Some(next) => next,
+                None => return,
+            };
+            let packet = match self.buffer.remove(&next) {
+                Some(packet) => packet,
+                None => {
+                    let first = match self.buffer.keys().next() {
+                        Some(&first) => first,
+                        None => return,
+                    };
+                    if !skip && self.highest - next < self.max_reorder {
+                        return;
+                    }
+                    self.next = Some(first);
+                    continue;
+                },
+            };
+            self.next = Some(next + 1);
+
+            // Bad packets show up as loss in the frames after them.
+            if let Ok(Some(frame)) = self.depacketizer.push(&packet) {
+                let refs = D::references(&frame);
+                if self.decodable(&refs) {
+                    self.decoded_frame(&refs);
+                    events.push(Event::Frame(frame));
+                } else if self.dropped_frame(&refs) && !self.keyframe_requested {
+                    self.keyframe_requested = true;
+                    events.push(Event::KeyframeRequest);
+                }
+            }
+        }
+    }
+
+    fn decodable(&self, refs: &References) -> bool {
+        if refs.keyframe {
+            return true;
+        }
+        if !self.have_keyframe {
+            return false;
+        }
+        if let Some(ref ids) = refs.references {
+            return ids.iter().all(|id| self.decoded.contains(id));
+        }
+        if let (Some(tid), Some(tl0)) = (refs.temporal_id, refs.tl0_pic_idx) {
+            let last = match self.last_tl0_pic_idx {
+                Some(last) => last,
+                None => return false,
+            };
+            if tid == 0 {
+                return tl0 == last.wrapping_add(1);
+            }
+            let loss = self.loss_since_tl0 || refs.after_loss;
+            return tl0 == last && (refs.layer_sync || !loss);
+        }
+        if !self.chain_intact {
+            return false;
+        }
+        if !refs.after_loss {
+            return true;
+        }
+        // Only packets of frames which aren't needed were lost.
+        let (id, last) = match (refs.picture_id, self.last_picture_id) {
+            (Some(id), Some(last)) => (id, last),
+            _ => return false,
+        };
+        let mut next = last.next();
+        while next != id {
+            if !refs.lost_non_reference.contains(&next.value()) {
+                return false;
+            }
+            next = next.next();
+        }
+        true
+    }
+
+    fn decoded_frame(&mut self, refs: &References) {
+        if refs.keyframe {
+            self.have_keyframe = true;
+            self.chain_intact = true;
+            self.keyframe_requested = false;
+            self.decoded.clear();
+        }
+        if let Some(id) = refs.picture_id {
+            self.last_picture_id = Some(id);
+            if self.decoded.len() == DECODED_HISTORY {
+                self.decoded.pop_front();
+            }
+            self.decoded.push_back(id.value());
+        }
+        match refs.temporal_id {
+            Some(tid) if tid > 0 => {
+                if refs.after_loss {
+                    self.loss_since_tl0 = true;
+                }
+            },
+            _ => {
+                if refs.tl0_pic_idx.is_some() {
+                    self.last_tl0_pic_idx = refs.tl0_pic_idx;
+                }
+                self.loss_since_tl0 = false;
+            },
+        }
+    }
+
+    /// Returns whether a keyframe is needed to recover.
+    fn dropped_frame(&mut self, refs: &References) -> bool {
+        if !self.have_keyframe {
+            return true;
+        }
+        if refs.temporal_id.map(|tid| tid > 0).unwrap_or(false) {
+            // The next base layer frame will do.
+            self.loss_since_tl0 = true;
+            return false;
+        }
+        if refs.non_reference {
+            return false;
+        }
+        self.chain_intact = false;
+        true
+    }
+}
@@ -0,0 +1,93 @@
+//! RTP payload formats for VP8 and VP9: splitting the encoder's frames into
+//! packets, and putting received packets back together for the decoder. The
+//! rest of the RTP header, and RTCP, are left to the caller's RTP stack.
+
+pub mod jitter;
+pub mod vp8;
+pub mod vp9;
+
+use {Error, ErrorKind};
+
+/// The RTP clock rate of both payload formats.
+pub const CLOCK_RATE: u32 = 90000;
+
+/// The parts of an RTP packet the payload formats deal with.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Packet {
+    pub sequence: u16,
+    /// In units of `CLOCK_RATE`.
+    pub timestamp: u32,
+    /// Set on the last packet of each frame.
+    pub marker: bool,
+    pub payload: Vec<u8>,
+}
+
+/// The picture ID of both payload descriptors, which may be 7 or 15 bits.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum PictureId {
+    Short(u8),
+    Long(u16),
+}
+impl PictureId {
+    pub fn value(&self) -> u16 {
+        match *self {
+            PictureId::Short(v) => v as u16,
+            PictureId::Long(v) => v,
+        }
+    }
+    /// The ID of the next picture, wrapping around at the same width.
+    pub fn next(&self) -> PictureId {
+        match *self {
+            PictureId::Short(v) => PictureId::Short(v.wrapping_add(1) & 0x7f),
+            PictureId::Long(v) => PictureId::Long(v.wrapping_add(1) & 0x7fff),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match *self {
+            PictureId::Short(_) => 1,
+            PictureId::Long(_) => 2,
+        }
+    }
+    fn write(&self, buf: &mut Vec<u8>) {
+        match *self {
+            PictureId::Short(v) => buf.push(v & 0x7f),
+            PictureId::Long(v) => {
+                buf.push(0x80 | (v >> 8) as u8 & 0x7f);
+                buf.push(v as u8);
+            },
+        }
+    }
+    fn parse(data: &[u8]) -> Result<PictureId, Error> {
+        match data.first() {
+            Some(&b) if b & 0x80 == 0 => Ok(PictureId::Short(b)),
+            Some(&b) if data.len() >= 2 => {
+                Ok(PictureId::Long((b as u16 & 0x7f) << 8 | data[1] as u16))
+            },
+            _ => Err(truncated()),
+        }
+    }
+}
+
+/// Convert a `pts` in the encoder's timebase to an RTP timestamp.
+pub fn timestamp(pts: u64, timebase_num: u32, timebase_den: u32) -> u32 {
+    (pts * timebase_num as u64 * CLOCK_RATE as u64 / timebase_den as u64) as u32
+}
+
+/// `a - b`, for sequence numbers which may have wrapped around.
+pub fn sequence_diff(a: u16, b: u16) -> i16 {
+    a.wrapping_sub(b) as i16
+}
+
+fn truncated() -> Error {
+    Error::new(ErrorKind::CorruptFrame,
+               "payload descriptor is truncated".to_string())
+}
+
+/// Split `len` bytes into as few chunks of at most `max` bytes as possible,
+/// with sizes as even as possible.
+fn split_sizes(len: usize, max: usize) -> Vec<usize> {
+    let count = ::std::cmp::max((len + max - 1) / max, 1);
+    let (size, rem) = (len / count, len % count);
+    (0..count).map(|i| if i < rem { size + 1 } else { size }).collect()
+}
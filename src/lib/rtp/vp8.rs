@@ -0,0 +1,395 @@
+//! The VP8 payload format of RFC 7741.
+
+use {Error, ErrorKind, Frame as EncodedFrame};
+use vp8::header;
+use super::{sequence_diff, split_sizes, truncated, Packet, PictureId};
+use super::jitter::{Depacketize, References};
+
+const X_BIT: u8 = 0x80;
+const N_BIT: u8 = 0x20;
+const S_BIT: u8 = 0x10;
+const PID_MASK: u8 = 0x07;
+const I_BIT: u8 = 0x80;
+const L_BIT: u8 = 0x40;
+const T_BIT: u8 = 0x20;
+const K_BIT: u8 = 0x10;
+const KEY_IDX_MASK: u8 = 0x1f;
+
+/// A temporal layer, as it's sent in the payload descriptor.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct TemporalLayer {
+    /// 0 to 3.
+    pub id: u8,
+    /// Whether this frame only depends on base layer frames, so the decoder
+    /// can switch up to this layer.
+    pub sync: bool,
+}
+
+/// The payload descriptor at the start of every packet.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Descriptor {
+    /// The frame isn't used as a reference, so it can be dropped.
+    pub non_reference: bool,
+    /// The packet starts a partition.
+    pub start_of_partition: bool,
+    /// 0 to 7. Partitions after the 8th all use 7.
+    pub partition_index: u8,
+    pub picture_id: Option<PictureId>,
+    /// A running index of the base temporal layer's frames.
+    pub tl0_pic_idx: Option<u8>,
+    pub temporal_layer: Option<TemporalLayer>,
+    /// A running index of the keyframes, 0 to 31.
+    pub key_idx: Option<u8>,
+}
+impl Descriptor {
+    fn has_extension(&self) -> bool {
+        self.picture_id.is_some() || self.tl0_pic_idx.is_some() ||
+            self.temporal_layer.is_some() || self.key_idx.is_some()
+    }
+
+    pub fn encoded_len(&self) -> usize {
+        let mut len = 1;
+        if self.has_extension() {
+            len += 1;
+        }
+        if let Some(id) = self.picture_id {
+            len += id.len();
+        }
+        if self.tl0_pic_idx.is_some() {
+            len += 1;
+        }
+        if self.temporal_layer.is_some() || self.key_idx.is_some() {
+            len += 1;
+        }
+        len
+    }
+
+    pub fn write(&self, buf: &mut Vec<u8>) {
+        let mut b = self.partition_index & PID_MASK;
+        if self.has_extension() { b |= X_BIT; }
+        if self.non_reference { b |= N_BIT; }
+        if self.start_of_partition { b |= S_BIT; }
+        buf.push(b);
+        if !self.has_extension() {
+            return;
+        }
+
+        let mut x = 0;
+        if self.picture_id.is_some() { x |= I_BIT; }
+        if self.tl0_pic_idx.is_some() { x |= L_BIT; }
+        if self.temporal_layer.is_some() { x |= T_BIT; }
+        if self.key_idx.is_some() { x |= K_BIT; }
+        buf.push(x);
+        if let Some(id) = self.picture_id {
+            id.write(buf);
+        }
+        if let Some(idx) = self.tl0_pic_idx {
+            buf.push(idx);
+        }
+        if self.temporal_layer.is_some() || self.key_idx.is_some() {
+            let mut b = self.key_idx.unwrap_or(0) & KEY_IDX_MASK;
+            if let Some(layer) = self.temporal_layer {
+                b |= (layer.id & 0x3) << 6;
+                if layer.sync { b |= 0x20; }
+            }
+            buf.push(b);
+        }
+    }
+
+    /// Parse the descriptor at the start of `data`, returning it and its
+    /// length.
+    pub fn parse(data: &[u8]) -> Result<(Descriptor, usize), Error> {
+        let b = match data.first() {
+            Some(&b) => b,
+            None => return Err(truncated()),
+        };
+        let mut desc = Descriptor {
+            non_reference: b & N_BIT != 0,
+            start_of_partition: b & S_BIT != 0,
+            partition_index: b & PID_MASK,
+            picture_id: None,
+            tl0_pic_idx: None,
+            temporal_layer: None,
+            key_idx: None,
+        };
+        let mut pos = 1;
+        if b & X_BIT == 0 {
+            return Ok((desc, pos));
+        }
+
+        let x = match data.get(pos) {
+            Some(&x) => x,
+            None => return Err(truncated()),
+        };
+        pos += 1;
+        if x & I_BIT != 0 {
+            let id = try!(PictureId::parse(&data[pos..]));
+            pos += id.len();
+            desc.picture_id = Some(id);
+        }
+        if x & L_BIT != 0 {
+            desc.tl0_pic_idx = Some(*try!(data.get(pos).ok_or_else(truncated)));
+            pos += 1;
+        }
+        if x & (T_BIT | K_BIT) != 0 {
+            let b = *try!(data.get(pos).ok_or_else(truncated));
+            pos += 1;
+            if x & T_BIT != 0 {
+                desc.temporal_layer = Some(TemporalLayer {
+                    id: b >> 6,
+                    sync: b & 0x20 != 0,
+                });
+            }
+            if x & K_BIT != 0 {
+                desc.key_idx = Some(b & KEY_IDX_MASK);
+            }
+        }
+        Ok((desc, pos))
+    }
+}
+
+/// Splits frames into packets of at most `mtu` bytes of payload.
+///
+/// With `VPX_CODEC_USE_OUTPUT_PARTITION`, give it each partition as the
+/// encoder outputs it; partitions are never put in the same packet.
+pub struct Packetizer {
+    mtu: usize,
+    sequence: u16,
+    picture_id: Option<PictureId>,
+    tl0_pic_idx: u8,
+    key_idx: Option<u8>,
+    /// Whether the last partition given wasn't the end of its frame.
+    in_frame: bool,
+}
+impl Packetizer {
+    /// `sequence` is the sequence number of the first packet. Picture IDs
+    /// are 15 bits, starting at 0.
+    pub fn new(mtu: usize, sequence: u16) -> Packetizer {
+        Packetizer {
+            mtu: mtu,
+            sequence: sequence,
+            picture_id: Some(PictureId::Long(0)),
+            tl0_pic_idx: 0xff,
+            key_idx: None,
+            in_frame: false,
+        }
+    }
+
+    /// The picture ID of the first frame, or `None` to not send them.
+    pub fn picture_id(mut self, picture_id: Option<PictureId>) -> Packetizer {
+        self.picture_id = picture_id;
+        self
+    }
+    /// Send a running index of keyframes.
+    pub fn key_idx(mut self, key_idx: bool) -> Packetizer {
+        self.key_idx = if key_idx { Some(KEY_IDX_MASK) } else { None };
+        self
+    }
+
+    pub fn next_sequence(&self) -> u16 { self.sequence }
+
+    /// `timestamp` is the RTP timestamp; see `rtp::timestamp`. `layer` is
+    /// the frame's temporal layer, if the encoder uses them, in which case
+    /// `TL0PICIDX` is sent too.
+    pub fn packetize<'a>(&mut self, frame: &EncodedFrame<'a>, timestamp: u32,
+                         layer: Option<TemporalLayer>) -> Result<Vec<Packet>, Error> {
+        if !self.in_frame {
+            if layer.map(|l| l.id == 0).unwrap_or(false) {
+                self.tl0_pic_idx = self.tl0_pic_idx.wrapping_add(1);
+            }
+            if frame.is_keyframe() {
+                self.key_idx = self.key_idx.map(|idx| idx.wrapping_add(1) & KEY_IDX_MASK);
+            }
+        }
+
+        let mut desc = Descriptor {
+            non_reference: frame.is_droppable(),
+            start_of_partition: true,
+            partition_index: if frame.partition_id > 0 {
+                ::std::cmp::min(frame.partition_id, PID_MASK as i32) as u8
+            } else {
+                0
+            },
+            picture_id: self.picture_id,
+            tl0_pic_idx: layer.map(|_| self.tl0_pic_idx),
+            temporal_layer: layer,
+            key_idx: self.key_idx,
+        };
+        if self.mtu <= desc.encoded_len() {
+            return Err(Error::new(ErrorKind::InvalidParam,
+                                  "MTU is too small for the payload descriptor".to_string()));
+        }
+
+        let data = frame.data();
+        let sizes = split_sizes(data.len(), self.mtu - desc.encoded_len());
+        let mut packets = Vec::with_capacity(sizes.len());
+        let mut pos = 0;
+        for (i, &size) in sizes.iter().enumerate() {
+            let mut payload = Vec::with_capacity(desc.encoded_len() + size);
+            desc.write(&mut payload);
+            payload.extend_from_slice(&data[pos..pos + size]);
+            pos += size;
+            desc.start_of_partition = false;
+
+            packets.push(Packet {
+                sequence: self.sequence,
+                timestamp: timestamp,
+                marker: i == sizes.len() - 1 && !frame.is_fragment(),
+                payload: payload,
+            });
+            self.sequence = self.sequence.wrapping_add(1);
+        }
+
+        self.in_frame = frame.is_fragment();
+        if !self.in_frame {
+            self.picture_id = self.picture_id.map(|id| id.next());
+        }
+        Ok(packets)
+    }
+}
+
+/// A frame put back together from its packets, ready for `Decoder::decode`.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Frame {
+    pub timestamp: u32,
+    pub keyframe: bool,
+    pub non_reference: bool,
+    pub picture_id: Option<PictureId>,
+    pub tl0_pic_idx: Option<u8>,
+    pub temporal_layer: Option<TemporalLayer>,
+    /// Packets were lost, or frames dropped, since the previous frame
+    /// returned, so this frame may reference one the decoder doesn't have.
+    pub after_loss: bool,
+    /// The picture IDs of the frames dropped since the previous frame which
+    /// were known to be non-reference frames, ie had a packet received.
+    pub lost_non_reference: Vec<PictureId>,
+    pub data: Vec<u8>,
+}
+
+/// Puts frames back together from packets, which have to be given in
+/// sequence number order. Frames missing any packets are dropped.
+pub struct Depacketizer {
+    last_sequence: Option<u16>,
+    /// The frame being put together.
+    frame: Option<Frame>,
+    after_loss: bool,
+    lost_non_reference: Vec<PictureId>,
+    packets_lost: u64,
+}
+impl Depacketizer {
+    pub fn new() -> Depacketizer {
+        Depacketizer {
+            last_sequence: None,
+            frame: None,
+            after_loss: false,
+            lost_non_reference: Vec::new(),
+            packets_lost: 0,
+        }
+    }
+
+    /// The number of packets missing from the sequence numbers so far.
+    pub fn packets_lost(&self) -> u64 { self.packets_lost }
+
+    /// Drop the frame being put together, eg after the caller gives up on a
+    /// missing packet.
+    pub fn reset(&mut self) {
+        if let Some(frame) = self.frame.take() {
+            if frame.non_reference {
+                // No other frame needs it, which the jitter buffer can tell
+                // by its picture ID.
+                if let Some(id) = frame.picture_id {
+                    self.lost_non_reference.push(id);
+                }
+            }
+            self.after_loss = true;
+        }
+    }
+
+    /// Returns the frame `packet` completes, if any. Duplicate and late
+    /// packets are ignored.
+    pub fn push(&mut self, packet: &Packet) -> Result<Option<Frame>, Error> {
+        if let Some(last) = self.last_sequence {
+            let diff = sequence_diff(packet.sequence, last);
+            if diff <= 0 {
+                return Ok(None);
+            }
+            if diff > 1 {
+                self.packets_lost += diff as u64 - 1;
+                self.reset();
+                self.after_loss = true;
+            }
+        }
+        self.last_sequence = Some(packet.sequence);
+
+        let (desc, len) = match Descriptor::parse(&packet.payload[..]) {
+            Ok(v) => v,
+            Err(e) => {
+                self.reset();
+                self.after_loss = true;
+                return Err(e);
+            },
+        };
+        let data = &packet.payload[len..];
+
+        if self.frame.as_ref().map(|f| f.timestamp != packet.timestamp).unwrap_or(false) {
+            // The last packet of the frame never came.
+            self.reset();
+        }
+        if self.frame.is_none() {
+            if !desc.start_of_partition || desc.partition_index != 0 {
+                // The start of this frame is gone.
+                self.after_loss = true;
+                return Ok(None);
+            }
+            let keyframe = match header::parse_tag(data) {
+                Ok(tag) => tag.keyframe,
+                Err(_) => data.first().map(|b| b & 1 == 0).unwrap_or(false),
+            };
+            self.frame = Some(Frame {
+                timestamp: packet.timestamp,
+                keyframe: keyframe,
+                non_reference: desc.non_reference,
+                picture_id: desc.picture_id,
+                tl0_pic_idx: desc.tl0_pic_idx,
+                temporal_layer: desc.temporal_layer,
+                after_loss: false,
+                lost_non_reference: Vec::new(),
+                data: Vec::new(),
+            });
+        }
+        if let Some(ref mut frame) = self.frame {
+            frame.data.extend_from_slice(data);
+        }
+
+        if !packet.marker {
+            return Ok(None);
+        }
+        let mut frame = self.frame.take().unwrap();
+        frame.after_loss = self.after_loss;
+        frame.lost_non_reference = ::std::mem::replace(&mut self.lost_non_reference, Vec::new());
+        self.after_loss = false;
+        Ok(Some(frame))
+    }
+}
+impl Default for Depacketizer {
+    fn default() -> Depacketizer { Depacketizer::new() }
+}
+impl Depacketize for Depacketizer {
+    type Frame = Frame;
+    fn push(&mut self, packet: &Packet) -> Result<Option<Frame>, Error> {
+        Depacketizer::push(self, packet)
+    }
+    fn references(frame: &Frame) -> References {
+        References {
+            keyframe: frame.keyframe,
+            after_loss: frame.after_loss,
+            non_reference: frame.non_reference,
+            picture_id: frame.picture_id,
+            tl0_pic_idx: frame.tl0_pic_idx,
+            temporal_id: frame.temporal_layer.map(|l| l.id),
+            layer_sync: frame.temporal_layer.map(|l| l.sync).unwrap_or(false),
+            references: None,
+            lost_non_reference: frame.lost_non_reference.iter().map(|id| id.value()).collect(),
+        }
+    }
+}
@@ -0,0 +1,656 @@
+//! The VP9 payload format of RFC 9628.
+//!
+//! Each packet carries part of one spatial layer's frame, so a superframe
+//! holding several spatial layers is split into its frames, and the
+//! depacketizer puts them back into a superframe for the decoder.
+
+use {Error, ErrorKind, Frame as EncodedFrame};
+use vp9::{header, superframe};
+use super::{sequence_diff, split_sizes, truncated, Packet, PictureId};
+use super::jitter::{Depacketize, References};
+
+const I_BIT: u8 = 0x80;
+const P_BIT: u8 = 0x40;
+const L_BIT: u8 = 0x20;
+const F_BIT: u8 = 0x10;
+const B_BIT: u8 = 0x08;
+const E_BIT: u8 = 0x04;
+const V_BIT: u8 = 0x02;
+const Z_BIT: u8 = 0x01;
+/// The `N` bit after each `P_DIFF`, set if another one follows.
+const N_BIT: u8 = 0x01;
+const Y_BIT: u8 = 0x10;
+const G_BIT: u8 = 0x08;
+/// A frame can have at most 3 references.
+pub const MAX_REFS: usize = 3;
+
+/// The layer of one spatial layer's frame.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct LayerIndices {
+    /// 0 to 7.
+    pub temporal_id: u8,
+    /// Whether the decoder can switch up to this temporal layer here.
+    pub switching_up_point: bool,
+    /// 0 to 7.
+    pub spatial_id: u8,
+    /// Whether the frame depends on the lower spatial layer of the same
+    /// picture.
+    pub inter_layer_dependency: bool,
+}
+impl LayerIndices {
+    fn to_byte(self) -> u8 {
+        let mut b = (self.temporal_id & 0x7) << 5 | (self.spatial_id & 0x7) << 1;
+        if self.switching_up_point { b |= 0x10; }
+        if self.inter_layer_dependency { b |= 0x01; }
+        b
+    }
+    fn from_byte(b: u8) -> LayerIndices {
+        LayerIndices {
+            temporal_id: b >> 5,
+            switching_up_point: b & 0x10 != 0,
+            spatial_id: b >> 1 & 0x7,
+            inter_layer_dependency: b & 0x01 != 0,
+        }
+    }
+}
+
+/// One spatial layer's frame of a picture.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct LayerFrame {
+    pub layer: LayerIndices,
+    /// In flexible mode, how many pictures back, by picture ID, each of the
+    /// frame's references is. At most `MAX_REFS`.
+    pub p_diffs: Vec<u8>,
+}
+
+/// One entry of the picture group of the scalability structure, which
+/// describes the references of non-flexible mode's repeating pattern.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct PictureGroupEntry {
+    pub temporal_id: u8,
+    pub switching_up_point: bool,
+    pub p_diffs: Vec<u8>,
+}
+
+/// Describes the layers of the stream, and is sent with keyframes.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct ScalabilityStructure {
+    /// 1 to 8.
+    pub spatial_layers: u8,
+    /// The size of each spatial layer, if they're sent.
+    pub resolutions: Option<Vec<(u16, u16)>>,
+    pub picture_group: Option<Vec<PictureGroupEntry>>,
+}
+impl ScalabilityStructure {
+    fn encoded_len(&self) -> usize {
+        let mut len = 1;
+        if let Some(ref r) = self.resolutions {
+            len += 4 * r.len();
+        }
+        if let Some(ref group) = self.picture_group {
+            len += 1;
+            for entry in group.iter() {
+                len += 1 + entry.p_diffs.len();
+            }
+        }
+        len
+    }
+    fn write(&self, buf: &mut Vec<u8>) {
+        let mut b = (self.spatial_layers.wrapping_sub(1) & 0x7) << 5;
+        if self.resolutions.is_some() { b |= Y_BIT; }
+        if self.picture_group.is_some() { b |= G_BIT; }
+        buf.push(b);
+        if let Some(ref r) = self.resolutions {
+            for &(w, h) in r.iter() {
+                buf.extend_from_slice(&[(w >> 8) as u8, w as u8, (h >> 8) as u8, h as u8]);
+            }
+        }
+        if let Some(ref group) = self.picture_group {
+            buf.push(group.len() as u8);
+            for entry in group.iter() {
+                let mut b = (entry.temporal_id & 0x7) << 5 |
+                    (entry.p_diffs.len() as u8 & 0x3) << 2;
+                if entry.switching_up_point { b |= 0x10; }
+                buf.push(b);
+                buf.extend_from_slice(&entry.p_diffs[..]);
+            }
+        }
+    }
+    fn parse(data: &[u8]) -> Result<(ScalabilityStructure, usize), Error> {
+        let b = *try!(data.first().ok_or_else(truncated));
+        let mut pos = 1;
+        let spatial_layers = (b >> 5) + 1;
+        let resolutions = if b & Y_BIT != 0 {
+            let len = 4 * spatial_layers as usize;
+            if data.len() < pos + len {
+                return Err(truncated());
+            }
+            let r = data[pos..pos + len].chunks(4).map(|c| {
+                ((c[0] as u16) << 8 | c[1] as u16, (c[2] as u16) << 8 | c[3] as u16)
+            }).collect();
+            pos += len;
+            Some(r)
+        } else {
+            None
+        };
+        let picture_group = if b & G_BIT != 0 {
+            let count = *try!(data.get(pos).ok_or_else(truncated));
+            pos += 1;
+            let mut group = Vec::with_capacity(count as usize);
+            for _ in 0..count {
+                let b = *try!(data.get(pos).ok_or_else(truncated));
+                pos += 1;
+                let refs = (b >> 2 & 0x3) as usize;
+                if data.len() < pos + refs {
+                    return Err(truncated());
+                }
+                group.push(PictureGroupEntry {
+                    temporal_id: b >> 5,
+                    switching_up_point: b & 0x10 != 0,
+                    p_diffs: data[pos..pos + refs].to_vec(),
+                });
+                pos += refs;
+            }
+            Some(group)
+        } else {
+            None
+        };
+        Ok((ScalabilityStructure {
+            spatial_layers: spatial_layers,
+            resolutions: resolutions,
+            picture_group: picture_group,
+        }, pos))
+    }
+}
+
+/// The payload descriptor at the start of every packet.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Descriptor {
+    pub picture_id: Option<PictureId>,
+    /// The frame references earlier pictures.
+    pub inter_picture_predicted: bool,
+    pub layer: Option<LayerIndices>,
+    /// Flexible mode: references are given by `p_diffs` rather than the
+    /// scalability structure's picture group.
+    pub flexible: bool,
+    /// The packet starts a spatial layer's frame.
+    pub start_of_frame: bool,
+    /// The packet ends a spatial layer's frame.
+    pub end_of_frame: bool,
+    /// Higher spatial layers don't depend on this frame.
+    pub not_upper_reference: bool,
+    /// A running index of the base temporal layer's pictures. Only sent in
+    /// non-flexible mode, along with `layer`.
+    pub tl0_pic_idx: Option<u8>,
+    /// Only in flexible mode.
+    pub p_diffs: Vec<u8>,
+    pub scalability_structure: Option<ScalabilityStructure>,
+}
+impl Descriptor {
+    pub fn encoded_len(&self) -> usize {
+        let mut len = 1;
+        if let Some(id) = self.picture_id {
+            len += id.len();
+        }
+        if self.layer.is_some() {
+            len += 1;
+            if !self.flexible {
+                len += 1;
+            }
+        }
+        if self.flexible && self.inter_picture_predicted {
+            len += self.p_diffs.len();
+        }
+        if let Some(ref ss) = self.scalability_structure {
+            len += ss.encoded_len();
+        }
+        len
+    }
+
+    pub fn write(&self, buf: &mut Vec<u8>) {
+        let mut b = 0;
+        if self.picture_id.is_some() { b |= I_BIT; }
+        if self.inter_picture_predicted { b |= P_BIT; }
+        if self.layer.is_some() { b |= L_BIT; }
+        if self.flexible { b |= F_BIT; }
+        if self.start_of_frame { b |= B_BIT; }
+        if self.end_of_frame { b |= E_BIT; }
+        if self.scalability_structure.is_some() { b |= V_BIT; }
+        if self.not_upper_reference { b |= Z_BIT; }
+        buf.push(b);
+        if let Some(id) = self.picture_id {
+            id.write(buf);
+        }
+        if let Some(layer) = self.layer {
+            buf.push(layer.to_byte());
+            if !self.flexible {
+                buf.push(self.tl0_pic_idx.unwrap_or(0));
+            }
+        }
+        if self.flexible && self.inter_picture_predicted {
+            for (i, &diff) in self.p_diffs.iter().enumerate() {
+                let more = if i + 1 < self.p_diffs.len() { N_BIT } else { 0 };
+                buf.push(diff << 1 | more);
+            }
+        }
+        if let Some(ref ss) = self.scalability_structure {
+            ss.write(buf);
+        }
+    }
+
+    /// Parse the descriptor at the start of `data`, returning it and its
+    /// length.
+    pub fn parse(data: &[u8]) -> Result<(Descriptor, usize), Error> {
+        let b = *try!(data.first().ok_or_else(truncated));
+        let mut desc = Descriptor {
+            picture_id: None,
+            inter_picture_predicted: b & P_BIT != 0,
+            layer: None,
+            flexible: b & F_BIT != 0,
+            start_of_frame: b & B_BIT != 0,
+            end_of_frame: b & E_BIT != 0,
+            not_upper_reference: b & Z_BIT != 0,
+            tl0_pic_idx: None,
+            p_diffs: Vec::new(),
+            scalability_structure: None,
+        };
+        let mut pos = 1;
+        if b & I_BIT != 0 {
+            let id = try!(PictureId::parse(&data[pos..]));
+            pos += id.len();
+            desc.picture_id = Some(id);
+        }
+        if b & L_BIT != 0 {
+            let l = *try!(data.get(pos).ok_or_else(truncated));
+            pos += 1;
+            desc.layer = Some(LayerIndices::from_byte(l));
+            if !desc.flexible {
+                desc.tl0_pic_idx = Some(*try!(data.get(pos).ok_or_else(truncated)));
+                pos += 1;
+            }
+        }
+        if desc.flexible && desc.inter_picture_predicted {
+            loop {
+                let d = *try!(data.get(pos).ok_or_else(truncated));
+                pos += 1;
+                if desc.p_diffs.len() == MAX_REFS {
+                    return Err(Error::new(ErrorKind::CorruptFrame,
+                                          "too many references".to_string()));
+                }
+                desc.p_diffs.push(d >> 1);
+                if d & N_BIT == 0 {
+                    break;
+                }
+            }
+        }
+        if b & V_BIT != 0 {
+            let (ss, len) = try!(ScalabilityStructure::parse(&data[pos..]));
+            pos += len;
+            desc.scalability_structure = Some(ss);
+        }
+        Ok((desc, pos))
+    }
+}
+
+/// Splits pictures into packets of at most `mtu` bytes of payload.
+pub struct Packetizer {
+    mtu: usize,
+    sequence: u16,
+    picture_id: Option<PictureId>,
+    flexible: bool,
+    scalability_structure: Option<ScalabilityStructure>,
+    tl0_pic_idx: u8,
+    /// The timestamp of the last picture, whose spatial layers may be given
+    /// separately.
+    last_timestamp: Option<u32>,
+}
+impl Packetizer {
+    /// `sequence` is the sequence number of the first packet. Picture IDs
+    /// are 15 bits, starting at 0.
+    pub fn new(mtu: usize, sequence: u16) -> Packetizer {
+        Packetizer {
+            mtu: mtu,
+            sequence: sequence,
+            picture_id: Some(PictureId::Long(0)),
+            flexible: false,
+            scalability_structure: None,
+            tl0_pic_idx: 0xff,
+            last_timestamp: None,
+        }
+    }
+
+    /// The picture ID of the first picture, or `None` to not send them,
+    /// which isn't allowed in flexible mode.
+    pub fn picture_id(mut self, picture_id: Option<PictureId>) -> Packetizer {
+        self.picture_id = picture_id;
+        self
+    }
+    /// Send each frame's references, from `LayerFrame::p_diffs`, rather
+    /// than a picture group in the scalability structure.
+    pub fn flexible(mut self, flexible: bool) -> Packetizer {
+        self.flexible = flexible;
+        self
+    }
+    /// Sent with each keyframe. Its number of spatial layers also decides
+    /// which layer's frame ends a picture, so spatial layers can only be
+    /// given to `packetize` separately with one.
+    pub fn scalability_structure(mut self, ss: Option<ScalabilityStructure>) -> Packetizer {
+        self.scalability_structure = ss;
+        self
+    }
+
+    pub fn next_sequence(&self) -> u16 { self.sequence }
+
+    /// Packetize `frame`, which may be a superframe with a spatial layer in
+    /// each of its frames, or a single spatial layer's frame. `layers` has
+    /// the layer of each, or is empty if the stream has no layers, in which
+    /// case a superframe is sent as is. Without a scalability structure,
+    /// `frame` has to be the whole picture.
+    ///
+    /// `timestamp` is the RTP timestamp; see `rtp::timestamp`. The spatial
+    /// layers of one picture all have the same timestamp.
+    pub fn packetize<'a>(&mut self, frame: &EncodedFrame<'a>, timestamp: u32,
+                         layers: &[LayerFrame]) -> Result<Vec<Packet>, Error> {
+        if self.scalability_structure.is_none() &&
+            layers.first().map(|l| l.layer.spatial_id != 0).unwrap_or(false) {
+            return Err(Error::new(ErrorKind::InvalidParam,
+                                  "spatial layers given separately need a scalability structure"
+                                  .to_string()));
+        }
+        let data = frame.data();
+        let frames = if layers.len() > 1 {
+            let frames = try!(superframe::split(data));
+            if frames.len() != layers.len() {
+                return Err(Error::new(ErrorKind::InvalidParam,
+                                      "superframe doesn't have a frame per layer".to_string()));
+            }
+            frames
+        } else {
+            vec![data]
+        };
+        if self.flexible {
+            if self.picture_id.is_none() {
+                return Err(Error::new(ErrorKind::InvalidParam,
+                                      "flexible mode needs picture IDs".to_string()));
+            }
+            // Otherwise inter frames would go out without the references
+            // their descriptors say follow.
+            if layers.len() != frames.len() {
+                return Err(Error::new(ErrorKind::InvalidParam,
+                                      "flexible mode needs the layer of each frame".to_string()));
+            }
+            if layers.iter().any(|l| l.p_diffs.len() > MAX_REFS) {
+                return Err(Error::new(ErrorKind::InvalidParam,
+                                      "a frame can have at most 3 references".to_string()));
+            }
+        }
+        let intra: Vec<bool> = frames.iter().map(|data| {
+            frame.is_keyframe() || header::parse(data).map(|h| h.is_intra()).unwrap_or(false)
+        }).collect();
+        if self.flexible && intra.iter().zip(layers.iter()).any(|(&i, l)| !i && l.p_diffs.is_empty()) {
+            return Err(Error::new(ErrorKind::InvalidParam,
+                                  "flexible mode needs the references of inter frames".to_string()));
+        }
+
+        if self.last_timestamp.map(|t| t != timestamp).unwrap_or(false) {
+            self.picture_id = self.picture_id.map(|id| id.next());
+        }
+        if self.last_timestamp != Some(timestamp) &&
+            layers.first().map(|l| l.layer.temporal_id == 0).unwrap_or(false) {
+            self.tl0_pic_idx = self.tl0_pic_idx.wrapping_add(1);
+        }
+        self.last_timestamp = Some(timestamp);
+
+        let ends_picture = match self.scalability_structure {
+            Some(ref ss) => {
+                layers.last()
+                    .map(|l| l.layer.spatial_id + 1 >= ss.spatial_layers)
+                    .unwrap_or(true)
+            },
+            // The whole picture.
+            None => true,
+        };
+
+        let mut packets = Vec::new();
+        for (i, data) in frames.iter().enumerate() {
+            let layer = layers.get(i);
+            let p_diffs = layer.map(|l| l.p_diffs.clone()).unwrap_or_else(Vec::new);
+            let first_layer = layer.map(|l| l.layer.spatial_id == 0).unwrap_or(true);
+
+            let mut desc = Descriptor {
+                picture_id: self.picture_id,
+                inter_picture_predicted: !intra[i],
+                layer: layer.map(|l| l.layer),
+                flexible: self.flexible,
+                start_of_frame: true,
+                end_of_frame: false,
+                not_upper_reference: false,
+                tl0_pic_idx: layer.map(|_| self.tl0_pic_idx),
+                p_diffs: p_diffs,
+                scalability_structure: if frame.is_keyframe() && first_layer {
+                    self.scalability_structure.clone()
+                } else {
+                    None
+                },
+            };
+            if self.mtu <= desc.encoded_len() {
+                return Err(Error::new(ErrorKind::InvalidParam,
+                                      "MTU is too small for the payload descriptor".to_string()));
+            }
+
+            let sizes = split_sizes(data.len(), self.mtu - desc.encoded_len());
+            let mut pos = 0;
+            for (j, &size) in sizes.iter().enumerate() {
+                let last = j == sizes.len() - 1;
+                desc.end_of_frame = last;
+                let mut payload = Vec::with_capacity(desc.encoded_len() + size);
+                desc.write(&mut payload);
+                payload.extend_from_slice(&data[pos..pos + size]);
+                pos += size;
+                desc.start_of_frame = false;
+                desc.scalability_structure = None;
+
+                packets.push(Packet {
+                    sequence: self.sequence,
+                    timestamp: timestamp,
+                    marker: last && i == frames.len() - 1 && ends_picture,
+                    payload: payload,
+                });
+                self.sequence = self.sequence.wrapping_add(1);
+            }
+        }
+        Ok(packets)
+    }
+}
+
+/// A picture put back together from its packets, ready for
+/// `Decoder::decode`.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Frame {
+    pub timestamp: u32,
+    pub keyframe: bool,
+    /// Whether the first spatial layer references earlier pictures.
+    pub inter_picture_predicted: bool,
+    pub picture_id: Option<PictureId>,
+    pub tl0_pic_idx: Option<u8>,
+    /// The layer of each frame in `data`, if the stream has layers.
+    pub layers: Vec<LayerFrame>,
+    pub scalability_structure: Option<ScalabilityStructure>,
+    /// Packets were lost, or pictures dropped, since the previous picture
+    /// returned, so this one may reference one the decoder doesn't have.
+    pub after_loss: bool,
+    /// A superframe if the picture has more than one spatial layer.
+    pub data: Vec<u8>,
+}
+
+/// Puts pictures back together from packets, which have to be given in
+/// sequence number order. Pictures missing any packets are dropped.
+pub struct Depacketizer {
+    last_sequence: Option<u16>,
+    /// The picture being put together, and the frame of each spatial layer.
+    picture: Option<(Frame, Vec<Vec<u8>>)>,
+    /// Whether the last packet ended a spatial layer's frame.
+    end_of_frame: bool,
+    after_loss: bool,
+    packets_lost: u64,
+}
+impl Depacketizer {
+    pub fn new() -> Depacketizer {
+        Depacketizer {
+            last_sequence: None,
+            picture: None,
+            end_of_frame: true,
+            after_loss: false,
+            packets_lost: 0,
+        }
+    }
+
+    /// The number of packets missing from the sequence numbers so far.
+    pub fn packets_lost(&self) -> u64 { self.packets_lost }
+
+    /// Drop the picture being put together, eg after the caller gives up on
+    /// a missing packet.
+    pub fn reset(&mut self) {
+        if self.picture.take().is_some() {
+            self.after_loss = true;
+        }
+        self.end_of_frame = true;
+    }
+
+    /// Returns the picture `packet` completes, if any. Duplicate and late
+    /// packets are ignored.
+    pub fn push(&mut self, packet: &Packet) -> Result<Option<Frame>, Error> {
+        if let Some(last) = self.last_sequence {
+            let diff = sequence_diff(packet.sequence, last);
+            if diff <= 0 {
+                return Ok(None);
+            }
+            if diff > 1 {
+                self.packets_lost += diff as u64 - 1;
+                self.reset();
+                self.after_loss = true;
+            }
+        }
+        self.last_sequence = Some(packet.sequence);
+
+        let (desc, len) = match Descriptor::parse(&packet.payload[..]) {
+            Ok(v) => v,
+            Err(e) => {
+                self.reset();
+                self.after_loss = true;
+                return Err(e);
+            },
+        };
+        let data = &packet.payload[len..];
+
+        if self.picture.as_ref().map(|p| p.0.timestamp != packet.timestamp).unwrap_or(false) {
+            // The last packet of the picture never came.
+            self.reset();
+        }
+        if desc.start_of_frame != self.end_of_frame {
+            // Either the start of this frame is gone, or the previous one
+            // didn't end.
+            self.reset();
+            self.after_loss = true;
+            return Ok(None);
+        }
+        if self.picture.is_none() {
+            if desc.layer.map(|l| l.spatial_id != 0).unwrap_or(false) {
+                // The base layer's frame is gone.
+                self.after_loss = true;
+                return Ok(None);
+            }
+            self.picture = Some((Frame {
+                timestamp: packet.timestamp,
+                keyframe: false,
+                inter_picture_predicted: desc.inter_picture_predicted,
+                picture_id: desc.picture_id,
+                tl0_pic_idx: desc.tl0_pic_idx,
+                layers: Vec::new(),
+                scalability_structure: None,
+                after_loss: false,
+                data: Vec::new(),
+            }, Vec::new()));
+        }
+        {
+            let &mut (ref mut picture, ref mut frames) = self.picture.as_mut().unwrap();
+            if desc.start_of_frame {
+                if let Some(layer) = desc.layer {
+                    picture.layers.push(LayerFrame {
+                        layer: layer,
+                        p_diffs: desc.p_diffs.clone(),
+                    });
+                }
+                frames.push(Vec::new());
+            }
+            if desc.scalability_structure.is_some() {
+                picture.scalability_structure = desc.scalability_structure.clone();
+            }
+            frames.last_mut().unwrap().extend_from_slice(data);
+        }
+        self.end_of_frame = desc.end_of_frame;
+
+        if !packet.marker {
+            return Ok(None);
+        }
+        if !self.end_of_frame {
+            self.reset();
+            return Ok(None);
+        }
+        let (mut picture, frames) = self.picture.take().unwrap();
+        picture.keyframe = header::parse(&frames[0][..])
+            .map(|h| h.is_keyframe())
+            .unwrap_or(!picture.inter_picture_predicted);
+        picture.data = if frames.len() == 1 {
+            frames.into_iter().next().unwrap()
+        } else {
+            let refs: Vec<&[u8]> = frames.iter().map(|f| &f[..]).collect();
+            match superframe::build(&refs[..]) {
+                Ok(data) => data,
+                Err(e) => {
+                    self.after_loss = true;
+                    return Err(e);
+                },
+            }
+        };
+        picture.after_loss = self.after_loss;
+        self.after_loss = false;
+        Ok(Some(picture))
+    }
+}
+impl Default for Depacketizer {
+    fn default() -> Depacketizer { Depacketizer::new() }
+}
+impl Depacketize for Depacketizer {
+    type Frame = Frame;
+    fn push(&mut self, packet: &Packet) -> Result<Option<Frame>, Error> {
+        Depacketizer::push(self, packet)
+    }
+    /// Only the base spatial layer is considered, since the upper ones can't
+    /// be decoded without it anyway.
+    fn references(frame: &Frame) -> References {
+        let base = frame.layers.first();
+        // Flexible mode gives the references of inter frames.
+        let references = match (frame.picture_id, base) {
+            _ if !frame.inter_picture_predicted => Some(Vec::new()),
+            (Some(id), Some(base)) if !base.p_diffs.is_empty() => {
+                let modulus = if let PictureId::Short(_) = id { 0x80 } else { 0x8000 };
+                Some(base.p_diffs.iter().map(|&diff| {
+                    ((id.value() as u32 + modulus - diff as u32) % modulus) as u16
+                }).collect())
+            },
+            _ => None,
+        };
+        References {
+            keyframe: frame.keyframe,
+            after_loss: frame.after_loss,
+            non_reference: false,
+            picture_id: frame.picture_id,
+            tl0_pic_idx: frame.tl0_pic_idx,
+            temporal_id: base.map(|l| l.layer.temporal_id),
+            layer_sync: base.map(|l| l.layer.switching_up_point).unwrap_or(false),
+            references: references,
+            lost_non_reference: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,373 @@
+//! The header at the start of each VP8 frame, as described in sections 9 and
+//! 19 of RFC 6386: a 3-byte frame tag, the start code and dimensions of
+//! keyframes, then the start of the first partition, which is bool coded.
+
+use {Error, ErrorKind};
+
+const TAG_SIZE: usize = 3;
+const START_CODE: [u8; 3] = [0x9d, 0x01, 0x2a];
+const KEYFRAME_HEADER_SIZE: usize = TAG_SIZE + 7;
+/// Quantizer and loop filter values are per segment.
+pub const MAX_SEGMENTS: usize = 4;
+/// The number of reference frame and mode loop filter deltas.
+pub const NUM_LF_DELTAS: usize = 4;
+
+fn corrupt(detail: &str) -> Error {
+    Error::new(ErrorKind::CorruptFrame, detail.to_string())
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct FrameTag {
+    pub keyframe: bool,
+    /// 0 to 3, picking the reconstruction and loop filters.
+    pub version: u8,
+    pub show_frame: bool,
+    /// The size of the first partition, which starts after the tag and, for
+    /// keyframes, the start code and dimensions.
+    pub first_part_size: u32,
+}
+impl FrameTag {
+    /// Where the first partition starts.
+    pub fn first_part_offset(&self) -> usize {
+        if self.keyframe { KEYFRAME_HEADER_SIZE } else { TAG_SIZE }
+    }
+
+    /// The 3 bytes of the tag, for rewriting it.
+    pub fn to_bytes(&self) -> [u8; 3] {
+        let v = (!self.keyframe) as u32 | (self.version as u32 & 0x7) << 1 |
+            (self.show_frame as u32) << 4 | (self.first_part_size & 0x7ffff) << 5;
+        [v as u8, (v >> 8) as u8, (v >> 16) as u8]
+    }
+}
+
+/// Parse just the frame tag.
+pub fn parse_tag(data: &[u8]) -> Result<FrameTag, Error> {
+    if data.len() < TAG_SIZE {
+        return Err(corrupt("frame is truncated"));
+    }
+    let v = data[0] as u32 | (data[1] as u32) << 8 | (data[2] as u32) << 16;
+    Ok(FrameTag {
+        keyframe: v & 1 == 0,
+        version: (v >> 1 & 0x7) as u8,
+        show_frame: v >> 4 & 1 != 0,
+        first_part_size: v >> 5,
+    })
+}
+
+/// The size of keyframes; every other frame has the size of the last
+/// keyframe.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Dimensions {
+    pub width: u16,
+    /// How the decoder should upscale the frame: 0 for not at all, 1 for
+    /// 5/4, 2 for 5/3 and 3 for 2.
+    pub horizontal_scale: u8,
+    pub height: u16,
+    pub vertical_scale: u8,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Segmentation {
+    pub update_map: bool,
+    pub update_data: bool,
+    /// Whether the values below replace the frame's values, rather than
+    /// being added to them.
+    pub absolute_values: bool,
+    /// Only present if `update_data` is.
+    pub quantizer: [Option<i8>; MAX_SEGMENTS],
+    pub loop_filter_level: [Option<i8>; MAX_SEGMENTS],
+    /// The probabilities for decoding the map, if `update_map`.
+    pub tree_probs: [Option<u8>; MAX_SEGMENTS - 1],
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum FilterType {
+    Normal,
+    Simple,
+}
+
+/// Deltas to the loop filter level, which are only present if they're
+/// updated.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct LoopFilterDeltas {
+    /// For the intra, last, golden and altref frames.
+    pub ref_frame: [Option<i8>; NUM_LF_DELTAS],
+    /// For the B_PRED, ZEROMV, NEARESTMV/NEARMV/NEWMV and SPLITMV modes.
+    pub mode: [Option<i8>; NUM_LF_DELTAS],
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Quantizers {
+    /// The base index, 0 to 127, which the deltas are relative to.
+    pub y_ac_qi: u8,
+    pub y_dc_delta: i8,
+    pub y2_dc_delta: i8,
+    pub y2_ac_delta: i8,
+    pub uv_dc_delta: i8,
+    pub uv_ac_delta: i8,
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Header {
+    pub tag: FrameTag,
+    /// Only present in keyframes.
+    pub dimensions: Option<Dimensions>,
+    /// 0 for YUV; 1 is reserved. Only present in keyframes.
+    pub color_space: u8,
+    /// Whether the decoder has to clamp reconstructed pixels. Only present in
+    /// keyframes.
+    pub clamping_required: bool,
+    pub segmentation: Option<Segmentation>,
+    pub filter_type: FilterType,
+    pub loop_filter_level: u8,
+    pub sharpness_level: u8,
+    /// `None` if loop filter adjustments are disabled.
+    pub loop_filter_deltas: Option<LoopFilterDeltas>,
+    /// 1, 2, 4 or 8.
+    pub token_partitions: u8,
+    pub quantizers: Quantizers,
+    /// False if the probabilities this frame updates are only used for it.
+    pub refresh_entropy_probs: bool,
+    /// Keyframes refresh all three references.
+    pub refresh_golden_frame: bool,
+    pub refresh_alternate_frame: bool,
+    pub refresh_last: bool,
+    /// 0 to not copy, 1 to copy the last frame and 2 to copy the altref (to
+    /// golden) or golden (to altref) frame.
+    pub copy_buffer_to_golden: u8,
+    pub copy_buffer_to_alternate: u8,
+    pub sign_bias_golden: bool,
+    pub sign_bias_alternate: bool,
+}
+
+/// The boolean entropy decoder of section 7.
+struct BoolDecoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    value: u32,
+    range: u32,
+    bit_count: u32,
+    /// How many bytes past the end of `data` were needed.
+    overrun: usize,
+}
+impl<'a> BoolDecoder<'a> {
+    fn new(data: &'a [u8]) -> BoolDecoder<'a> {
+        let mut d = BoolDecoder {
+            data: data,
+            pos: 0,
+            value: 0,
+            range: 255,
+            bit_count: 0,
+            overrun: 0,
+        };
+        d.value = (d.next_byte() as u32) << 8 | d.next_byte() as u32;
+        d
+    }
+    fn next_byte(&mut self) -> u8 {
+        match self.data.get(self.pos) {
+            Some(&b) => {
+                self.pos += 1;
+                b
+            },
+            None => {
+                self.overrun += 1;
+                0
+            },
+        }
+    }
+
+    fn read_bool(&mut self, prob: u8) -> bool {
+        let split = 1 + (((self.range - 1) * prob as u32) >> 8);
+        let big_split = split << 8;
+        let bit = if self.value >= big_split {
+            self.range -= split;
+            self.value -= big_split;
+            true
+        } else {
+            self.range = split;
+            false
+        };
+        while self.range < 128 {
+            self.value <<= 1;
+            self.range <<= 1;
+            self.bit_count += 1;
+            if self.bit_count == 8 {
+                self.bit_count = 0;
+                self.value |= self.next_byte() as u32;
+            }
+        }
+        bit
+    }
+    /// `L(1)`.
+    fn read_flag(&mut self) -> bool { self.read_bool(128) }
+    /// `L(n)`.
+    fn read_literal(&mut self, n: u32) -> u32 {
+        let mut v = 0;
+        for _ in 0..n {
+            v = v << 1 | self.read_flag() as u32;
+        }
+        v
+    }
+    /// A magnitude of `n` bits then a sign bit.
+    fn read_signed(&mut self, n: u32) -> i8 {
+        let v = self.read_literal(n) as i8;
+        if self.read_flag() { -v } else { v }
+    }
+    /// A flag, then the signed value if it's set.
+    fn read_optional_signed(&mut self, n: u32) -> Option<i8> {
+        if self.read_flag() { Some(self.read_signed(n)) } else { None }
+    }
+}
+
+/// Parse the frame header at the start of `data`, which needs to hold the
+/// frame tag and enough of the first partition for the header fields.
+pub fn parse(data: &[u8]) -> Result<Header, Error> {
+    let tag = try!(parse_tag(data));
+
+    let dimensions = if tag.keyframe {
+        if data.len() < KEYFRAME_HEADER_SIZE {
+            return Err(corrupt("keyframe header is truncated"));
+        }
+        if data[TAG_SIZE..TAG_SIZE + 3] != START_CODE {
+            return Err(corrupt("invalid keyframe start code"));
+        }
+        let width = data[6] as u16 | (data[7] as u16) << 8;
+        let height = data[8] as u16 | (data[9] as u16) << 8;
+        Some(Dimensions {
+            width: width & 0x3fff,
+            horizontal_scale: (width >> 14) as u8,
+            height: height & 0x3fff,
+            vertical_scale: (height >> 14) as u8,
+        })
+    } else {
+        None
+    };
+
+    let first_part = &data[tag.first_part_offset()..];
+    let first_part = if first_part.len() > tag.first_part_size as usize {
+        &first_part[..tag.first_part_size as usize]
+    } else {
+        first_part
+    };
+    let mut d = BoolDecoder::new(first_part);
+
+    let (color_space, clamping_required) = if tag.keyframe {
+        let color_space = d.read_flag() as u8;
+        (color_space, !d.read_flag())
+    } else {
+        (0, true)
+    };
+
+    let segmentation = if d.read_flag() {
+        let update_map = d.read_flag();
+        let update_data = d.read_flag();
+        let mut segmentation = Segmentation {
+            update_map: update_map,
+            update_data: update_data,
+            absolute_values: false,
+            quantizer: [None; MAX_SEGMENTS],
+            loop_filter_level: [None; MAX_SEGMENTS],
+            tree_probs: [None; MAX_SEGMENTS - 1],
+        };
+        if update_data {
+            segmentation.absolute_values = d.read_flag();
+            for q in segmentation.quantizer.iter_mut() {
+                *q = d.read_optional_signed(7);
+            }
+            for lf in segmentation.loop_filter_level.iter_mut() {
+                *lf = d.read_optional_signed(6);
+            }
+        }
+        if update_map {
+            for prob in segmentation.tree_probs.iter_mut() {
+                if d.read_flag() {
+                    *prob = Some(d.read_literal(8) as u8);
+                }
+            }
+        }
+        Some(segmentation)
+    } else {
+        None
+    };
+
+    let filter_type = if d.read_flag() { FilterType::Simple } else { FilterType::Normal };
+    let loop_filter_level = d.read_literal(6) as u8;
+    let sharpness_level = d.read_literal(3) as u8;
+    let loop_filter_deltas = if d.read_flag() {
+        let mut deltas = LoopFilterDeltas {
+            ref_frame: [None; NUM_LF_DELTAS],
+            mode: [None; NUM_LF_DELTAS],
+        };
+        if d.read_flag() {
+            for delta in deltas.ref_frame.iter_mut() {
+                *delta = d.read_optional_signed(6);
+            }
+            for delta in deltas.mode.iter_mut() {
+                *delta = d.read_optional_signed(6);
+            }
+        }
+        Some(deltas)
+    } else {
+        None
+    };
+
+    let token_partitions = 1 << d.read_literal(2);
+
+    let y_ac_qi = d.read_literal(7) as u8;
+    let mut deltas = [0i8; 5];
+    for delta in deltas.iter_mut() {
+        *delta = d.read_optional_signed(4).unwrap_or(0);
+    }
+    let quantizers = Quantizers {
+        y_ac_qi: y_ac_qi,
+        y_dc_delta: deltas[0],
+        y2_dc_delta: deltas[1],
+        y2_ac_delta: deltas[2],
+        uv_dc_delta: deltas[3],
+        uv_ac_delta: deltas[4],
+    };
+
+    let mut h = Header {
+        tag: tag,
+        dimensions: dimensions,
+        color_space: color_space,
+        clamping_required: clamping_required,
+        segmentation: segmentation,
+        filter_type: filter_type,
+        loop_filter_level: loop_filter_level,
+        sharpness_level: sharpness_level,
+        loop_filter_deltas: loop_filter_deltas,
+        token_partitions: token_partitions,
+        quantizers: quantizers,
+        refresh_entropy_probs: true,
+        refresh_golden_frame: true,
+        refresh_alternate_frame: true,
+        refresh_last: true,
+        copy_buffer_to_golden: 0,
+        copy_buffer_to_alternate: 0,
+        sign_bias_golden: false,
+        sign_bias_alternate: false,
+    };
+    if tag.keyframe {
+        h.refresh_entropy_probs = d.read_flag();
+    } else {
+        h.refresh_golden_frame = d.read_flag();
+        h.refresh_alternate_frame = d.read_flag();
+        if !h.refresh_golden_frame {
+            h.copy_buffer_to_golden = d.read_literal(2) as u8;
+        }
+        if !h.refresh_alternate_frame {
+            h.copy_buffer_to_alternate = d.read_literal(2) as u8;
+        }
+        h.sign_bias_golden = d.read_flag();
+        h.sign_bias_alternate = d.read_flag();
+        h.refresh_entropy_probs = d.read_flag();
+        h.refresh_last = d.read_flag();
+    }
+
+    // The decoder reads ahead by up to 2 bytes.
+    if d.overrun > 2 {
+        return Err(corrupt("first partition is truncated"));
+    }
+    Ok(h)
+}
@@ -0,0 +1,4 @@
+//! Parsing of the VP8 bitstream, for code which needs to look inside frames
+//! without decoding them.
+
+pub mod header;
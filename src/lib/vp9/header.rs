@@ -0,0 +1,315 @@
+//! The uncompressed header at the start of each VP9 frame, as described in
+//! section 6.2 of the VP9 bitstream specification.
+//!
+//! Fields which depend on earlier frames, like the size of a frame which
+//! copies it from a reference, can't be known from one frame alone and are
+//! left as `None`.
+
+use {BitDepth, ColorSpace, Error, ErrorKind};
+use bits::BitReader;
+use convert::Range;
+use super::superframe;
+
+use std::convert::TryFrom;
+
+const FRAME_MARKER: u32 = 2;
+const SYNC_CODE: u32 = 0x498342;
+/// `color_space` value of RGB, which libvpx calls sRGB.
+const CS_RGB: u32 = 7;
+/// The number of reference frames each inter frame uses.
+pub const REFS_PER_FRAME: usize = 3;
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum FrameType {
+    Key,
+    /// Includes intra only frames, see `Header::intra_only`.
+    Inter,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct ColorConfig {
+    pub bit_depth: BitDepth,
+    pub color_space: ColorSpace,
+    pub range: Range,
+    pub subsampling_x: bool,
+    pub subsampling_y: bool,
+}
+impl Default for ColorConfig {
+    /// What profile 0 intra only frames use.
+    fn default() -> ColorConfig {
+        ColorConfig {
+            bit_depth: BitDepth::Eight,
+            color_space: ColorSpace::BT601,
+            range: Range::Limited,
+            subsampling_x: true,
+            subsampling_y: true,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum InterpolationFilter {
+    EightTapSmooth,
+    EightTap,
+    EightTapSharp,
+    Bilinear,
+    /// Chosen per block.
+    Switchable,
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Header {
+    pub profile: u8,
+    /// If set, this frame just shows the reference frame at this index, and
+    /// all other fields are left at their defaults.
+    pub show_existing_frame: Option<u8>,
+    pub frame_type: FrameType,
+    pub show_frame: bool,
+    pub error_resilient_mode: bool,
+    pub intra_only: bool,
+    pub reset_frame_context: u8,
+    /// Only present in keyframes and intra only frames.
+    pub color_config: Option<ColorConfig>,
+    /// `None` if the frame uses the size of a reference frame, see
+    /// `size_from_ref`.
+    pub frame_size: Option<(u32, u32)>,
+    /// The index into `ref_frame_idx` of the reference whose size this frame
+    /// has.
+    pub size_from_ref: Option<usize>,
+    /// The size the frame is meant to be displayed at. `None` if it's the
+    /// same as a reference frame's size.
+    pub render_size: Option<(u32, u32)>,
+    /// A bit for each of the 8 reference slots this frame is stored in.
+    pub refresh_frame_flags: u8,
+    /// The reference slots used for the last, golden and altref frames.
+    pub ref_frame_idx: [u8; REFS_PER_FRAME],
+    pub ref_frame_sign_bias: [bool; REFS_PER_FRAME],
+    pub allow_high_precision_mv: bool,
+    pub interpolation_filter: Option<InterpolationFilter>,
+    pub refresh_frame_context: bool,
+    pub frame_parallel_decoding_mode: bool,
+    pub frame_context_idx: u8,
+    pub loop_filter_level: u8,
+    pub sharpness_level: u8,
+    pub base_q_idx: u8,
+}
+impl Default for Header {
+    fn default() -> Header {
+        Header {
+            profile: 0,
+            show_existing_frame: None,
+            frame_type: FrameType::Inter,
+            show_frame: true,
+            error_resilient_mode: false,
+            intra_only: false,
+            reset_frame_context: 0,
+            color_config: None,
+            frame_size: None,
+            size_from_ref: None,
+            render_size: None,
+            refresh_frame_flags: 0,
+            ref_frame_idx: [0; REFS_PER_FRAME],
+            ref_frame_sign_bias: [false; REFS_PER_FRAME],
+            allow_high_precision_mv: false,
+            interpolation_filter: None,
+            refresh_frame_context: false,
+            frame_parallel_decoding_mode: false,
+            frame_context_idx: 0,
+            loop_filter_level: 0,
+            sharpness_level: 0,
+            base_q_idx: 0,
+        }
+    }
+}
+impl Header {
+    pub fn is_keyframe(&self) -> bool { self.frame_type == FrameType::Key }
+    /// Whether the frame can be decoded without any reference frames.
+    pub fn is_intra(&self) -> bool { self.is_keyframe() || self.intra_only }
+}
+
+fn corrupt(detail: &str) -> Error {
+    Error::new(ErrorKind::CorruptFrame, detail.to_string())
+}
+
+/// Parse the header of the frame at the start of `data`. For a superframe,
+/// that's the first frame; use `parse_all` to get the rest.
+pub fn parse(data: &[u8]) -> Result<Header, Error> {
+    let mut r = BitReader::new(data);
+    let mut h: Header = Default::default();
+
+    if try!(r.read_bits(2)) != FRAME_MARKER {
+        return Err(corrupt("invalid frame marker"));
+    }
+    let low = try!(r.read_bit()) as u8;
+    let high = try!(r.read_bit()) as u8;
+    h.profile = high << 1 | low;
+    if h.profile == 3 && try!(r.read_bit()) {
+        return Err(Error::new(ErrorKind::UnsupportedBitstream,
+                              "unsupported profile".to_string()));
+    }
+
+    if try!(r.read_bit()) {
+        h.show_existing_frame = Some(try!(r.read_bits(3)) as u8);
+        return Ok(h);
+    }
+
+    h.frame_type = if try!(r.read_bit()) { FrameType::Inter } else { FrameType::Key };
+    h.show_frame = try!(r.read_bit());
+    h.error_resilient_mode = try!(r.read_bit());
+
+    if h.frame_type == FrameType::Key {
+        try!(read_sync_code(&mut r));
+        h.color_config = Some(try!(read_color_config(&mut r, h.profile)));
+        h.frame_size = Some(try!(read_size(&mut r)));
+        h.render_size = try!(read_render_size(&mut r)).or(h.frame_size);
+        h.refresh_frame_flags = 0xff;
+    } else {
+        if !h.show_frame {
+            h.intra_only = try!(r.read_bit());
+        }
+        if !h.error_resilient_mode {
+            h.reset_frame_context = try!(r.read_bits(2)) as u8;
+        }
+
+        if h.intra_only {
+            try!(read_sync_code(&mut r));
+            h.color_config = if h.profile > 0 {
+                Some(try!(read_color_config(&mut r, h.profile)))
+            } else {
+                Some(Default::default())
+            };
+            h.refresh_frame_flags = try!(r.read_bits(8)) as u8;
+            h.frame_size = Some(try!(read_size(&mut r)));
+            h.render_size = try!(read_render_size(&mut r)).or(h.frame_size);
+        } else {
+            h.refresh_frame_flags = try!(r.read_bits(8)) as u8;
+            for i in 0..REFS_PER_FRAME {
+                h.ref_frame_idx[i] = try!(r.read_bits(3)) as u8;
+                h.ref_frame_sign_bias[i] = try!(r.read_bit());
+            }
+
+            for i in 0..REFS_PER_FRAME {
+                if try!(r.read_bit()) {
+                    h.size_from_ref = Some(i);
+                    break;
+                }
+            }
+            if h.size_from_ref.is_none() {
+                h.frame_size = Some(try!(read_size(&mut r)));
+            }
+            h.render_size = try!(read_render_size(&mut r)).or(h.frame_size);
+
+            h.allow_high_precision_mv = try!(r.read_bit());
+            h.interpolation_filter = Some(if try!(r.read_bit()) {
+                InterpolationFilter::Switchable
+            } else {
+                match try!(r.read_bits(2)) {
+                    0 => InterpolationFilter::EightTapSmooth,
+                    1 => InterpolationFilter::EightTap,
+                    2 => InterpolationFilter::EightTapSharp,
+                    _ => InterpolationFilter::Bilinear,
+                }
+            });
+        }
+    }
+
+    if !h.error_resilient_mode {
+        h.refresh_frame_context = try!(r.read_bit());
+        h.frame_parallel_decoding_mode = try!(r.read_bit());
+    } else {
+        h.frame_parallel_decoding_mode = true;
+    }
+    h.frame_context_idx = try!(r.read_bits(2)) as u8;
+
+    // loop_filter_params()
+    h.loop_filter_level = try!(r.read_bits(6)) as u8;
+    h.sharpness_level = try!(r.read_bits(3)) as u8;
+    // The reference and mode deltas.
+    if try!(r.read_bit()) && try!(r.read_bit()) {
+        for _ in 0..4 + 2 {
+            if try!(r.read_bit()) {
+                try!(r.read_signed(6));
+            }
+        }
+    }
+
+    // quantization_params()
+    h.base_q_idx = try!(r.read_bits(8)) as u8;
+
+    Ok(h)
+}
+
+/// Parse the headers of each frame in `data`, which may be a superframe.
+pub fn parse_all(data: &[u8]) -> Result<Vec<Header>, Error> {
+    let frames = try!(superframe::split(data));
+    let mut headers = Vec::with_capacity(frames.len());
+    for frame in frames {
+        headers.push(try!(parse(frame)));
+    }
+    Ok(headers)
+}
+
+fn read_sync_code(r: &mut BitReader) -> Result<(), Error> {
+    if try!(r.read_bits(24)) != SYNC_CODE {
+        Err(corrupt("invalid frame sync code"))
+    } else {
+        Ok(())
+    }
+}
+
+fn read_color_config(r: &mut BitReader, profile: u8) -> Result<ColorConfig, Error> {
+    let bit_depth = if profile >= 2 {
+        if try!(r.read_bit()) { BitDepth::Twelve } else { BitDepth::Ten }
+    } else {
+        BitDepth::Eight
+    };
+
+    let cs = try!(r.read_bits(3));
+    let color_space = try!(ColorSpace::try_from(cs));
+    let (range, subsampling_x, subsampling_y) = if cs != CS_RGB {
+        let range = if try!(r.read_bit()) { Range::Full } else { Range::Limited };
+        if profile == 1 || profile == 3 {
+            let x = try!(r.read_bit());
+            let y = try!(r.read_bit());
+            if try!(r.read_bit()) {
+                return Err(corrupt("reserved bit set"));
+            }
+            (range, x, y)
+        } else {
+            (range, true, true)
+        }
+    } else {
+        if profile == 1 || profile == 3 {
+            if try!(r.read_bit()) {
+                return Err(corrupt("reserved bit set"));
+            }
+        } else {
+            return Err(Error::new(ErrorKind::UnsupportedBitstream,
+                                  "4:4:4 color not supported in profile 0 or 2"
+                                  .to_string()));
+        }
+        (Range::Full, false, false)
+    };
+
+    Ok(ColorConfig {
+        bit_depth: bit_depth,
+        color_space: color_space,
+        range: range,
+        subsampling_x: subsampling_x,
+        subsampling_y: subsampling_y,
+    })
+}
+
+fn read_size(r: &mut BitReader) -> Result<(u32, u32), Error> {
+    let width = try!(r.read_bits(16)) + 1;
+    let height = try!(r.read_bits(16)) + 1;
+    Ok((width, height))
+}
+/// `None` if the render size is the frame size.
+fn read_render_size(r: &mut BitReader) -> Result<Option<(u32, u32)>, Error> {
+    if try!(r.read_bit()) {
+        read_size(r).map(Some)
+    } else {
+        Ok(None)
+    }
+}
@@ -0,0 +1,5 @@
+//! Parsing of the VP9 bitstream, for code which needs to look inside frames
+//! without decoding them.
+
+pub mod header;
+pub mod superframe;
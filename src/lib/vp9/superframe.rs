@@ -0,0 +1,114 @@
+//! VP9 superframes: several frames packed into one, followed by an index of
+//! their sizes. libvpx uses them to output an invisible altref frame along
+//! with the next shown frame, so each packet still has one shown frame.
+//!
+//! The index is a marker byte, the sizes in little endian, and the marker
+//! byte again. The marker is `0b110mmfff`, with `mm + 1` bytes per size and
+//! `fff + 1` frames.
+
+use {Error, ErrorKind};
+
+/// A superframe holds at most 8 frames.
+pub const MAX_FRAMES: usize = 8;
+
+const MARKER_MASK: u8 = 0xe0;
+const MARKER: u8 = 0xc0;
+
+fn corrupt(detail: &str) -> Error {
+    Error::new(ErrorKind::CorruptFrame, detail.to_string())
+}
+
+/// The size of the index for `frames` frames with sizes of `size_bytes`
+/// bytes each.
+fn index_len(frames: usize, size_bytes: usize) -> usize {
+    2 + size_bytes * frames
+}
+
+/// Parse the superframe index at the end of `data`, returning the size of
+/// each frame. `Ok(None)` if `data` isn't a superframe, and an error if it
+/// has an index that doesn't fit.
+pub fn parse_index(data: &[u8]) -> Result<Option<Vec<usize>>, Error> {
+    let marker = match data.last() {
+        Some(&marker) if marker & MARKER_MASK == MARKER => marker,
+        _ => return Ok(None),
+    };
+    let frames = (marker & 0x7) as usize + 1;
+    let size_bytes = ((marker >> 3) & 0x3) as usize + 1;
+    let index_len = index_len(frames, size_bytes);
+    // Without the matching marker at the start, the last byte is just frame
+    // data.
+    if data.len() < index_len || data[data.len() - index_len] != marker {
+        return Ok(None);
+    }
+
+    let index = &data[data.len() - index_len + 1..data.len() - 1];
+    let sizes: Vec<usize> = index.chunks(size_bytes)
+        .map(|size| {
+            size.iter().rev().fold(0, |v, &b| v << 8 | b as usize)
+        })
+        .collect();
+
+    let total = sizes.iter().fold(0, |total, &size| total + size);
+    if total > data.len() - index_len {
+        return Err(corrupt("superframe index sizes exceed the data"));
+    }
+    Ok(Some(sizes))
+}
+
+/// Split `data` into its frames. A frame which isn't a superframe is
+/// returned as is.
+pub fn split(data: &[u8]) -> Result<Vec<&[u8]>, Error> {
+    let sizes = match try!(parse_index(data)) {
+        Some(sizes) => sizes,
+        None => return Ok(vec![data]),
+    };
+
+    let mut frames = Vec::with_capacity(sizes.len());
+    let mut pos = 0;
+    for size in sizes {
+        frames.push(&data[pos..pos + size]);
+        pos += size;
+    }
+    Ok(frames)
+}
+
+/// Pack `frames` into a superframe. There must be 1 to 8 frames, each at
+/// most 4GB, and none of them may be a superframe itself.
+pub fn build(frames: &[&[u8]]) -> Result<Vec<u8>, Error> {
+    if frames.is_empty() || frames.len() > MAX_FRAMES {
+        return Err(Error::new(ErrorKind::InvalidParam,
+                              format!("superframes hold 1 to {} frames, not {}",
+                                      MAX_FRAMES, frames.len())));
+    }
+
+    let max = frames.iter().fold(0, |max, frame| ::std::cmp::max(max, frame.len()));
+    if max as u64 > 0xffffffff {
+        return Err(Error::new(ErrorKind::InvalidParam,
+                              "superframe frames are limited to 4GB".to_string()));
+    }
+    let mut size_bytes = 1;
+    while size_bytes < 4 && max >> (8 * size_bytes) != 0 {
+        size_bytes += 1;
+    }
+    for frame in frames.iter() {
+        if try!(parse_index(frame)).is_some() {
+            return Err(Error::new(ErrorKind::InvalidParam,
+                                  "superframes can't be nested".to_string()));
+        }
+    }
+
+    let total = frames.iter().fold(0, |total, frame| total + frame.len());
+    let marker = MARKER | ((size_bytes - 1) << 3) as u8 | (frames.len() - 1) as u8;
+    let mut out = Vec::with_capacity(total + index_len(frames.len(), size_bytes));
+    for frame in frames.iter() {
+        out.extend_from_slice(frame);
+    }
+    out.push(marker);
+    for frame in frames.iter() {
+        for i in 0..size_bytes {
+            out.push((frame.len() >> (8 * i)) as u8);
+        }
+    }
+    out.push(marker);
+    Ok(out)
+}
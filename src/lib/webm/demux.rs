@@ -0,0 +1,438 @@
+use Codec;
+use super::ebml;
+use super::*;
+
+use std::collections::VecDeque;
+use std::io::{self, Read};
+
+/// The default `TimecodeScale`, for files which don't set it.
+const DEFAULT_TIMECODE_SCALE: u64 = 1_000_000;
+
+/// A VP8 or VP9 track. Tracks in other codecs are skipped.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct VideoTrack {
+    pub number: u64,
+    pub codec: Codec,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A compressed frame, ready for `Decoder::decode`.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Frame {
+    /// The `number` of the frame's `VideoTrack`.
+    pub track: u64,
+    /// In nanoseconds. Blocks are relative to their cluster, so this may be
+    /// negative.
+    pub timestamp: i64,
+    pub keyframe: bool,
+    pub invisible: bool,
+    pub data: Vec<u8>,
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Reads the VP8 and VP9 frames of a WebM or Matroska file, in file order.
+/// Only needs `Read`, so it works on streams too.
+pub struct Demuxer<R: Read> {
+    input: R,
+    /// The number of bytes read so far.
+    pos: u64,
+    segment_end: Option<u64>,
+    /// `Some` while in a cluster, with its end if the size is known.
+    cluster_end: Option<Option<u64>>,
+    cluster_timecode: u64,
+    timecode_scale: u64,
+    tracks: Vec<VideoTrack>,
+    /// The rest of a block with several frames laced into it.
+    pending: VecDeque<Frame>,
+}
+impl<R: Read> Demuxer<R> {
+    /// Read up to and including the `Tracks`.
+    pub fn new(input: R) -> io::Result<Demuxer<R>> {
+        let mut demuxer = Demuxer {
+            input: input,
+            pos: 0,
+            segment_end: None,
+            cluster_end: None,
+            cluster_timecode: 0,
+            timecode_scale: DEFAULT_TIMECODE_SCALE,
+            tracks: Vec::new(),
+            pending: VecDeque::new(),
+        };
+
+        match try!(demuxer.read_header()) {
+            Some((EBML, Some(size))) => {
+                let header = try!(demuxer.read_data(size));
+                try!(check_doc_type(&header[..]));
+            },
+            _ => return Err(invalid_data("not an EBML file")),
+        }
+
+        loop {
+            match try!(demuxer.read_header()) {
+                Some((SEGMENT, size)) => {
+                    demuxer.segment_end = size.map(|size| demuxer.pos + size);
+                    break;
+                },
+                Some((id, size)) => try!(demuxer.skip_element(id, size)),
+                None => return Err(invalid_data("no segment")),
+            }
+        }
+
+        loop {
+            let (id, size) = match try!(demuxer.read_header()) {
+                Some(header) => header,
+                None => return Err(invalid_data("no tracks")),
+            };
+            match id {
+                TRACKS => {
+                    let data = try!(demuxer.read_element(id, size));
+                    try!(demuxer.parse_tracks(&data[..]));
+                    return Ok(demuxer);
+                },
+                CLUSTER => return Err(invalid_data("cluster before tracks")),
+                _ => try!(demuxer.read_segment_child(id, size)),
+            }
+        }
+    }
+
+    /// The VP8 and VP9 tracks.
+    pub fn tracks(&self) -> &[VideoTrack] { &self.tracks[..] }
+    pub fn into_inner(self) -> R { self.input }
+
+    fn read_header(&mut self) -> io::Result<Option<(u32, Option<u64>)>> {
+        let (id, id_len) = match try!(ebml::read_vint(&mut self.input, true)) {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+        let (size, size_len) = match try!(ebml::read_vint(&mut self.input, false)) {
+            Some(size) => size,
+            None => return Err(invalid_data("truncated element header")),
+        };
+        self.pos += (id_len + size_len) as u64;
+        let size = if ebml::is_unknown_size(size, size_len) {
+            None
+        } else {
+            Some(size)
+        };
+        Ok(Some((id as u32, size)))
+    }
+    fn read_data(&mut self, size: u64) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        let read = try!((&mut self.input).take(size).read_to_end(&mut data));
+        self.pos += read as u64;
+        if (read as u64) < size {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                      "truncated element"));
+        }
+        Ok(data)
+    }
+    fn read_element(&mut self, id: u32, size: Option<u64>) -> io::Result<Vec<u8>> {
+        match size {
+            Some(size) => self.read_data(size),
+            None => Err(invalid_data(&format!("element {:X} has an unknown size", id))),
+        }
+    }
+    fn skip_element(&mut self, id: u32, size: Option<u64>) -> io::Result<()> {
+        match size {
+            Some(size) => self.skip(size),
+            None => Err(invalid_data(&format!("element {:X} has an unknown size", id))),
+        }
+    }
+    fn skip(&mut self, size: u64) -> io::Result<()> {
+        let skipped = try!(io::copy(&mut (&mut self.input).take(size),
+                                    &mut io::sink()));
+        self.pos += skipped;
+        if skipped < size {
+            Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated element"))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Handle any child of the segment besides `Tracks`.
+    fn read_segment_child(&mut self, id: u32, size: Option<u64>) -> io::Result<()> {
+        match id {
+            CLUSTER => {
+                self.cluster_end = Some(size.map(|size| self.pos + size));
+                self.cluster_timecode = 0;
+            },
+            INFO => {
+                let data = try!(self.read_element(id, size));
+                for (id, data) in Children(&data[..]) {
+                    if id == TIMECODE_SCALE {
+                        self.timecode_scale = ebml::parse_uint(data);
+                    }
+                }
+            },
+            _ => try!(self.skip_element(id, size)),
+        }
+        Ok(())
+    }
+
+    fn parse_tracks(&mut self, data: &[u8]) -> io::Result<()> {
+        for (id, entry) in Children(data) {
+            if id != TRACK_ENTRY { continue; }
+
+            let mut number = None;
+            let mut track_type = None;
+            let mut codec = None;
+            let mut width = 0;
+            let mut height = 0;
+            for (id, data) in Children(entry) {
+                match id {
+                    TRACK_NUMBER => number = Some(ebml::parse_uint(data)),
+                    TRACK_TYPE => track_type = Some(ebml::parse_uint(data)),
+                    CODEC_ID => {
+                        codec = match data {
+                            b"V_VP8" => Some(Codec::VP8),
+                            b"V_VP9" => Some(Codec::VP9),
+                            _ => None,
+                        };
+                    },
+                    VIDEO => {
+                        for (id, data) in Children(data) {
+                            match id {
+                                PIXEL_WIDTH => width = ebml::parse_uint(data) as u32,
+                                PIXEL_HEIGHT => height = ebml::parse_uint(data) as u32,
+                                _ => {},
+                            }
+                        }
+                    },
+                    _ => {},
+                }
+            }
+
+            if let (Some(number), Some(TRACK_TYPE_VIDEO), Some(codec)) =
+                (number, track_type, codec)
+            {
+                self.tracks.push(VideoTrack {
+                    number: number,
+                    codec: codec,
+                    width: width,
+                    height: height,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn is_video_track(&self, number: u64) -> bool {
+        self.tracks.iter().any(|track| track.number == number)
+    }
+
+    /// Parse a `Block` or `SimpleBlock`. `keyframe` is only used for
+    /// `Block`s, whose flags don't have it.
+    fn parse_block(&mut self, data: &[u8], keyframe: Option<bool>) -> io::Result<()> {
+        let (track, len) = match ebml::parse_vint(data, false) {
+            Some(track) => track,
+            None => return Err(invalid_data("invalid block")),
+        };
+        if !self.is_video_track(track) { return Ok(()); }
+        let data = &data[len..];
+        if data.len() < 3 {
+            return Err(invalid_data("invalid block"));
+        }
+
+        let relative = ((data[0] as u16) << 8 | data[1] as u16) as i16;
+        let flags = data[2];
+        let timecode = self.cluster_timecode as i64 + relative as i64;
+        let timestamp = timecode * self.timecode_scale as i64;
+        let keyframe = keyframe.unwrap_or(flags & BLOCK_KEYFRAME != 0);
+        let invisible = flags & BLOCK_INVISIBLE != 0;
+
+        for frame in try!(unlace(flags, &data[3..])) {
+            self.pending.push_back(Frame {
+                track: track,
+                timestamp: timestamp,
+                keyframe: keyframe,
+                invisible: invisible,
+                data: frame.to_vec(),
+            });
+        }
+        Ok(())
+    }
+
+    /// `None` at the end of the segment.
+    pub fn read_frame(&mut self) -> io::Result<Option<Frame>> {
+        loop {
+            if let Some(frame) = self.pending.pop_front() {
+                return Ok(Some(frame));
+            }
+            if let Some(end) = self.segment_end {
+                if self.pos >= end { return Ok(None); }
+            }
+            if let Some(Some(end)) = self.cluster_end {
+                if self.pos >= end { self.cluster_end = None; }
+            }
+
+            let (id, size) = match try!(self.read_header()) {
+                Some(header) => header,
+                None => return Ok(None),
+            };
+
+            if self.cluster_end.is_some() {
+                match id {
+                    TIMECODE => {
+                        let data = try!(self.read_element(id, size));
+                        self.cluster_timecode = ebml::parse_uint(&data[..]);
+                        continue;
+                    },
+                    SIMPLE_BLOCK => {
+                        let data = try!(self.read_element(id, size));
+                        try!(self.parse_block(&data[..], None));
+                        continue;
+                    },
+                    BLOCK_GROUP => {
+                        let data = try!(self.read_element(id, size));
+                        let mut block = None;
+                        let mut keyframe = true;
+                        for (id, data) in Children(&data[..]) {
+                            match id {
+                                BLOCK => block = Some(data),
+                                // Only frames which aren't keyframes
+                                // reference others.
+                                REFERENCE_BLOCK => keyframe = false,
+                                _ => {},
+                            }
+                        }
+                        if let Some(block) = block {
+                            try!(self.parse_block(block, Some(keyframe)));
+                        }
+                        continue;
+                    },
+                    SEEK_HEAD | INFO | TRACKS | CLUSTER | CUES | CHAPTERS |
+                    TAGS | ATTACHMENTS if self.cluster_end == Some(None) => {
+                        // The end of a cluster of unknown size.
+                        self.cluster_end = None;
+                    },
+                    _ => {
+                        try!(self.skip_element(id, size));
+                        continue;
+                    },
+                }
+            }
+
+            try!(self.read_segment_child(id, size));
+        }
+    }
+}
+impl<R: Read> Iterator for Demuxer<R> {
+    type Item = io::Result<Frame>;
+    fn next(&mut self) -> Option<io::Result<Frame>> {
+        match self.read_frame() {
+            Ok(Some(frame)) => Some(Ok(frame)),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+fn check_doc_type(header: &[u8]) -> io::Result<()> {
+    for (id, data) in Children(header) {
+        if id == DOC_TYPE {
+            return match data {
+                b"webm" | b"matroska" => Ok(()),
+                _ => Err(invalid_data("not a WebM or Matroska file")),
+            };
+        }
+    }
+    // Matroska is the default.
+    Ok(())
+}
+
+/// Split the frames laced into a block.
+fn unlace(flags: u8, data: &[u8]) -> io::Result<Vec<&[u8]>> {
+    let lacing = (flags & BLOCK_LACING) >> 1;
+    if lacing == 0 {
+        return Ok(vec![data]);
+    }
+    let invalid = || invalid_data("invalid block lacing");
+
+    if data.is_empty() { return Err(invalid()); }
+    let count = data[0] as usize + 1;
+    let mut pos = 1;
+    let mut sizes = Vec::with_capacity(count);
+    match lacing {
+        // Xiph
+        1 => {
+            for _ in 0..count - 1 {
+                let mut size = 0;
+                loop {
+                    let b = match data.get(pos) {
+                        Some(&b) => b,
+                        None => return Err(invalid()),
+                    };
+                    pos += 1;
+                    size += b as usize;
+                    if b != 255 { break; }
+                }
+                sizes.push(size);
+            }
+        },
+        // Fixed size
+        2 => {
+            let rest = data.len() - pos;
+            if rest % count != 0 { return Err(invalid()); }
+            for _ in 0..count - 1 {
+                sizes.push(rest / count);
+            }
+        },
+        // EBML: the first size, then the differences between each size and
+        // the previous one.
+        _ => {
+            let mut size = 0i64;
+            for i in 0..count - 1 {
+                let (v, len) = match ebml::parse_vint(&data[pos..], false) {
+                    Some(v) => v,
+                    None => return Err(invalid()),
+                };
+                pos += len;
+                size = if i == 0 {
+                    v as i64
+                } else {
+                    size + v as i64 - ((1i64 << (7 * len - 1)) - 1)
+                };
+                if size < 0 { return Err(invalid()); }
+                sizes.push(size as usize);
+            }
+        },
+    }
+
+    let mut frames = Vec::with_capacity(count);
+    for size in sizes {
+        if pos + size > data.len() { return Err(invalid()); }
+        frames.push(&data[pos..pos + size]);
+        pos += size;
+    }
+    frames.push(&data[pos..]);
+    Ok(frames)
+}
+
+/// Iterates over the child elements in a master element's data. Stops at
+/// the first malformed one.
+struct Children<'a>(&'a [u8]);
+impl<'a> Iterator for Children<'a> {
+    type Item = (u32, &'a [u8]);
+    fn next(&mut self) -> Option<(u32, &'a [u8])> {
+        let (id, id_len) = match ebml::parse_vint(self.0, true) {
+            Some(id) => id,
+            None => return None,
+        };
+        let (size, size_len) = match ebml::parse_vint(&self.0[id_len..], false) {
+            Some(size) => size,
+            None => return None,
+        };
+        let start = id_len + size_len;
+        if size > (self.0.len() - start) as u64 {
+            return None;
+        }
+        let end = start + size as usize;
+        let data = &self.0[start..end];
+        self.0 = &self.0[end..];
+        Some((id as u32, data))
+    }
+}
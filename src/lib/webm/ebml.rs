@@ -0,0 +1,144 @@
+//! Encoding and decoding of EBML, the binary format WebM is built on. Every
+//! element is an ID and a size, both variable length integers, then the
+//! data.
+
+use std::io::{self, Read};
+
+/// A size whose length isn't known yet, for elements which are patched or
+/// left open ended.
+pub const UNKNOWN_SIZE: u64 = 0x00ff_ffff_ffff_ffff;
+
+/// The number of bytes needed to encode `size` as a variable length
+/// integer. All ones is reserved for the unknown size.
+pub fn size_len(size: u64) -> usize {
+    let mut len = 1;
+    while len < 8 && size >= (1 << (7 * len)) - 1 {
+        len += 1;
+    }
+    len
+}
+
+pub fn put_id(buf: &mut Vec<u8>, id: u32) {
+    // IDs keep their length marker, so the leading byte gives the length.
+    let len = if id >= 0x1000_0000 {
+        4
+    } else if id >= 0x20_0000 {
+        3
+    } else if id >= 0x4000 {
+        2
+    } else {
+        1
+    };
+    for i in (0..len).rev() {
+        buf.push((id >> (8 * i)) as u8);
+    }
+}
+/// Encode `size` in exactly `len` bytes.
+pub fn put_size_len(buf: &mut Vec<u8>, size: u64, len: usize) {
+    let marked = size | (1 << (7 * len));
+    for i in (0..len).rev() {
+        buf.push((marked >> (8 * i)) as u8);
+    }
+}
+pub fn put_size(buf: &mut Vec<u8>, size: u64) {
+    put_size_len(buf, size, size_len(size));
+}
+
+pub fn put_uint(buf: &mut Vec<u8>, id: u32, v: u64) {
+    let mut len = 1;
+    while len < 8 && v >> (8 * len) != 0 {
+        len += 1;
+    }
+    put_id(buf, id);
+    put_size(buf, len as u64);
+    for i in (0..len).rev() {
+        buf.push((v >> (8 * i)) as u8);
+    }
+}
+pub fn put_float(buf: &mut Vec<u8>, id: u32, v: f64) {
+    put_id(buf, id);
+    put_size(buf, 8);
+    let bits = v.to_bits();
+    for i in (0..8).rev() {
+        buf.push((bits >> (8 * i)) as u8);
+    }
+}
+pub fn put_bytes(buf: &mut Vec<u8>, id: u32, data: &[u8]) {
+    put_id(buf, id);
+    put_size(buf, data.len() as u64);
+    buf.extend_from_slice(data);
+}
+pub fn put_str(buf: &mut Vec<u8>, id: u32, s: &str) {
+    put_bytes(buf, id, s.as_bytes());
+}
+
+/// A `Void` element taking up exactly `len` bytes, which must be at least
+/// 2.
+pub fn put_void(buf: &mut Vec<u8>, len: usize) {
+    assert!(len >= 2);
+    put_id(buf, super::VOID);
+    // Pick the size length so the whole element is `len` bytes.
+    let mut size_bytes = 1;
+    while size_len((len - 1 - size_bytes) as u64) > size_bytes {
+        size_bytes += 1;
+    }
+    let size = len - 1 - size_bytes;
+    put_size_len(buf, size as u64, size_bytes);
+    buf.extend(::std::iter::repeat(0).take(size));
+}
+
+/// Parse a variable length integer from the start of `data`, returning it
+/// and its length. IDs keep their length marker, sizes don't. `None` if
+/// `data` is too short or the first byte is invalid.
+pub fn parse_vint(data: &[u8], keep_marker: bool) -> Option<(u64, usize)> {
+    let first = match data.first() {
+        Some(&first) if first != 0 => first,
+        _ => return None,
+    };
+    let len = first.leading_zeros() as usize + 1;
+    if data.len() < len { return None; }
+
+    let mut v = if keep_marker {
+        first as u64
+    } else {
+        first as u64 & (0xff >> len)
+    };
+    for &b in data[1..len].iter() {
+        v = v << 8 | b as u64;
+    }
+    Some((v, len))
+}
+/// Whether a size of `len` bytes is all ones, ie unknown.
+pub fn is_unknown_size(size: u64, len: usize) -> bool {
+    size == (1 << (7 * len)) - 1
+}
+
+/// Read a variable length integer, returning it and its length. `None` on
+/// a clean end of file.
+pub fn read_vint<R: Read>(input: &mut R,
+                          keep_marker: bool) -> io::Result<Option<(u64, usize)>> {
+    let mut buf = [0u8; 8];
+    if try!(input.read(&mut buf[..1])) == 0 {
+        return Ok(None);
+    }
+    if buf[0] == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                  "invalid EBML variable length integer"));
+    }
+    let len = buf[0].leading_zeros() as usize + 1;
+    try!(input.read_exact(&mut buf[1..len]));
+    Ok(parse_vint(&buf[..len], keep_marker))
+}
+
+pub fn parse_uint(data: &[u8]) -> u64 {
+    data.iter().fold(0, |v, &b| v << 8 | b as u64)
+}
+/// 0 for empty data, as EBML says.
+pub fn parse_float(data: &[u8]) -> Option<f64> {
+    match data.len() {
+        0 => Some(0.0),
+        4 => Some(f32::from_bits(parse_uint(data) as u32) as f64),
+        8 => Some(f64::from_bits(parse_uint(data))),
+        _ => None,
+    }
+}
@@ -0,0 +1,377 @@
+use ffi;
+use {BitDepth, Codec, ColorSpace, Frame};
+use convert::Range;
+use encoder::PacketWriter;
+use super::ebml;
+use super::*;
+
+use std::convert::TryFrom;
+use std::io::{self, Seek, SeekFrom, Write};
+
+/// Space left at the start of the segment for the `SeekHead`, which can
+/// only be written once we know where the `Cues` are.
+const SEEK_HEAD_RESERVE: usize = 80;
+/// Clusters are patched with an 8-byte size once they're closed.
+const CLUSTER_SIZE_LEN: usize = 8;
+const SEGMENT_SIZE_LEN: usize = 8;
+
+/// Describes the video track. Everything but the colour metadata is taken
+/// from the encoder's config.
+#[derive(Copy, Clone, Debug)]
+pub struct Track {
+    codec: Codec,
+    width: u32,
+    height: u32,
+    timebase_num: u64,
+    timebase_den: u64,
+    bit_depth: Option<BitDepth>,
+    color_space: ColorSpace,
+    range: Option<Range>,
+}
+impl Track {
+    pub fn new<C>(codec: Codec, cfg: &C) -> Track
+        where C: AsRef<ffi::vpx_codec_enc_cfg_t>,
+    {
+        let cfg = cfg.as_ref();
+        Track {
+            codec: codec,
+            width: cfg.g_w,
+            height: cfg.g_h,
+            timebase_num: cfg.g_timebase.num as u64,
+            timebase_den: cfg.g_timebase.den as u64,
+            bit_depth: BitDepth::try_from(cfg.g_bit_depth).ok(),
+            color_space: ColorSpace::Unknown,
+            range: None,
+        }
+    }
+
+    /// Write `Colour` metadata for `cs`. Nothing is written for `Unknown`
+    /// or `Reserved`.
+    pub fn color_space(mut self, cs: ColorSpace) -> Track {
+        self.color_space = cs;
+        self
+    }
+    pub fn range(mut self, range: Range) -> Track {
+        self.range = Some(range);
+        self
+    }
+
+    fn codec_id(&self) -> &'static str {
+        match self.codec {
+            Codec::VP8 => "V_VP8",
+            Codec::VP9 => "V_VP9",
+        }
+    }
+
+    /// The `MatrixCoefficients`, `TransferCharacteristics` and `Primaries`
+    /// values of ISO/IEC 23001-8 matching our color space.
+    fn colour(&self) -> Option<(u64, u64, u64)> {
+        match self.color_space {
+            ColorSpace::Unknown | ColorSpace::Reserved => None,
+            ColorSpace::BT601 => Some((5, 6, 5)),
+            ColorSpace::SMPTE170 => Some((6, 6, 6)),
+            ColorSpace::BT709 => Some((1, 1, 1)),
+            ColorSpace::SMPTE240 => Some((7, 7, 7)),
+            ColorSpace::BT2020 => {
+                let transfer = match self.bit_depth {
+                    Some(BitDepth::Twelve) => 15,
+                    _ => 14,
+                };
+                Some((9, transfer, 9))
+            },
+            ColorSpace::SRGB => Some((0, 13, 1)),
+        }
+    }
+
+    fn write(&self, buf: &mut Vec<u8>) {
+        let mut colour = Vec::new();
+        if let Some((matrix, transfer, primaries)) = self.colour() {
+            ebml::put_uint(&mut colour, MATRIX_COEFFICIENTS, matrix);
+            ebml::put_uint(&mut colour, TRANSFER_CHARACTERISTICS, transfer);
+            ebml::put_uint(&mut colour, PRIMARIES, primaries);
+        }
+        if let Some(depth) = self.bit_depth {
+            ebml::put_uint(&mut colour, BITS_PER_CHANNEL, depth.bits() as u64);
+        }
+        if let Some(range) = self.range {
+            let range = match range {
+                Range::Limited => 1,
+                Range::Full => 2,
+            };
+            ebml::put_uint(&mut colour, RANGE, range);
+        }
+
+        let mut video = Vec::new();
+        ebml::put_uint(&mut video, PIXEL_WIDTH, self.width as u64);
+        ebml::put_uint(&mut video, PIXEL_HEIGHT, self.height as u64);
+        if !colour.is_empty() {
+            ebml::put_bytes(&mut video, COLOUR, &colour[..]);
+        }
+
+        let mut entry = Vec::new();
+        ebml::put_uint(&mut entry, TRACK_NUMBER, 1);
+        ebml::put_uint(&mut entry, TRACK_UID, 1);
+        ebml::put_uint(&mut entry, TRACK_TYPE, TRACK_TYPE_VIDEO);
+        ebml::put_str(&mut entry, CODEC_ID, self.codec_id());
+        ebml::put_bytes(&mut entry, VIDEO, &video[..]);
+
+        let mut tracks = Vec::new();
+        ebml::put_bytes(&mut tracks, TRACK_ENTRY, &entry[..]);
+        ebml::put_bytes(buf, TRACKS, &tracks[..]);
+    }
+}
+
+struct Cluster {
+    /// Where the cluster's size goes.
+    size_pos: u64,
+    timecode: u64,
+}
+
+/// Writes the frames from `Encoder::packets` to a WebM file with a single
+/// video track. Call `finish` once done, to write the cues and fill in the
+/// sizes and duration.
+pub struct Muxer<W: Write + Seek> {
+    out: W,
+    track: Track,
+    /// Where the segment's data starts. Positions in the `SeekHead` and
+    /// `Cues` are relative to it.
+    segment_start: u64,
+    duration_pos: u64,
+    info_pos: u64,
+    tracks_pos: u64,
+    cluster: Option<Cluster>,
+    /// The timecode and position of each cluster starting with a keyframe.
+    cues: Vec<(u64, u64)>,
+    end_timecode: u64,
+    fragments: Vec<u8>,
+    fragments_frame: Option<(u64, u64, ffi::vpx_codec_frame_flags_t)>,
+}
+impl<W: Write + Seek> Muxer<W> {
+    /// Write everything up to the first cluster.
+    pub fn new(mut out: W, track: Track) -> io::Result<Muxer<W>> {
+        if track.timebase_num == 0 || track.timebase_den == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "the timebase must be positive"));
+        }
+
+        let start = try!(out.seek(SeekFrom::Current(0)));
+
+        let mut header = Vec::new();
+        ebml::put_uint(&mut header, EBML_VERSION, 1);
+        ebml::put_uint(&mut header, EBML_READ_VERSION, 1);
+        ebml::put_uint(&mut header, EBML_MAX_ID_LENGTH, 4);
+        ebml::put_uint(&mut header, EBML_MAX_SIZE_LENGTH, 8);
+        ebml::put_str(&mut header, DOC_TYPE, "webm");
+        ebml::put_uint(&mut header, DOC_TYPE_VERSION, 4);
+        ebml::put_uint(&mut header, DOC_TYPE_READ_VERSION, 2);
+
+        let mut buf = Vec::new();
+        ebml::put_bytes(&mut buf, EBML, &header[..]);
+        ebml::put_id(&mut buf, SEGMENT);
+        ebml::put_size_len(&mut buf, ebml::UNKNOWN_SIZE, SEGMENT_SIZE_LEN);
+        let segment_start = start + buf.len() as u64;
+        ebml::put_void(&mut buf, SEEK_HEAD_RESERVE);
+
+        let info_pos = start + buf.len() as u64 - segment_start;
+        let mut info = Vec::new();
+        ebml::put_uint(&mut info, TIMECODE_SCALE, TIMECODE_SCALE_NS);
+        ebml::put_float(&mut info, DURATION, 0.0);
+        let duration_offset = info.len() - 8;
+        ebml::put_str(&mut info, MUXING_APP, "rust-vpx");
+        ebml::put_str(&mut info, WRITING_APP, "rust-vpx");
+        ebml::put_bytes(&mut buf, INFO, &info[..]);
+        let duration_pos = start + buf.len() as u64 - (info.len() - duration_offset) as u64;
+
+        let tracks_pos = start + buf.len() as u64 - segment_start;
+        track.write(&mut buf);
+
+        try!(out.write_all(&buf[..]));
+        Ok(Muxer {
+            out: out,
+            track: track,
+            segment_start: segment_start,
+            duration_pos: duration_pos,
+            info_pos: info_pos,
+            tracks_pos: tracks_pos,
+            cluster: None,
+            cues: Vec::new(),
+            end_timecode: 0,
+            fragments: Vec::new(),
+            fragments_frame: None,
+        })
+    }
+
+    /// Convert from the encoder's timebase to milliseconds.
+    fn timecode(&self, pts: u64) -> u64 {
+        pts * self.track.timebase_num * 1000 / self.track.timebase_den
+    }
+
+    fn position(&mut self) -> io::Result<u64> {
+        self.out.seek(SeekFrom::Current(0))
+    }
+    fn patch(&mut self, pos: u64, data: &[u8]) -> io::Result<()> {
+        let end = try!(self.position());
+        try!(self.out.seek(SeekFrom::Start(pos)));
+        try!(self.out.write_all(data));
+        try!(self.out.seek(SeekFrom::Start(end)));
+        Ok(())
+    }
+
+    fn open_cluster(&mut self, timecode: u64, keyframe: bool) -> io::Result<()> {
+        try!(self.close_cluster());
+
+        let pos = try!(self.position());
+        if keyframe {
+            self.cues.push((timecode, pos - self.segment_start));
+        }
+        let mut buf = Vec::new();
+        ebml::put_id(&mut buf, CLUSTER);
+        let size_pos = pos + buf.len() as u64;
+        ebml::put_size_len(&mut buf, ebml::UNKNOWN_SIZE, CLUSTER_SIZE_LEN);
+        ebml::put_uint(&mut buf, TIMECODE, timecode);
+        try!(self.out.write_all(&buf[..]));
+
+        self.cluster = Some(Cluster {
+            size_pos: size_pos,
+            timecode: timecode,
+        });
+        Ok(())
+    }
+    fn close_cluster(&mut self) -> io::Result<()> {
+        if let Some(cluster) = self.cluster.take() {
+            let data_start = cluster.size_pos + CLUSTER_SIZE_LEN as u64;
+            let size = try!(self.position()) - data_start;
+            let mut buf = Vec::new();
+            ebml::put_size_len(&mut buf, size, CLUSTER_SIZE_LEN);
+            try!(self.patch(cluster.size_pos, &buf[..]));
+        }
+        Ok(())
+    }
+
+    /// Write a whole frame, ie not a partition, as a `SimpleBlock`.
+    pub fn write_raw_frame(&mut self, pts: u64, duration: u64,
+                           flags: ffi::vpx_codec_frame_flags_t,
+                           data: &[u8]) -> io::Result<()> {
+        let timecode = self.timecode(pts);
+        let keyframe = flags & ::FRAME_IS_KEY != 0;
+
+        let relative = match self.cluster {
+            Some(ref cluster) if !keyframe && timecode >= cluster.timecode &&
+                timecode - cluster.timecode <= i16::max_value() as u64 =>
+            {
+                Some((timecode - cluster.timecode) as i16)
+            },
+            _ => None,
+        };
+        let relative = match relative {
+            Some(relative) => relative,
+            None => {
+                try!(self.open_cluster(timecode, keyframe));
+                0
+            },
+        };
+
+        let mut block_flags = 0;
+        if keyframe { block_flags |= BLOCK_KEYFRAME; }
+        if flags & ::FRAME_IS_INVISIBLE != 0 { block_flags |= BLOCK_INVISIBLE; }
+        if flags & ::FRAME_IS_DROPPABLE != 0 { block_flags |= BLOCK_DISCARDABLE; }
+
+        let mut buf = Vec::with_capacity(16);
+        ebml::put_id(&mut buf, SIMPLE_BLOCK);
+        ebml::put_size(&mut buf, 4 + data.len() as u64);
+        // The track number, as a variable length integer.
+        buf.push(0x81);
+        buf.push((relative >> 8) as u8);
+        buf.push(relative as u8);
+        buf.push(block_flags);
+        try!(self.out.write_all(&buf[..]));
+        try!(self.out.write_all(data));
+
+        let end = self.timecode(pts + duration);
+        if end > self.end_timecode {
+            self.end_timecode = end;
+        }
+        Ok(())
+    }
+
+    /// Write the cues, fill in the sizes, duration and seek head, and return
+    /// the output.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some((pts, duration, flags)) = self.fragments_frame.take() {
+            let fragments = ::std::mem::replace(&mut self.fragments, Vec::new());
+            try!(self.write_raw_frame(pts, duration, flags, &fragments[..]));
+        }
+        try!(self.close_cluster());
+
+        let cues_pos = try!(self.position()) - self.segment_start;
+        if !self.cues.is_empty() {
+            let mut cues = Vec::new();
+            for &(timecode, pos) in self.cues.iter() {
+                let mut positions = Vec::new();
+                ebml::put_uint(&mut positions, CUE_TRACK, 1);
+                ebml::put_uint(&mut positions, CUE_CLUSTER_POSITION, pos);
+                let mut point = Vec::new();
+                ebml::put_uint(&mut point, CUE_TIME, timecode);
+                ebml::put_bytes(&mut point, CUE_TRACK_POSITIONS, &positions[..]);
+                ebml::put_bytes(&mut cues, CUE_POINT, &point[..]);
+            }
+            let mut buf = Vec::new();
+            ebml::put_bytes(&mut buf, CUES, &cues[..]);
+            try!(self.out.write_all(&buf[..]));
+        }
+
+        let segment_size = try!(self.position()) - self.segment_start;
+        let mut buf = Vec::new();
+        ebml::put_size_len(&mut buf, segment_size, SEGMENT_SIZE_LEN);
+        let segment_size_pos = self.segment_start - SEGMENT_SIZE_LEN as u64;
+        try!(self.patch(segment_size_pos, &buf[..]));
+
+        let duration = self.end_timecode as f64;
+        let mut buf = Vec::new();
+        ebml::put_float(&mut buf, DURATION, duration);
+        let duration_pos = self.duration_pos;
+        try!(self.patch(duration_pos, &buf[buf.len() - 8..]));
+
+        let mut entries = vec![(INFO, self.info_pos), (TRACKS, self.tracks_pos)];
+        if !self.cues.is_empty() {
+            entries.push((CUES, cues_pos));
+        }
+        let mut seek_head = Vec::new();
+        for &(id, pos) in entries.iter() {
+            let mut id_buf = Vec::new();
+            ebml::put_id(&mut id_buf, id);
+            let mut seek = Vec::new();
+            ebml::put_bytes(&mut seek, SEEK_ID, &id_buf[..]);
+            ebml::put_uint(&mut seek, SEEK_POSITION, pos);
+            ebml::put_bytes(&mut seek_head, SEEK, &seek[..]);
+        }
+        let mut buf = Vec::new();
+        ebml::put_bytes(&mut buf, SEEK_HEAD, &seek_head[..]);
+        let len = buf.len();
+        ebml::put_void(&mut buf, SEEK_HEAD_RESERVE - len);
+        let segment_start = self.segment_start;
+        try!(self.patch(segment_start, &buf[..]));
+
+        try!(self.out.flush());
+        Ok(self.out)
+    }
+}
+impl<W: Write + Seek> PacketWriter for Muxer<W> {
+    fn write_frame<'a>(&mut self, frame: &Frame<'a>) -> io::Result<()> {
+        // Partitions are joined back into whole frames; the last one is the
+        // one without the fragment flag.
+        if frame.is_fragment() || self.fragments_frame.is_some() {
+            if self.fragments_frame.is_none() {
+                self.fragments_frame = Some((frame.pts, frame.duration, frame.flags));
+            }
+            self.fragments.extend_from_slice(frame.data());
+            if frame.is_fragment() {
+                return Ok(());
+            }
+            let (pts, duration, flags) = self.fragments_frame.take().unwrap();
+            let fragments = ::std::mem::replace(&mut self.fragments, Vec::new());
+            return self.write_raw_frame(pts, duration, flags, &fragments[..]);
+        }
+        self.write_raw_frame(frame.pts, frame.duration, frame.flags, frame.data())
+    }
+}
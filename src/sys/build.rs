@@ -1,10 +1,91 @@
-
 extern crate pnacl_build_helper as helper;
 extern crate pkg_config as pkg;
 
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// The ABI versions `lib.rs` was generated from; update these with it.
+/// `vpx_codec_*_init_ver` rejects any others, and the structs wouldn't
+/// match them anyway.
+const ABI_VERSIONS: [(&'static str, i32); 4] = [
+    ("VPX_IMAGE_ABI_VERSION", 3),
+    ("VPX_CODEC_ABI_VERSION", 6),
+    ("VPX_DECODER_ABI_VERSION", 9),
+    ("VPX_ENCODER_ABI_VERSION", 11),
+];
+
+/// The `*_ABI_VERSION` defines of every header in `dir`, as they're written.
+fn read_defines(dir: &Path) -> HashMap<String, String> {
+    let mut defines = HashMap::new();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return defines,
+    };
+    for entry in entries {
+        let path = entry.unwrap().path();
+        if path.extension().map_or(true, |ext| ext != "h") { continue; }
+        let mut header = String::new();
+        File::open(&path).unwrap().read_to_string(&mut header).unwrap();
+        for line in header.replace("\\\n", " ").lines() {
+            let mut words = line.trim().splitn(3, char::is_whitespace);
+            match (words.next(), words.next(), words.next()) {
+                (Some("#define"), Some(name), Some(value)) if name.ends_with("_ABI_VERSION") => {
+                    let value = value.split("/*").next().unwrap().split("//").next().unwrap();
+                    defines.insert(name.to_string(), value.trim().to_string());
+                },
+                _ => {},
+            }
+        }
+    }
+    defines
+}
+
+/// Evaluates a define like `(3 + VPX_CODEC_ABI_VERSION)`.
+fn eval(name: &str, defines: &HashMap<String, String>) -> i32 {
+    let value = defines.get(name)
+        .unwrap_or_else(|| panic!("libvpx's headers don't define {}", name));
+    value.split('+').map(|term| {
+        let term = term.trim_matches(|c: char| c == '(' || c == ')' || c.is_whitespace());
+        term.parse().unwrap_or_else(|_| eval(term, defines))
+    }).sum()
+}
+
 pub fn main() {
     helper::set_pkg_config_envs();
     helper::print_lib_paths();
 
-    pkg::Config::new().statik(true).find("vpx").unwrap();
+    let lib = pkg::Config::new().statik(true).find("vpx").unwrap();
+
+    let mut dirs = lib.include_paths.clone();
+    dirs.push(PathBuf::from("/usr/include"));
+    dirs.push(PathBuf::from("/usr/local/include"));
+    let header_dir = dirs.iter()
+        .map(|dir| dir.join("vpx"))
+        .find(|dir| dir.join("vpx_encoder.h").exists());
+
+    let defines = header_dir.as_ref().map(|dir| {
+        println!("cargo:rerun-if-changed={}", dir.display());
+        read_defines(dir)
+    });
+    if defines.is_none() {
+        println!("cargo:warning=libvpx's headers weren't found, so its ABI versions are unchecked");
+    }
+
+    let mut out = String::new();
+    for &(name, version) in ABI_VERSIONS.iter() {
+        if let Some(ref defines) = defines {
+            let installed = eval(name, defines);
+            if installed != version {
+                panic!("the installed libvpx has {} {}, but the bindings were generated for {}; \
+                        regenerate them with regen-ffi.sh", name, installed, version);
+            }
+        }
+        out.push_str(&format!("pub const {}: ::libc::c_int = {};\n", name, version));
+    }
+
+    let path = Path::new(&env::var("OUT_DIR").unwrap()).join("abi.rs");
+    File::create(&path).unwrap().write_all(out.as_bytes()).unwrap();
 }
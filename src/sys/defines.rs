@@ -0,0 +1,27 @@
+/* `#define`s which bindgen doesn't translate; appended by regen-ffi.sh */
+
+/* VPX_*_ABI_VERSION, checked against libvpx's headers by build.rs */
+include!(concat!(env!("OUT_DIR"), "/abi.rs"));
+
+/* vpx_codec_flags_t bits for vpx_codec_enc_init_ver */
+pub const VPX_CODEC_USE_PSNR: vpx_codec_flags_t = 0x10000;
+pub const VPX_CODEC_USE_OUTPUT_PARTITION: vpx_codec_flags_t = 0x20000;
+pub const VPX_CODEC_USE_HIGHBITDEPTH: vpx_codec_flags_t = 0x40000;
+
+/* vpx_img_fmt_t bits */
+pub const VPX_IMG_FMT_PLANAR: vpx_img_fmt_t = 0x100;
+pub const VPX_IMG_FMT_UV_FLIP: vpx_img_fmt_t = 0x200;
+pub const VPX_IMG_FMT_HAS_ALPHA: vpx_img_fmt_t = 0x400;
+pub const VPX_IMG_FMT_HIGHBITDEPTH: vpx_img_fmt_t = 0x800;
+
+/* vpx_enc_frame_flags_t bits for vpx_codec_encode */
+pub const VPX_EFLAG_FORCE_KF: vpx_enc_frame_flags_t = 1 << 0;
+pub const VP8_EFLAG_NO_REF_LAST: vpx_enc_frame_flags_t = 1 << 16;
+pub const VP8_EFLAG_NO_REF_GF: vpx_enc_frame_flags_t = 1 << 17;
+pub const VP8_EFLAG_NO_UPD_LAST: vpx_enc_frame_flags_t = 1 << 18;
+pub const VP8_EFLAG_FORCE_GF: vpx_enc_frame_flags_t = 1 << 19;
+pub const VP8_EFLAG_NO_UPD_ENTROPY: vpx_enc_frame_flags_t = 1 << 20;
+pub const VP8_EFLAG_NO_REF_ARF: vpx_enc_frame_flags_t = 1 << 21;
+pub const VP8_EFLAG_NO_UPD_GF: vpx_enc_frame_flags_t = 1 << 22;
+pub const VP8_EFLAG_NO_UPD_ARF: vpx_enc_frame_flags_t = 1 << 23;
+pub const VP8_EFLAG_FORCE_ARF: vpx_enc_frame_flags_t = 1 << 24;
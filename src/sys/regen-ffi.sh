@@ -21,3 +21,4 @@ $BINDGEN -nostdinc -I $NACL_INCLUDE_DIR -isystem $NACL_DIR/toolchain/`$NACL_DIR/
 
 cat temp >> $OUT
 rm temp
+cat defines.rs >> $OUT
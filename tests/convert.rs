@@ -0,0 +1,97 @@
+extern crate vpx;
+
+use vpx::{ColorSpace, Format, Image};
+use vpx::convert::{rgb_to_yuv, yuv_to_rgb, Range};
+
+const WIDTH: u32 = 16;
+const HEIGHT: u32 = 8;
+
+fn rgb_image<F: Fn(usize, usize) -> (u8, u8, u8)>(pixel: F) -> Image<'static> {
+    let mut image = Image::alloc(Format::RGB24, ColorSpace::Unknown, WIDTH, HEIGHT, 16).unwrap();
+    let stride = image.stride(0).unwrap() as usize;
+    {
+        let plane = image.plane_mut(0).unwrap();
+        for y in 0..HEIGHT as usize {
+            for x in 0..WIDTH as usize {
+                let (r, g, b) = pixel(x, y);
+                let px = &mut plane[y * stride + x * 3..];
+                px[0] = r;
+                px[1] = g;
+                px[2] = b;
+            }
+        }
+    }
+    image
+}
+
+fn pixel(image: &Image, plane: usize, x: usize, y: usize) -> u8 {
+    image.plane(plane).unwrap()[y * image.stride(plane).unwrap() as usize + x]
+}
+
+#[test]
+fn round_trip() {
+    let gradient = |x: usize, y: usize| {
+        ((x * 16) as u8, (y * 32) as u8, (255 - x * 8 - y * 8) as u8)
+    };
+    let src = rgb_image(gradient);
+    let color_spaces = [ColorSpace::BT601, ColorSpace::SMPTE170, ColorSpace::BT709,
+                        ColorSpace::SMPTE240, ColorSpace::BT2020];
+    for &cs in color_spaces.iter() {
+        for &range in [Range::Limited, Range::Full].iter() {
+            // 4:4:4, so chroma subsampling doesn't lose anything.
+            let fmt = Format::I444 { hi_bit_depth: false };
+            let yuv = rgb_to_yuv(&src, fmt, cs, range).unwrap();
+            assert_eq!(yuv.get_color_space(), cs);
+            let rgb = yuv_to_rgb(&yuv, Format::RGB24, range).unwrap();
+
+            let plane = rgb.plane(0).unwrap();
+            let stride = rgb.stride(0).unwrap() as usize;
+            for y in 0..HEIGHT as usize {
+                for x in 0..WIDTH as usize {
+                    let (r, g, b) = gradient(x, y);
+                    let px = &plane[y * stride + x * 3..];
+                    for &(got, want) in [(px[0], r), (px[1], g), (px[2], b)].iter() {
+                        assert!((got as i32 - want as i32).abs() <= 3,
+                                "{:?} {:?} ({}, {}): {} != {}", cs, range, x, y, got, want);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn known_values() {
+    // (color space, range, RGB, YUV)
+    let cases = [
+        (ColorSpace::BT601, Range::Limited, (255, 255, 255), (235, 128, 128)),
+        (ColorSpace::BT601, Range::Limited, (0, 0, 0), (16, 128, 128)),
+        (ColorSpace::BT601, Range::Limited, (255, 0, 0), (81, 90, 240)),
+        (ColorSpace::BT601, Range::Limited, (0, 255, 0), (145, 54, 34)),
+        (ColorSpace::BT601, Range::Limited, (0, 0, 255), (41, 240, 110)),
+        (ColorSpace::BT601, Range::Full, (255, 0, 0), (76, 85, 255)),
+        (ColorSpace::BT709, Range::Limited, (255, 0, 0), (63, 102, 240)),
+        (ColorSpace::BT709, Range::Limited, (0, 255, 0), (173, 42, 26)),
+        (ColorSpace::BT709, Range::Limited, (0, 0, 255), (32, 240, 118)),
+    ];
+    for &(cs, range, rgb, (y, u, v)) in cases.iter() {
+        let src = rgb_image(|_, _| rgb);
+        let yuv = rgb_to_yuv(&src, Format::I420 { hi_bit_depth: false }, cs, range).unwrap();
+        let got = (pixel(&yuv, 0, 3, 3), pixel(&yuv, 1, 1, 1), pixel(&yuv, 2, 1, 1));
+        assert_eq!(got, (y, u, v), "{:?} {:?} {:?}", cs, range, rgb);
+
+        let back = yuv_to_rgb(&yuv, Format::RGB24, range).unwrap();
+        let px = &back.plane(0).unwrap()[..3];
+        for &(got, want) in [(px[0], rgb.0), (px[1], rgb.1), (px[2], rgb.2)].iter() {
+            assert!((got as i32 - want as i32).abs() <= 2, "{:?} {:?}", cs, rgb);
+        }
+    }
+}
+
+#[test]
+fn no_matrix() {
+    let src = rgb_image(|_, _| (1, 2, 3));
+    let fmt = Format::I420 { hi_bit_depth: false };
+    assert!(rgb_to_yuv(&src, fmt, ColorSpace::SRGB, Range::Full).is_err());
+    assert!(rgb_to_yuv(&src, Format::RGB24, ColorSpace::BT601, Range::Full).is_err());
+}
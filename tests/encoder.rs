@@ -0,0 +1,55 @@
+extern crate vpx;
+
+use vpx::{ColorSpace, Format, Image, Interface};
+use vpx::encoder::{vp8, CfgBuilder, Encoder, FrameFlags, Packet, TemporalLayering};
+
+/// `VPX_DL_GOOD_QUALITY`.
+const GOOD_QUALITY: u64 = 1000000;
+
+#[test]
+fn lagged_temporal_layers() {
+    let layering = TemporalLayering::new(3);
+    let pattern = layering.pattern();
+    let cfg = vp8::Cfg::default()
+        .size(64, 48)
+        .timebase(1, 30)
+        .target_bitrate(200)
+        .lag_in_frames(10)
+        .temporal_layering(layering);
+    let mut ctx = vp8::Interface.create(cfg, 0).unwrap();
+    ctx.set_auto_alt_ref(false).unwrap();
+
+    let mut image = Image::alloc(Format::I420 { hi_bit_depth: false },
+                                 ColorSpace::BT601, 64, 48, 16).unwrap();
+    let mut frames = Vec::new();
+    for pts in 0..20 {
+        for plane in 0..3 {
+            for byte in image.plane_mut(plane).unwrap() {
+                *byte = (pts * 8) as u8;
+            }
+        }
+        ctx.encode(&image, pts, 1, FrameFlags::new(), GOOD_QUALITY).unwrap();
+        let mut packets = Vec::new();
+        ctx.packets(&mut packets).unwrap();
+        frames.extend(packets);
+    }
+    let mut packets = Vec::new();
+    loop {
+        ctx.flush(20, 1, FrameFlags::new(), GOOD_QUALITY).unwrap();
+        let len = packets.len();
+        ctx.packets(&mut packets).unwrap();
+        if packets.len() == len { break; }
+    }
+    frames.extend(packets);
+
+    let frames: Vec<_> = frames.into_iter().filter_map(|packet| match packet {
+        Packet::Frame(frame) => Some(frame),
+        _ => None,
+    }).collect();
+    // The lag holds the first frames back until later ones were given.
+    assert_eq!(frames.len(), 20);
+    for frame in &frames {
+        let expected = pattern[frame.pts as usize % pattern.len()];
+        assert_eq!(frame.temporal_layer, Some(expected), "pts {}", frame.pts);
+    }
+}
@@ -0,0 +1,84 @@
+extern crate vpx;
+extern crate vpx_sys as ffi;
+
+use std::convert::TryFrom;
+
+use vpx::{ColorSpace, Format};
+
+const FORMATS: &'static [(Format, ffi::vpx_img_fmt_t)] = &[
+    (Format::RGB24, ffi::VPX_IMG_FMT_RGB24),
+    (Format::RGB32 { le: false, }, ffi::VPX_IMG_FMT_RGB32),
+    (Format::RGB32 { le: true, }, ffi::VPX_IMG_FMT_RGB32_LE),
+    (Format::RGB565 { le: false, }, ffi::VPX_IMG_FMT_RGB565),
+    (Format::RGB565 { le: true, }, ffi::VPX_IMG_FMT_RGB565_LE),
+    (Format::RGB555 { le: false, }, ffi::VPX_IMG_FMT_RGB555),
+    (Format::RGB555 { le: true, }, ffi::VPX_IMG_FMT_RGB555_LE),
+    (Format::UYVY, ffi::VPX_IMG_FMT_UYVY),
+    (Format::YUY2, ffi::VPX_IMG_FMT_YUY2),
+    (Format::YVYU, ffi::VPX_IMG_FMT_YVYU),
+    (Format::BGR24, ffi::VPX_IMG_FMT_BGR24),
+    (Format::ARGB, ffi::VPX_IMG_FMT_ARGB),
+    (Format::BGRA, ffi::VPX_IMG_FMT_ARGB_LE),
+    (Format::YV12_VPX, ffi::VPX_IMG_FMT_VPXYV12),
+    (Format::I420_VPX, ffi::VPX_IMG_FMT_VPXI420),
+    (Format::YV12, ffi::VPX_IMG_FMT_YV12),
+    (Format::I420 { hi_bit_depth: false }, ffi::VPX_IMG_FMT_I420),
+    (Format::I422 { hi_bit_depth: false }, ffi::VPX_IMG_FMT_I422),
+    (Format::I440 { hi_bit_depth: false }, ffi::VPX_IMG_FMT_I440),
+    (Format::I444 { hi_bit_depth: false }, ffi::VPX_IMG_FMT_I444),
+    (Format::I420 { hi_bit_depth: true }, ffi::VPX_IMG_FMT_I42016),
+    (Format::I422 { hi_bit_depth: true }, ffi::VPX_IMG_FMT_I42216),
+    (Format::I440 { hi_bit_depth: true }, ffi::VPX_IMG_FMT_I44016),
+    (Format::I444 { hi_bit_depth: true }, ffi::VPX_IMG_FMT_I44416),
+    (Format::I444A, ffi::VPX_IMG_FMT_444A),
+];
+
+const COLOR_SPACES: &'static [(ColorSpace, ffi::vpx_color_space_t)] = &[
+    (ColorSpace::Unknown, ffi::VPX_CS_UNKNOWN),
+    (ColorSpace::BT601, ffi::VPX_CS_BT_601),
+    (ColorSpace::BT709, ffi::VPX_CS_BT_709),
+    (ColorSpace::SMPTE170, ffi::VPX_CS_SMPTE_170),
+    (ColorSpace::SMPTE240, ffi::VPX_CS_SMPTE_240),
+    (ColorSpace::BT2020, ffi::VPX_CS_BT_2020),
+    (ColorSpace::Reserved, ffi::VPX_CS_RESERVED),
+    (ColorSpace::SRGB, ffi::VPX_CS_SRGB),
+];
+
+#[test]
+fn format_into_raw() {
+    for &(fmt, raw) in FORMATS {
+        let into: ffi::vpx_img_fmt_t = fmt.into();
+        assert_eq!(into, raw, "{:?}", fmt);
+    }
+}
+
+#[test]
+fn format_from_raw() {
+    for &(fmt, raw) in FORMATS {
+        assert_eq!(Format::try_from(raw), Ok(fmt));
+    }
+}
+
+#[test]
+fn format_round_trip() {
+    for &(fmt, _) in FORMATS {
+        let raw: ffi::vpx_img_fmt_t = fmt.into();
+        assert_eq!(Format::try_from(raw), Ok(fmt));
+    }
+}
+
+#[test]
+fn format_from_unknown_raw() {
+    assert!(Format::try_from(ffi::VPX_IMG_FMT_NONE).is_err());
+    assert!(Format::try_from(0xffff as ffi::vpx_img_fmt_t).is_err());
+}
+
+#[test]
+fn color_space_round_trip() {
+    for &(cs, raw) in COLOR_SPACES {
+        let into: ffi::vpx_color_space_t = cs.into();
+        assert_eq!(into, raw, "{:?}", cs);
+        assert_eq!(ColorSpace::try_from(raw), Ok(cs));
+    }
+    assert!(ColorSpace::try_from(8 as ffi::vpx_color_space_t).is_err());
+}
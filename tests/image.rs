@@ -0,0 +1,78 @@
+extern crate vpx;
+
+use vpx::{ColorSpace, Format, Image};
+
+#[test]
+fn out_of_range_planes() {
+    let fmt = Format::I420 { hi_bit_depth: true };
+    let mut image = Image::alloc(fmt, ColorSpace::BT709, 64, 48, 16).unwrap();
+    for plane in 0..3 {
+        assert!(image.stride(plane).is_some(), "plane {}", plane);
+        assert!(image.plane_u16(plane).is_some(), "plane {}", plane);
+        assert!(image.plane_u16_mut(plane).is_some(), "plane {}", plane);
+    }
+    for plane in 3..6 {
+        assert!(image.stride(plane).is_none(), "plane {}", plane);
+        assert!(image.plane(plane).is_none(), "plane {}", plane);
+        assert!(image.plane_u16(plane).is_none(), "plane {}", plane);
+        assert!(image.plane_u16_mut(plane).is_none(), "plane {}", plane);
+    }
+}
+
+#[test]
+fn packed_strides() {
+    let image = Image::alloc(Format::RGB24, ColorSpace::Unknown, 10, 4, 16).unwrap();
+    assert_eq!(image.stride(0), Some(32));
+    assert_eq!(image.stride(1), None);
+}
+
+/// Where each plane `vpx_img_wrap` laid out ends, relative to `data`,
+/// counting the padding of its last row.
+fn layout_end(image: &Image, data: &[u8]) -> usize {
+    (0..4).filter_map(|plane| {
+        image.plane(plane).map(|p| {
+            let rows = if plane == 1 || plane == 2 {
+                image.height() >> image.get_format().chroma_shift().1
+            } else {
+                image.height()
+            };
+            let offset = p.as_ptr() as usize - data.as_ptr() as usize;
+            offset + image.stride(plane).unwrap() as usize * rows as usize
+        })
+    }).max().unwrap()
+}
+
+#[test]
+fn required_size_odd_dimensions() {
+    // libvpx rounds the width up to 66 before picking the stride, so the
+    // chroma planes get 33 samples a row.
+    assert_eq!(Image::required_size(Format::I420 { hi_bit_depth: false }, 65, 33, 1),
+               Some(66 * 34 + 33 * 17 * 2));
+    assert_eq!(Image::required_size(Format::I420 { hi_bit_depth: false }, 65, 33, 32),
+               Some(96 * 34 + 48 * 17 * 2));
+
+    let formats = [
+        Format::I420 { hi_bit_depth: false },
+        Format::I422 { hi_bit_depth: false },
+        Format::I440 { hi_bit_depth: false },
+        Format::I444 { hi_bit_depth: false },
+        Format::I420 { hi_bit_depth: true },
+        Format::I444A,
+        Format::YV12,
+        Format::RGB24,
+    ];
+    for &fmt in formats.iter() {
+        for &(width, height) in [(65, 33), (1, 1), (17, 64)].iter() {
+            for &align in [1, 16].iter() {
+                let size = Image::required_size(fmt, width, height, align).unwrap();
+                let data = vec![0; size];
+                let image = Image::new(data[..].into(), fmt, ColorSpace::BT709,
+                                       width, height, align).unwrap();
+                assert_eq!(layout_end(&image, &data), size,
+                           "{:?} {}x{} aligned to {}", fmt, width, height, align);
+                assert!(Image::new(data[..size - 1].into(), fmt, ColorSpace::BT709,
+                                   width, height, align).is_err());
+            }
+        }
+    }
+}
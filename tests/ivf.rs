@@ -0,0 +1,108 @@
+extern crate vpx;
+extern crate vpx_sys as ffi;
+
+use std::io::Cursor;
+
+use vpx::{Codec, Frame, FRAME_IS_FRAGMENT, FRAME_IS_KEY};
+use vpx::encoder::PacketWriter;
+use vpx::ivf::{Reader, Writer};
+
+struct Cfg(ffi::vpx_codec_enc_cfg_t);
+impl AsRef<ffi::vpx_codec_enc_cfg_t> for Cfg {
+    fn as_ref(&self) -> &ffi::vpx_codec_enc_cfg_t { &self.0 }
+}
+
+fn cfg(width: u32, height: u32) -> Cfg {
+    let mut cfg: ffi::vpx_codec_enc_cfg_t = Default::default();
+    cfg.g_w = width;
+    cfg.g_h = height;
+    cfg.g_timebase = ffi::vpx_rational_t { num: 1, den: 30 };
+    Cfg(cfg)
+}
+
+fn raw_frame(data: &[u8], pts: i64, flags: u32) -> ffi::Struct_Unnamed6 {
+    ffi::Struct_Unnamed6 {
+        buf: data.as_ptr() as *mut _,
+        sz: data.len() as _,
+        pts: pts,
+        duration: 1,
+        flags: flags,
+        partition_id: 0,
+    }
+}
+
+#[test]
+fn write_read() {
+    let mut writer = Writer::new(Cursor::new(Vec::new()), Codec::VP9, &cfg(320, 240)).unwrap();
+    writer.write_raw_frame(0, &[1, 2, 3]).unwrap();
+    // A frame output in partitions is joined into one.
+    let parts: [&[u8]; 3] = [&[4, 5], &[6], &[7, 8, 9]];
+    for (i, part) in parts.iter().enumerate() {
+        let flags = if i < 2 { FRAME_IS_FRAGMENT } else { 0 };
+        let raw = raw_frame(part, 1, flags);
+        writer.write_frame(&Frame::from(&raw)).unwrap();
+    }
+    let raw = raw_frame(&[10], 2, FRAME_IS_KEY);
+    writer.write_frame(&Frame::from(&raw)).unwrap();
+    assert_eq!(writer.frame_count(), 3);
+    let out = writer.finish().unwrap().into_inner();
+
+    let mut reader = Reader::new(Cursor::new(out)).unwrap();
+    {
+        let header = reader.header();
+        assert_eq!(&header.fourcc, b"VP90");
+        assert_eq!(header.codec(), Some(Codec::VP9));
+        assert_eq!((header.width, header.height), (320, 240));
+        assert_eq!((header.timebase_num, header.timebase_den), (1, 30));
+        // Patched in by `finish`.
+        assert_eq!(header.frame_count, 3);
+    }
+    let frames: Vec<(u64, Vec<u8>)> = reader.by_ref()
+        .map(|f| f.map(|f| (f.pts, f.data)).unwrap())
+        .collect();
+    assert_eq!(frames, vec![
+        (0, vec![1, 2, 3]),
+        (1, vec![4, 5, 6, 7, 8, 9]),
+        (2, vec![10]),
+    ]);
+    assert!(reader.read_frame().unwrap().is_none());
+}
+
+#[test]
+fn finish_flushes_fragments() {
+    let mut writer = Writer::new(Cursor::new(Vec::new()), Codec::VP8, &cfg(16, 16)).unwrap();
+    let raw = raw_frame(&[1, 2], 5, FRAME_IS_FRAGMENT);
+    writer.write_frame(&Frame::from(&raw)).unwrap();
+    let out = writer.finish().unwrap().into_inner();
+
+    let mut reader = Reader::new(Cursor::new(out)).unwrap();
+    assert_eq!(reader.header().codec(), Some(Codec::VP8));
+    assert_eq!(reader.header().frame_count, 1);
+    let frame = reader.read_frame().unwrap().unwrap();
+    assert_eq!((frame.pts, frame.data), (5, vec![1, 2]));
+}
+
+#[test]
+fn truncated() {
+    let mut writer = Writer::new(Cursor::new(Vec::new()), Codec::VP8, &cfg(16, 16)).unwrap();
+    writer.write_raw_frame(0, &[1, 2, 3, 4]).unwrap();
+    let mut out = writer.finish().unwrap().into_inner();
+    out.pop();
+
+    let mut reader = Reader::new(Cursor::new(&out[..])).unwrap();
+    assert!(reader.read_frame().is_err());
+    // In the frame header.
+    let mut reader = Reader::new(Cursor::new(&out[..36])).unwrap();
+    assert!(reader.read_frame().is_err());
+    assert!(Reader::new(Cursor::new(&out[..20])).is_err());
+}
+
+#[test]
+fn bad_header() {
+    let mut writer = Writer::new(Cursor::new(Vec::new()), Codec::VP8, &cfg(16, 16)).unwrap();
+    writer.write_raw_frame(0, &[1]).unwrap();
+    let mut out = writer.finish().unwrap().into_inner();
+    out[0] = b'X';
+    assert!(Reader::new(Cursor::new(out)).is_err());
+    assert!(Writer::new(Cursor::new(Vec::new()), Codec::VP8, &cfg(70000, 16)).is_err());
+}
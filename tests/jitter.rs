@@ -0,0 +1,381 @@
+extern crate vpx;
+
+use vpx::rtp::{Packet, PictureId};
+use vpx::rtp::jitter::{Event, JitterBuffer};
+use vpx::rtp::{vp8, vp9};
+
+/// Builds the packets of VP8 frames, each split into `packets_per_frame`.
+struct Vp8Sender {
+    sequence: u16,
+    picture_id: u16,
+    tl0_pic_idx: u8,
+    packets_per_frame: usize,
+}
+impl Vp8Sender {
+    fn new(sequence: u16) -> Vp8Sender {
+        Vp8Sender {
+            sequence: sequence,
+            picture_id: 0,
+            tl0_pic_idx: 0,
+            packets_per_frame: 2,
+        }
+    }
+
+    fn frame(&mut self, keyframe: bool, layer: Option<vp8::TemporalLayer>) -> Vec<Packet> {
+        self.packets(keyframe, false, layer)
+    }
+    fn non_reference_frame(&mut self) -> Vec<Packet> {
+        self.packets(false, true, None)
+    }
+
+    fn packets(&mut self, keyframe: bool, non_reference: bool,
+               layer: Option<vp8::TemporalLayer>) -> Vec<Packet> {
+        if layer.map(|l| l.id == 0).unwrap_or(false) && !keyframe {
+            self.tl0_pic_idx = self.tl0_pic_idx.wrapping_add(1);
+        }
+        let mut desc = vp8::Descriptor {
+            non_reference: non_reference,
+            start_of_partition: true,
+            partition_index: 0,
+            picture_id: Some(PictureId::Long(self.picture_id)),
+            tl0_pic_idx: layer.map(|_| self.tl0_pic_idx),
+            temporal_layer: layer,
+            key_idx: None,
+        };
+        let timestamp = self.picture_id as u32 * 3000;
+        let mut packets = Vec::new();
+        for i in 0..self.packets_per_frame {
+            let mut payload = Vec::new();
+            desc.write(&mut payload);
+            if i == 0 {
+                // The frame tag; only the keyframe bit matters.
+                payload.extend_from_slice(&[if keyframe { 0x10 } else { 0x11 }, 0, 0]);
+            }
+            payload.extend_from_slice(&[self.picture_id as u8; 10]);
+            desc.start_of_partition = false;
+            packets.push(Packet {
+                sequence: self.sequence,
+                timestamp: timestamp,
+                marker: i == self.packets_per_frame - 1,
+                payload: payload,
+            });
+            self.sequence = self.sequence.wrapping_add(1);
+        }
+        self.picture_id += 1;
+        packets
+    }
+}
+
+fn layer(id: u8, sync: bool) -> Option<vp8::TemporalLayer> {
+    Some(vp8::TemporalLayer { id: id, sync: sync })
+}
+
+/// The picture IDs of the frames, whether a keyframe was asked for, and the
+/// sequence numbers which were.
+fn frames(events: Vec<Event<vp8::Frame>>) -> (Vec<u16>, bool, Vec<u16>) {
+    let mut ids = Vec::new();
+    let mut keyframe_request = false;
+    let mut nacks = Vec::new();
+    for event in events {
+        match event {
+            Event::Frame(f) => ids.push(f.picture_id.unwrap().value()),
+            Event::KeyframeRequest => keyframe_request = true,
+            Event::Nack(seqs) => nacks.extend(seqs),
+        }
+    }
+    (ids, keyframe_request, nacks)
+}
+
+fn push_all(jb: &mut JitterBuffer<vp8::Depacketizer>, packets: Vec<Packet>) -> Vec<Event<vp8::Frame>> {
+    let mut events = Vec::new();
+    for packet in packets {
+        events.extend(jb.push(packet));
+    }
+    events
+}
+
+#[test]
+fn in_order() {
+    let mut sender = Vp8Sender::new(100);
+    let mut jb = JitterBuffer::new(vp8::Depacketizer::new());
+    let mut packets = sender.frame(true, None);
+    for _ in 0..4 {
+        packets.extend(sender.frame(false, None));
+    }
+    let (ids, keyframe_request, nacks) = frames(push_all(&mut jb, packets));
+    assert_eq!(ids, vec![0, 1, 2, 3, 4]);
+    assert!(!keyframe_request);
+    assert!(nacks.is_empty());
+}
+
+#[test]
+fn reordered() {
+    let mut sender = Vp8Sender::new(100);
+    let mut jb = JitterBuffer::new(vp8::Depacketizer::new());
+    let mut packets = sender.frame(true, None);
+    for _ in 0..3 {
+        packets.extend(sender.frame(false, None));
+    }
+    packets.swap(1, 2);
+    packets.swap(4, 7);
+    let (ids, keyframe_request, nacks) = frames(push_all(&mut jb, packets));
+    assert_eq!(ids, vec![0, 1, 2, 3]);
+    assert!(!keyframe_request);
+    // The packets which came late were asked for.
+    assert_eq!(nacks, vec![101, 104, 105, 106]);
+    assert_eq!(jb.buffered(), 0);
+}
+
+#[test]
+fn duplicates_ignored() {
+    let mut sender = Vp8Sender::new(0);
+    let mut jb = JitterBuffer::new(vp8::Depacketizer::new());
+    let key = sender.frame(true, None);
+    let mut packets = key.clone();
+    packets.extend(key);
+    packets.extend(sender.frame(false, None));
+    let (ids, _, _) = frames(push_all(&mut jb, packets));
+    assert_eq!(ids, vec![0, 1]);
+}
+
+#[test]
+fn retransmitted() {
+    let mut sender = Vp8Sender::new(10);
+    let mut jb = JitterBuffer::new(vp8::Depacketizer::new());
+    let mut packets = sender.frame(true, None);
+    packets.extend(sender.frame(false, None));
+    packets.extend(sender.frame(false, None));
+    let lost = packets.remove(2);
+
+    let (ids, keyframe_request, nacks) = frames(push_all(&mut jb, packets));
+    assert_eq!(ids, vec![0]);
+    assert!(!keyframe_request);
+    assert_eq!(nacks, vec![12]);
+
+    let (ids, _, _) = frames(jb.push(lost));
+    assert_eq!(ids, vec![1, 2]);
+}
+
+#[test]
+fn lost_until_keyframe() {
+    let mut sender = Vp8Sender::new(10);
+    let mut jb = JitterBuffer::new(vp8::Depacketizer::new()).max_reorder(4);
+    let mut packets = sender.frame(true, None);
+    for _ in 0..5 {
+        packets.extend(sender.frame(false, None));
+    }
+    // The second packet of frame 1.
+    packets.remove(3);
+
+    let (ids, keyframe_request, nacks) = frames(push_all(&mut jb, packets));
+    // Frame 1 is gone, so nothing after it can be decoded.
+    assert_eq!(ids, vec![0]);
+    assert!(keyframe_request);
+    assert_eq!(nacks, vec![13]);
+
+    let mut packets = sender.frame(false, None);
+    packets.extend(sender.frame(true, None));
+    packets.extend(sender.frame(false, None));
+    let (ids, keyframe_request, _) = frames(push_all(&mut jb, packets));
+    assert_eq!(ids, vec![7, 8]);
+    assert!(!keyframe_request);
+}
+
+#[test]
+fn lost_non_reference() {
+    let mut sender = Vp8Sender::new(10);
+    let mut jb = JitterBuffer::new(vp8::Depacketizer::new()).max_reorder(2);
+    let mut packets = sender.frame(true, None);
+    // The last packet of frame 1, which nothing references.
+    packets.extend(sender.non_reference_frame().into_iter().take(1));
+    for _ in 0..4 {
+        packets.extend(sender.frame(false, None));
+    }
+
+    let (ids, keyframe_request, nacks) = frames(push_all(&mut jb, packets));
+    assert_eq!(ids, vec![0, 2, 3, 4, 5]);
+    assert!(!keyframe_request);
+    assert_eq!(nacks, vec![13]);
+}
+
+#[test]
+fn lost_non_reference_and_reference() {
+    let mut sender = Vp8Sender::new(10);
+    let mut jb = JitterBuffer::new(vp8::Depacketizer::new()).max_reorder(2);
+    let mut packets = sender.frame(true, None);
+    packets.extend(sender.non_reference_frame().into_iter().take(1));
+    // All of frame 2, which is a reference frame.
+    sender.frame(false, None);
+    for _ in 0..3 {
+        packets.extend(sender.frame(false, None));
+    }
+
+    let (ids, keyframe_request, _) = frames(push_all(&mut jb, packets));
+    assert_eq!(ids, vec![0]);
+    assert!(keyframe_request);
+}
+
+#[test]
+fn lost_whole_non_reference() {
+    let mut sender = Vp8Sender::new(10);
+    let mut jb = JitterBuffer::new(vp8::Depacketizer::new()).max_reorder(2);
+    let mut packets = sender.frame(true, None);
+    // Without a packet of frame 1, or temporal layers, there's no telling
+    // it wasn't a reference frame.
+    sender.non_reference_frame();
+    for _ in 0..3 {
+        packets.extend(sender.frame(false, None));
+    }
+
+    let (ids, keyframe_request, _) = frames(push_all(&mut jb, packets));
+    assert_eq!(ids, vec![0]);
+    assert!(keyframe_request);
+
+    // With them, TL0PICIDX shows no base layer frame was lost.
+    let mut sender = Vp8Sender::new(0);
+    let mut jb = JitterBuffer::new(vp8::Depacketizer::new()).max_reorder(2);
+    let mut packets = sender.frame(true, layer(0, false));
+    sender.frame(false, layer(1, true));
+    packets.extend(sender.frame(false, layer(0, false)));
+    packets.extend(sender.frame(false, layer(1, true)));
+
+    let (ids, keyframe_request, _) = frames(push_all(&mut jb, packets));
+    assert_eq!(ids, vec![0, 2, 3]);
+    assert!(!keyframe_request);
+}
+
+#[test]
+fn skip_missing() {
+    let mut sender = Vp8Sender::new(10);
+    let mut jb = JitterBuffer::new(vp8::Depacketizer::new());
+    let mut packets = sender.frame(true, None);
+    packets.extend(sender.frame(false, None));
+    packets.extend(sender.frame(false, None));
+    // The start of frame 1.
+    packets.remove(2);
+
+    let (ids, _, _) = frames(push_all(&mut jb, packets));
+    assert_eq!(ids, vec![0]);
+    assert_eq!(jb.buffered(), 3);
+    let (ids, keyframe_request, _) = frames(jb.skip_missing());
+    assert!(ids.is_empty());
+    assert!(keyframe_request);
+    assert_eq!(jb.buffered(), 0);
+}
+
+#[test]
+fn lost_upper_temporal_layer() {
+    let mut sender = Vp8Sender::new(0);
+    let mut jb = JitterBuffer::new(vp8::Depacketizer::new()).max_reorder(2);
+    let mut packets = sender.frame(true, layer(0, false));
+    // A 0101 pattern, where frame 1 is lost.
+    packets.extend(sender.frame(false, layer(1, true)).into_iter().skip(1));
+    packets.extend(sender.frame(false, layer(0, false)));
+    packets.extend(sender.frame(false, layer(1, true)));
+    packets.extend(sender.frame(false, layer(0, false)));
+
+    let (ids, keyframe_request, _) = frames(push_all(&mut jb, packets));
+    assert_eq!(ids, vec![0, 2, 3, 4]);
+    assert!(!keyframe_request);
+}
+
+#[test]
+fn upper_layer_needs_sync_after_loss() {
+    let mut sender = Vp8Sender::new(0);
+    let mut jb = JitterBuffer::new(vp8::Depacketizer::new()).max_reorder(2);
+    let mut packets = sender.frame(true, layer(0, false));
+    // Frames 1 to 3 are in layer 2 and depend on each other; 1 is lost.
+    packets.extend(sender.frame(false, layer(2, true)).into_iter().skip(1));
+    packets.extend(sender.frame(false, layer(2, false)));
+    packets.extend(sender.frame(false, layer(2, false)));
+    packets.extend(sender.frame(false, layer(0, false)));
+    packets.extend(sender.frame(false, layer(2, false)));
+
+    let (ids, keyframe_request, _) = frames(push_all(&mut jb, packets));
+    assert_eq!(ids, vec![0, 4, 5]);
+    assert!(!keyframe_request);
+}
+
+#[test]
+fn lost_base_temporal_layer() {
+    let mut sender = Vp8Sender::new(0);
+    let mut jb = JitterBuffer::new(vp8::Depacketizer::new()).max_reorder(2);
+    let mut packets = sender.frame(true, layer(0, false));
+    packets.extend(sender.frame(false, layer(1, true)));
+    packets.extend(sender.frame(false, layer(0, false)).into_iter().skip(1));
+    packets.extend(sender.frame(false, layer(1, true)));
+    packets.extend(sender.frame(false, layer(0, false)));
+
+    let (ids, keyframe_request, _) = frames(push_all(&mut jb, packets));
+    assert_eq!(ids, vec![0, 1]);
+    assert!(keyframe_request);
+}
+
+#[test]
+fn sequence_wraps() {
+    let mut sender = Vp8Sender::new(65533);
+    let mut jb = JitterBuffer::new(vp8::Depacketizer::new());
+    let mut packets = sender.frame(true, None);
+    packets.extend(sender.frame(false, None));
+    packets.extend(sender.frame(false, None));
+    packets.swap(2, 3);
+    let (ids, keyframe_request, nacks) = frames(push_all(&mut jb, packets));
+    assert_eq!(ids, vec![0, 1, 2]);
+    assert!(!keyframe_request);
+    assert_eq!(nacks, vec![65535]);
+}
+
+fn vp9_packet(sequence: u16, picture_id: u16, p_diffs: Vec<u8>) -> Packet {
+    let desc = vp9::Descriptor {
+        picture_id: Some(PictureId::Long(picture_id)),
+        inter_picture_predicted: !p_diffs.is_empty(),
+        layer: Some(vp9::LayerIndices {
+            temporal_id: 0,
+            switching_up_point: false,
+            spatial_id: 0,
+            inter_layer_dependency: false,
+        }),
+        flexible: true,
+        start_of_frame: true,
+        end_of_frame: true,
+        not_upper_reference: false,
+        tl0_pic_idx: None,
+        p_diffs: p_diffs,
+        scalability_structure: None,
+    };
+    let mut payload = Vec::new();
+    desc.write(&mut payload);
+    payload.extend_from_slice(&[0x80; 8]);
+    Packet {
+        sequence: sequence,
+        timestamp: picture_id as u32 * 3000,
+        marker: true,
+        payload: payload,
+    }
+}
+
+#[test]
+fn vp9_flexible_references() {
+    let mut jb = JitterBuffer::new(vp9::Depacketizer::new()).max_reorder(1);
+    let mut ids = Vec::new();
+    let mut keyframe_request = false;
+    // Picture 2 is lost; 3 references it, but 4 only references 0.
+    let packets = vec![
+        vp9_packet(0, 0, vec![]),
+        vp9_packet(1, 1, vec![1]),
+        vp9_packet(3, 3, vec![1]),
+        vp9_packet(4, 4, vec![4]),
+        vp9_packet(5, 5, vec![1, 4]),
+    ];
+    for packet in packets {
+        for event in jb.push(packet) {
+            match event {
+                Event::Frame(f) => ids.push(f.picture_id.unwrap().value()),
+                Event::KeyframeRequest => keyframe_request = true,
+                Event::Nack(_) => (),
+            }
+        }
+    }
+    assert_eq!(ids, vec![0, 1, 4, 5]);
+    assert!(keyframe_request);
+}
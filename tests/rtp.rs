@@ -0,0 +1,363 @@
+extern crate vpx;
+extern crate vpx_sys as ffi;
+
+use vpx::{ErrorKind, Frame, FRAME_IS_DROPPABLE, FRAME_IS_FRAGMENT, FRAME_IS_KEY};
+use vpx::rtp::{Packet, PictureId};
+use vpx::rtp::{vp8, vp9};
+use vpx::rtp::vp9::{LayerFrame, LayerIndices, ScalabilityStructure};
+use vpx::vp9::superframe;
+
+fn raw_frame(data: &[u8], flags: u32, partition_id: i32) -> ffi::Struct_Unnamed6 {
+    ffi::Struct_Unnamed6 {
+        buf: data.as_ptr() as *mut _,
+        sz: data.len() as _,
+        pts: 0,
+        duration: 1,
+        flags: flags,
+        partition_id: partition_id,
+    }
+}
+
+/// A VP8 frame of `len` bytes, starting with a frame tag.
+fn vp8_data(keyframe: bool, len: usize) -> Vec<u8> {
+    let mut data: Vec<u8> = (0..len).map(|i| i as u8).collect();
+    data[0] = if keyframe { 0x10 } else { 0x11 };
+    data
+}
+
+fn vp8_packetize(packetizer: &mut vp8::Packetizer, data: &[u8], flags: u32, partition_id: i32,
+                 timestamp: u32, layer: Option<vp8::TemporalLayer>) -> Vec<Packet> {
+    let raw = raw_frame(data, flags, partition_id);
+    packetizer.packetize(&Frame::from(&raw), timestamp, layer).unwrap()
+}
+
+fn vp8_descriptors(packets: &[Packet]) -> Vec<vp8::Descriptor> {
+    packets.iter().map(|p| vp8::Descriptor::parse(&p.payload).unwrap().0).collect()
+}
+
+#[test]
+fn vp8_descriptor() {
+    let plain = vp8::Descriptor {
+        non_reference: false,
+        start_of_partition: true,
+        partition_index: 0,
+        picture_id: None,
+        tl0_pic_idx: None,
+        temporal_layer: None,
+        key_idx: None,
+    };
+    let layer = vp8::TemporalLayer { id: 2, sync: true };
+    let cases = [
+        (plain, vec![0x10]),
+        (vp8::Descriptor { non_reference: true, partition_index: 3, ..plain },
+         vec![0x33]),
+        (vp8::Descriptor { picture_id: Some(PictureId::Short(0x55)), ..plain },
+         vec![0x90, 0x80, 0x55]),
+        (vp8::Descriptor { picture_id: Some(PictureId::Long(0x1234)), ..plain },
+         vec![0x90, 0x80, 0x92, 0x34]),
+        (vp8::Descriptor { tl0_pic_idx: Some(7), temporal_layer: Some(layer), ..plain },
+         vec![0x90, 0x60, 0x07, 0xa0]),
+        (vp8::Descriptor { key_idx: Some(21), ..plain },
+         vec![0x90, 0x10, 0x15]),
+        (vp8::Descriptor {
+            start_of_partition: false,
+            partition_index: 7,
+            picture_id: Some(PictureId::Long(0x7fff)),
+            tl0_pic_idx: Some(0xff),
+            temporal_layer: Some(vp8::TemporalLayer { id: 1, sync: false }),
+            key_idx: Some(31),
+            ..plain
+        }, vec![0x87, 0xf0, 0xff, 0xff, 0xff, 0x5f]),
+    ];
+    for &(desc, ref bytes) in cases.iter() {
+        let mut buf = Vec::new();
+        desc.write(&mut buf);
+        assert_eq!(&buf, bytes, "{:?}", desc);
+        assert_eq!(desc.encoded_len(), buf.len());
+
+        // Followed by the payload.
+        buf.push(0xaa);
+        assert_eq!(vp8::Descriptor::parse(&buf).unwrap(), (desc, bytes.len()));
+        for len in 0..bytes.len() {
+            assert_eq!(vp8::Descriptor::parse(&buf[..len]).unwrap_err().kind(),
+                       ErrorKind::CorruptFrame);
+        }
+    }
+}
+
+#[test]
+fn vp8_mtu_split() {
+    let mut packetizer = vp8::Packetizer::new(4 + 10, 100);
+    let data = vp8_data(true, 25);
+    let packets = vp8_packetize(&mut packetizer, &data, FRAME_IS_KEY, 0, 9000, None);
+
+    assert_eq!(packets.iter().map(|p| p.sequence).collect::<Vec<_>>(), vec![100, 101, 102]);
+    assert!(packets.iter().all(|p| p.timestamp == 9000));
+    assert_eq!(packets.iter().map(|p| p.marker).collect::<Vec<_>>(), vec![false, false, true]);
+    // As even as possible.
+    assert_eq!(packets.iter().map(|p| p.payload.len()).collect::<Vec<_>>(), vec![13, 12, 12]);
+
+    let descs = vp8_descriptors(&packets);
+    // Only the first packet starts the partition.
+    assert_eq!(descs.iter().map(|d| d.start_of_partition).collect::<Vec<_>>(),
+               vec![true, false, false]);
+    assert!(descs.iter().all(|d| {
+        d.picture_id == Some(PictureId::Long(0)) && d.partition_index == 0 &&
+            !d.non_reference && d.tl0_pic_idx.is_none() && d.key_idx.is_none()
+    }));
+
+    let mut depacketizer = vp8::Depacketizer::new();
+    assert_eq!(depacketizer.push(&packets[0]).unwrap(), None);
+    assert_eq!(depacketizer.push(&packets[1]).unwrap(), None);
+    let frame = depacketizer.push(&packets[2]).unwrap().unwrap();
+    assert_eq!(frame, vp8::Frame {
+        timestamp: 9000,
+        keyframe: true,
+        non_reference: false,
+        picture_id: Some(PictureId::Long(0)),
+        tl0_pic_idx: None,
+        temporal_layer: None,
+        after_loss: false,
+        lost_non_reference: Vec::new(),
+        data: data,
+    });
+
+    // The picture ID moves on with each frame.
+    let data = vp8_data(false, 5);
+    let packets = vp8_packetize(&mut packetizer, &data, FRAME_IS_DROPPABLE, 0, 12000, None);
+    assert_eq!(packets.len(), 1);
+    let desc = vp8_descriptors(&packets)[0];
+    assert_eq!(desc.picture_id, Some(PictureId::Long(1)));
+    assert!(desc.non_reference);
+    let frame = depacketizer.push(&packets[0]).unwrap().unwrap();
+    assert!(!frame.keyframe && frame.non_reference && !frame.after_loss);
+    assert_eq!(frame.data, data);
+
+    // Too small for the descriptor and any data.
+    let mut packetizer = vp8::Packetizer::new(4, 0);
+    let raw = raw_frame(&data, 0, 0);
+    let err = packetizer.packetize(&Frame::from(&raw), 0, None).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidParam);
+}
+
+#[test]
+fn vp8_layers_and_key_idx() {
+    let mut packetizer = vp8::Packetizer::new(1200, 0xfffe)
+        .picture_id(Some(PictureId::Short(126)))
+        .key_idx(true);
+    let layer = |id, sync| Some(vp8::TemporalLayer { id: id, sync: sync });
+    // (keyframe, layer)
+    let frames = [
+        (true, layer(0, true)),
+        (false, layer(2, true)),
+        (false, layer(1, true)),
+        (false, layer(2, false)),
+        (false, layer(0, false)),
+        (true, layer(0, true)),
+    ];
+    let mut depacketizer = vp8::Depacketizer::new();
+    let mut got = Vec::new();
+    for (i, &(keyframe, layer)) in frames.iter().enumerate() {
+        let data = vp8_data(keyframe, 8);
+        let flags = if keyframe { FRAME_IS_KEY } else { 0 };
+        let packets = vp8_packetize(&mut packetizer, &data, flags, 0, i as u32 * 3000, layer);
+        assert_eq!(packets.len(), 1);
+        let desc = vp8_descriptors(&packets)[0];
+        assert_eq!(desc.temporal_layer, layer);
+        got.push((packets[0].sequence, desc.picture_id.unwrap(), desc.tl0_pic_idx.unwrap(),
+                  desc.key_idx.unwrap()));
+
+        let frame = depacketizer.push(&packets[0]).unwrap().unwrap();
+        assert_eq!((frame.keyframe, frame.temporal_layer), (keyframe, layer));
+        assert_eq!((frame.picture_id, frame.tl0_pic_idx), (desc.picture_id, desc.tl0_pic_idx));
+        assert!(!frame.after_loss);
+    }
+    // Short picture IDs wrap at 7 bits, TL0PICIDX moves on with each base
+    // layer frame and KEYIDX with each keyframe.
+    assert_eq!(got, vec![
+        (0xfffe, PictureId::Short(126), 0, 0),
+        (0xffff, PictureId::Short(127), 0, 0),
+        (0, PictureId::Short(0), 0, 0),
+        (1, PictureId::Short(1), 0, 0),
+        (2, PictureId::Short(2), 1, 0),
+        (3, PictureId::Short(3), 2, 1),
+    ]);
+
+    // No picture ID, so no extension at all.
+    let mut packetizer = vp8::Packetizer::new(1200, 0).picture_id(None);
+    let packets = vp8_packetize(&mut packetizer, &vp8_data(true, 8), FRAME_IS_KEY, 0, 0, None);
+    assert_eq!(packets[0].payload[0], 0x10);
+    assert_eq!(packets[0].payload.len(), 1 + 8);
+}
+
+#[test]
+fn vp8_partitions() {
+    let mut packetizer = vp8::Packetizer::new(4 + 4, 0);
+    let mut depacketizer = vp8::Depacketizer::new();
+    let mut data = Vec::new();
+    let mut descs = Vec::new();
+    let mut markers = Vec::new();
+    let mut frame = None;
+    // 10 partitions of 6 bytes, each split in 2 packets.
+    for id in 0..10 {
+        let partition = if id == 0 { vp8_data(false, 6) } else { vec![id as u8; 6] };
+        data.extend_from_slice(&partition);
+        let flags = if id < 9 { FRAME_IS_FRAGMENT } else { 0 };
+        let packets = vp8_packetize(&mut packetizer, &partition, flags, id, 3000, None);
+        assert_eq!(packets.len(), 2);
+        descs.extend(vp8_descriptors(&packets));
+        markers.extend(packets.iter().map(|p| p.marker));
+        for packet in packets.iter() {
+            assert!(frame.is_none());
+            frame = depacketizer.push(packet).unwrap();
+        }
+    }
+
+    let pids: Vec<_> = descs.iter().map(|d| d.partition_index).collect();
+    assert_eq!(pids, vec![0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 7, 7, 7, 7]);
+    // Each partition starts in a new packet.
+    assert!(descs.iter().enumerate().all(|(i, d)| d.start_of_partition == (i % 2 == 0)));
+    // The same picture, ending with the last partition.
+    assert!(descs.iter().all(|d| d.picture_id == Some(PictureId::Long(0))));
+    assert_eq!(markers.iter().filter(|&&m| m).count(), 1);
+    assert!(markers[markers.len() - 1]);
+
+    let frame = frame.unwrap();
+    assert_eq!(frame.data, data);
+    assert!(!frame.keyframe && !frame.after_loss);
+
+    let packets = vp8_packetize(&mut packetizer, &vp8_data(false, 4), 0, 0, 6000, None);
+    assert_eq!(vp8_descriptors(&packets)[0].picture_id, Some(PictureId::Long(1)));
+}
+
+fn vp9_layer(spatial_id: u8, temporal_id: u8) -> LayerFrame {
+    LayerFrame {
+        layer: LayerIndices {
+            temporal_id: temporal_id,
+            switching_up_point: false,
+            spatial_id: spatial_id,
+            inter_layer_dependency: spatial_id > 0,
+        },
+        p_diffs: Vec::new(),
+    }
+}
+
+fn vp9_packetize(packetizer: &mut vp9::Packetizer, data: &[u8], flags: u32, timestamp: u32,
+                 layers: &[LayerFrame]) -> Result<Vec<Packet>, vpx::Error> {
+    let raw = raw_frame(data, flags, 0);
+    packetizer.packetize(&Frame::from(&raw), timestamp, layers)
+}
+
+fn vp9_descriptors(packets: &[Packet]) -> Vec<vp9::Descriptor> {
+    packets.iter().map(|p| vp9::Descriptor::parse(&p.payload).unwrap().0).collect()
+}
+
+fn vp9_depacketize(depacketizer: &mut vp9::Depacketizer, packets: &[Packet]) -> Vec<vp9::Frame> {
+    packets.iter().filter_map(|p| depacketizer.push(p).unwrap()).collect()
+}
+
+#[test]
+fn vp9_spatial_layers() {
+    let ss = ScalabilityStructure {
+        spatial_layers: 2,
+        resolutions: Some(vec![(160, 90), (320, 180)]),
+        picture_group: None,
+    };
+    // 8 bytes of data fit with the scalability structure, 17 without.
+    let mut packetizer = vp9::Packetizer::new(14 + 8, 0).scalability_structure(Some(ss.clone()));
+    let mut depacketizer = vp9::Depacketizer::new();
+    let layers = [vp9_layer(0, 0), vp9_layer(1, 0)];
+
+    // A keyframe as a superframe.
+    let base = [1u8; 16];
+    let upper = [2u8; 34];
+    let data = superframe::build(&[&base[..], &upper[..]]).unwrap();
+    let packets = vp9_packetize(&mut packetizer, &data, FRAME_IS_KEY, 0, &layers).unwrap();
+    assert_eq!(packets.len(), 4);
+    assert_eq!(packets.iter().map(|p| p.marker).collect::<Vec<_>>(),
+               vec![false, false, false, true]);
+    let descs = vp9_descriptors(&packets);
+    assert_eq!(descs.iter().map(|d| (d.start_of_frame, d.end_of_frame)).collect::<Vec<_>>(),
+               vec![(true, false), (false, true), (true, false), (false, true)]);
+    assert_eq!(descs[0].scalability_structure, Some(ss.clone()));
+    assert!(descs[1..].iter().all(|d| d.scalability_structure.is_none()));
+    assert!(descs.iter().all(|d| d.picture_id == Some(PictureId::Long(0))));
+
+    let frames = vp9_depacketize(&mut depacketizer, &packets);
+    assert_eq!(frames.len(), 1);
+    assert!(frames[0].keyframe && !frames[0].after_loss);
+    assert_eq!(frames[0].layers, layers.to_vec());
+    assert_eq!(frames[0].scalability_structure, Some(ss.clone()));
+    assert_eq!(frames[0].data, data);
+
+    // An inter picture, a layer at a time. Only the upper layer ends it.
+    let base = [3u8; 16];
+    let upper = [4u8; 34];
+    let mut packets = vp9_packetize(&mut packetizer, &base, 0, 3000, &layers[..1]).unwrap();
+    packets.extend(vp9_packetize(&mut packetizer, &upper, 0, 3000, &layers[1..]).unwrap());
+    assert_eq!(packets.iter().map(|p| p.marker).collect::<Vec<_>>(), vec![false, false, true]);
+    assert_eq!(packets.iter().map(|p| p.sequence).collect::<Vec<_>>(), vec![4, 5, 6]);
+    let descs = vp9_descriptors(&packets);
+    assert!(descs.iter().all(|d| {
+        d.picture_id == Some(PictureId::Long(1)) && d.inter_picture_predicted &&
+            d.scalability_structure.is_none()
+    }));
+
+    let frames = vp9_depacketize(&mut depacketizer, &packets);
+    assert_eq!(frames.len(), 1);
+    assert!(!frames[0].keyframe && !frames[0].after_loss);
+    assert_eq!(frames[0].layers, layers.to_vec());
+    assert_eq!(frames[0].data, superframe::build(&[&base[..], &upper[..]]).unwrap());
+}
+
+#[test]
+fn vp9_spatial_layers_without_scalability_structure() {
+    let mut packetizer = vp9::Packetizer::new(1200, 0);
+    let mut depacketizer = vp9::Depacketizer::new();
+    let layers = [vp9_layer(0, 0), vp9_layer(1, 0)];
+
+    // A whole picture ends with its last layer.
+    let data = superframe::build(&[&[1u8; 8][..], &[2u8; 8][..]]).unwrap();
+    let packets = vp9_packetize(&mut packetizer, &data, FRAME_IS_KEY, 0, &layers).unwrap();
+    assert_eq!(packets.iter().map(|p| p.marker).collect::<Vec<_>>(), vec![false, true]);
+    let frames = vp9_depacketize(&mut depacketizer, &packets);
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].layers, layers.to_vec());
+    assert_eq!(frames[0].data, data);
+
+    // Without the number of layers, there's no telling which one ends the
+    // picture.
+    vp9_packetize(&mut packetizer, &[3u8; 8], 0, 3000, &layers[..1]).unwrap();
+    let err = vp9_packetize(&mut packetizer, &[4u8; 8], 0, 3000, &layers[1..]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidParam);
+}
+
+#[test]
+fn vp9_flexible_references() {
+    let mut packetizer = vp9::Packetizer::new(1200, 0).flexible(true);
+    let mut depacketizer = vp9::Depacketizer::new();
+
+    let packets = vp9_packetize(&mut packetizer, &[1u8; 8], FRAME_IS_KEY, 0,
+                                &[vp9_layer(0, 0)]).unwrap();
+    let frames = vp9_depacketize(&mut depacketizer, &packets);
+    assert_eq!(frames.len(), 1);
+    assert!(frames[0].keyframe && !frames[0].inter_picture_predicted);
+
+    // An inter frame's references have to be given, along with its layer.
+    let err = vp9_packetize(&mut packetizer, &[2u8; 8], 0, 3000, &[]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidParam);
+    let err = vp9_packetize(&mut packetizer, &[2u8; 8], 0, 3000,
+                            &[vp9_layer(0, 0)]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidParam);
+
+    let layers = [LayerFrame { p_diffs: vec![1], ..vp9_layer(0, 0) }];
+    let packets = vp9_packetize(&mut packetizer, &[2u8; 8], 0, 3000, &layers).unwrap();
+    let descriptor = &vp9_descriptors(&packets)[0];
+    assert!(descriptor.flexible && descriptor.inter_picture_predicted);
+    assert_eq!(descriptor.p_diffs, vec![1]);
+    let frames = vp9_depacketize(&mut depacketizer, &packets);
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].layers, layers.to_vec());
+    assert_eq!(frames[0].picture_id, Some(PictureId::Long(1)));
+    assert_eq!(frames[0].data, vec![2u8; 8]);
+}
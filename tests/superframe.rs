@@ -0,0 +1,87 @@
+extern crate vpx;
+
+use vpx::ErrorKind;
+use vpx::vp9::superframe;
+
+/// Two frames, of 3 and 2 bytes, with 1-byte sizes.
+const TWO_FRAMES: &'static [u8] = &[
+    0x80, 0x01, 0x02,
+    0x81, 0x03,
+    0xc1, 0x03, 0x02, 0xc1,
+];
+
+#[test]
+fn parse_index() {
+    assert_eq!(superframe::parse_index(TWO_FRAMES).unwrap(), Some(vec![3, 2]));
+}
+
+#[test]
+fn parse_index_two_byte_sizes() {
+    let mut data = vec![0u8; 0x123 + 4];
+    // 2 frames, 2 bytes per size.
+    data.extend_from_slice(&[0xc9, 0x23, 0x01, 0x04, 0x00, 0xc9]);
+    assert_eq!(superframe::parse_index(&data[..]).unwrap(), Some(vec![0x123, 4]));
+}
+
+#[test]
+fn not_a_superframe() {
+    // Ends in something that looks like a marker, but doesn't have the
+    // matching marker at the start of the index.
+    let data = [0x82, 0x49, 0x83, 0x42, 0x00, 0xc1];
+    assert_eq!(superframe::parse_index(&data).unwrap(), None);
+    assert_eq!(superframe::split(&data).unwrap(), vec![&data[..]]);
+
+    assert_eq!(superframe::parse_index(&[]).unwrap(), None);
+    assert_eq!(superframe::parse_index(&[0x82, 0x49, 0x83]).unwrap(), None);
+}
+
+#[test]
+fn index_larger_than_data() {
+    let data = [0x80, 0xc1, 0x03, 0x02, 0xc1];
+    let err = superframe::parse_index(&data).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::CorruptFrame);
+    assert!(superframe::split(&data).is_err());
+}
+
+#[test]
+fn split() {
+    let frames = superframe::split(TWO_FRAMES).unwrap();
+    assert_eq!(frames, vec![&[0x80, 0x01, 0x02][..], &[0x81, 0x03][..]]);
+}
+
+#[test]
+fn build() {
+    let frames: [&[u8]; 2] = [&[0x80, 0x01, 0x02], &[0x81, 0x03]];
+    assert_eq!(superframe::build(&frames).unwrap(), TWO_FRAMES.to_vec());
+}
+
+#[test]
+fn build_picks_size_bytes() {
+    let big = vec![0x80; 0x10000];
+    let frames: [&[u8]; 2] = [&[0x81], &big[..]];
+    let data = superframe::build(&frames).unwrap();
+    // 3 bytes per size.
+    let marker = 0xc0 | (2 << 3) | 1;
+    assert_eq!(data[data.len() - 1], marker);
+    assert_eq!(&data[data.len() - 8..],
+               &[marker, 0x01, 0x00, 0x00, 0x00, 0x00, 0x01, marker][..]);
+    assert_eq!(superframe::split(&data[..]).unwrap(), frames.to_vec());
+}
+
+#[test]
+fn build_round_trip() {
+    let a = [0x82, 0x49, 0x83, 0x42, 0x00];
+    let b = [0x86, 0x00];
+    let c = [0x87];
+    let frames: [&[u8]; 3] = [&a, &b, &c];
+    let data = superframe::build(&frames).unwrap();
+    assert_eq!(superframe::split(&data[..]).unwrap(), frames.to_vec());
+}
+
+#[test]
+fn build_invalid() {
+    assert!(superframe::build(&[]).is_err());
+    let frame: &[u8] = &[0x80];
+    assert!(superframe::build(&[frame; 9]).is_err());
+    assert!(superframe::build(&[TWO_FRAMES]).is_err());
+}
@@ -0,0 +1,264 @@
+extern crate vpx;
+
+use vpx::ErrorKind;
+use vpx::vp8::header::{self, Dimensions, FilterType, FrameTag, LoopFilterDeltas, Quantizers,
+                       Segmentation};
+
+/// The boolean entropy encoder of section 7.3 of RFC 6386.
+struct BoolEncoder {
+    output: Vec<u8>,
+    range: u32,
+    bottom: u32,
+    bit_count: u32,
+}
+impl BoolEncoder {
+    fn new() -> BoolEncoder {
+        BoolEncoder { output: Vec::new(), range: 255, bottom: 0, bit_count: 24 }
+    }
+    fn add_one_to_output(&mut self) {
+        for b in self.output.iter_mut().rev() {
+            if *b == 255 {
+                *b = 0;
+            } else {
+                *b += 1;
+                break;
+            }
+        }
+    }
+    fn write_bool(&mut self, prob: u8, value: bool) {
+        let split = 1 + (((self.range - 1) * prob as u32) >> 8);
+        if value {
+            self.bottom = self.bottom.wrapping_add(split);
+            self.range -= split;
+        } else {
+            self.range = split;
+        }
+        while self.range < 128 {
+            self.range <<= 1;
+            if self.bottom & (1 << 31) != 0 {
+                self.add_one_to_output();
+            }
+            self.bottom <<= 1;
+            self.bit_count -= 1;
+            if self.bit_count == 0 {
+                self.output.push((self.bottom >> 24) as u8);
+                self.bottom &= (1 << 24) - 1;
+                self.bit_count = 8;
+            }
+        }
+    }
+    fn flush(mut self) -> Vec<u8> {
+        let mut v = self.bottom;
+        if v & (1 << (32 - self.bit_count)) != 0 {
+            self.add_one_to_output();
+        }
+        v <<= self.bit_count & 7;
+        for _ in 0..self.bit_count >> 3 {
+            v <<= 8;
+        }
+        for _ in 0..4 {
+            self.output.push((v >> 24) as u8);
+            v <<= 8;
+        }
+        self.output
+    }
+
+    fn flag(&mut self, v: bool) -> &mut BoolEncoder {
+        self.write_bool(128, v);
+        self
+    }
+    fn literal(&mut self, v: u32, n: u32) -> &mut BoolEncoder {
+        for i in (0..n).rev() {
+            self.flag(v >> i & 1 != 0);
+        }
+        self
+    }
+    fn optional_signed(&mut self, v: Option<i8>, n: u32) -> &mut BoolEncoder {
+        self.flag(v.is_some());
+        if let Some(v) = v {
+            self.literal(v.unsigned_abs() as u32, n).flag(v < 0);
+        }
+        self
+    }
+}
+
+/// A frame with `first_part` as its first partition, and no others.
+fn frame(keyframe: bool, version: u8, first_part: &[u8]) -> Vec<u8> {
+    let tag = FrameTag {
+        keyframe: keyframe,
+        version: version,
+        show_frame: keyframe,
+        first_part_size: first_part.len() as u32,
+    };
+    let mut data = tag.to_bytes().to_vec();
+    if keyframe {
+        data.extend_from_slice(&[0x9d, 0x01, 0x2a]);
+        // 640x480, with the width scaled by 5/4.
+        data.extend_from_slice(&[0x80, 0x42, 0xe0, 0x01]);
+    }
+    data.extend_from_slice(first_part);
+    data
+}
+
+#[test]
+fn frame_tag() {
+    let tag = header::parse_tag(&[0x50, 0x2a, 0x00]).unwrap();
+    assert_eq!(tag, FrameTag {
+        keyframe: true,
+        version: 0,
+        show_frame: true,
+        first_part_size: 338,
+    });
+    assert_eq!(tag.first_part_offset(), 10);
+
+    for &keyframe in [true, false].iter() {
+        for version in 0..4 {
+            for &show_frame in [true, false].iter() {
+                for &first_part_size in [0, 1, 338, 0x7ffff].iter() {
+                    let tag = FrameTag {
+                        keyframe: keyframe,
+                        version: version,
+                        show_frame: show_frame,
+                        first_part_size: first_part_size,
+                    };
+                    assert_eq!(header::parse_tag(&tag.to_bytes()).unwrap(), tag);
+                }
+            }
+        }
+    }
+    assert!(header::parse_tag(&[0x50, 0x2a]).is_err());
+}
+
+#[test]
+fn keyframe() {
+    let mut e = BoolEncoder::new();
+    // YUV, clamping required.
+    e.flag(false).flag(false);
+    // Segmentation, updating the map and absolute data.
+    e.flag(true).flag(true).flag(true).flag(true);
+    for &q in [Some(10), None, Some(-3), None].iter() {
+        e.optional_signed(q, 7);
+    }
+    for &lf in [None, None, None, Some(5)].iter() {
+        e.optional_signed(lf, 6);
+    }
+    e.flag(true).literal(200, 8).flag(false).flag(true).literal(1, 8);
+    // Normal filter, level 32, sharpness 3, with deltas updated.
+    e.flag(false).literal(32, 6).literal(3, 3);
+    e.flag(true).flag(true);
+    for &delta in [Some(2), None, Some(-2), Some(-2), Some(4), Some(-2), Some(2), Some(4)].iter() {
+        e.optional_signed(delta, 6);
+    }
+    // 4 token partitions.
+    e.literal(2, 2);
+    e.literal(100, 7).optional_signed(Some(-2), 4);
+    for _ in 0..4 {
+        e.optional_signed(None, 4);
+    }
+    // refresh_entropy_probs
+    e.flag(true);
+    let data = frame(true, 0, &e.flush());
+
+    let h = header::parse(&data).unwrap();
+    assert_eq!(h.tag.first_part_size as usize, data.len() - 10);
+    assert_eq!(h.dimensions, Some(Dimensions {
+        width: 640,
+        horizontal_scale: 1,
+        height: 480,
+        vertical_scale: 0,
+    }));
+    assert_eq!((h.color_space, h.clamping_required), (0, true));
+    assert_eq!(h.segmentation, Some(Segmentation {
+        update_map: true,
+        update_data: true,
+        absolute_values: true,
+        quantizer: [Some(10), None, Some(-3), None],
+        loop_filter_level: [None, None, None, Some(5)],
+        tree_probs: [Some(200), None, Some(1)],
+    }));
+    assert_eq!(h.filter_type, FilterType::Normal);
+    assert_eq!((h.loop_filter_level, h.sharpness_level), (32, 3));
+    assert_eq!(h.loop_filter_deltas, Some(LoopFilterDeltas {
+        ref_frame: [Some(2), None, Some(-2), Some(-2)],
+        mode: [Some(4), Some(-2), Some(2), Some(4)],
+    }));
+    assert_eq!(h.token_partitions, 4);
+    assert_eq!(h.quantizers, Quantizers {
+        y_ac_qi: 100,
+        y_dc_delta: -2,
+        y2_dc_delta: 0,
+        y2_ac_delta: 0,
+        uv_dc_delta: 0,
+        uv_ac_delta: 0,
+    });
+    assert!(h.refresh_entropy_probs);
+    assert!(h.refresh_golden_frame && h.refresh_alternate_frame && h.refresh_last);
+}
+
+#[test]
+fn inter_frame() {
+    let mut e = BoolEncoder::new();
+    // No segmentation, the simple filter, no deltas and 1 partition.
+    e.flag(false).flag(true).literal(12, 6).literal(0, 3).flag(false).literal(0, 2);
+    e.literal(5, 7);
+    for &delta in [None, None, Some(7), None, Some(-1)].iter() {
+        e.optional_signed(delta, 4);
+    }
+    // Golden copied from the last frame, altref refreshed.
+    e.flag(false).flag(true).literal(1, 2);
+    // Sign biases, refresh_entropy_probs, refresh_last.
+    e.flag(true).flag(false).flag(false).flag(true);
+    // The start of the rest of the partition.
+    e.literal(0x5a5a, 16);
+    let data = frame(false, 1, &e.flush());
+
+    let h = header::parse(&data).unwrap();
+    assert_eq!(h.tag, FrameTag {
+        keyframe: false,
+        version: 1,
+        show_frame: false,
+        first_part_size: data.len() as u32 - 3,
+    });
+    assert_eq!(h.dimensions, None);
+    assert_eq!(h.segmentation, None);
+    assert_eq!(h.filter_type, FilterType::Simple);
+    assert_eq!((h.loop_filter_level, h.sharpness_level), (12, 0));
+    assert_eq!(h.loop_filter_deltas, None);
+    assert_eq!(h.token_partitions, 1);
+    assert_eq!(h.quantizers, Quantizers {
+        y_ac_qi: 5,
+        y_dc_delta: 0,
+        y2_dc_delta: 0,
+        y2_ac_delta: 7,
+        uv_dc_delta: 0,
+        uv_ac_delta: -1,
+    });
+    assert!(!h.refresh_golden_frame && h.refresh_alternate_frame);
+    assert_eq!((h.copy_buffer_to_golden, h.copy_buffer_to_alternate), (1, 0));
+    assert!(h.sign_bias_golden && !h.sign_bias_alternate);
+    assert!(!h.refresh_entropy_probs && h.refresh_last);
+}
+
+#[test]
+fn invalid() {
+    let err = |data: &[u8]| header::parse(data).unwrap_err().kind();
+
+    let mut e = BoolEncoder::new();
+    e.literal(0, 32).literal(0, 32);
+    let data = frame(true, 0, &e.flush());
+    assert!(header::parse(&data).is_ok());
+
+    let mut bad = data.clone();
+    bad[4] = 0;
+    assert_eq!(err(&bad), ErrorKind::CorruptFrame);
+    // In the start code and dimensions.
+    for len in 0..10 {
+        assert_eq!(err(&data[..len]), ErrorKind::CorruptFrame);
+    }
+    // With too little of the first partition for the header.
+    assert_eq!(err(&data[..11]), ErrorKind::CorruptFrame);
+    // The same, going by the tag's size.
+    let mut short = frame(true, 0, &[0]);
+    short.extend_from_slice(&[0; 16]);
+    assert_eq!(err(&short), ErrorKind::CorruptFrame);
+}
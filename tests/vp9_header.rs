@@ -0,0 +1,290 @@
+extern crate vpx;
+
+use vpx::{BitDepth, ColorSpace, ErrorKind};
+use vpx::convert::Range;
+use vpx::vp9::header::{self, ColorConfig, FrameType, Header, InterpolationFilter};
+
+/// Writes fields most significant bit first, like the headers.
+struct BitWriter {
+    data: Vec<u8>,
+    /// In bits.
+    pos: usize,
+}
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter { data: Vec::new(), pos: 0 }
+    }
+    fn put(&mut self, v: u32, n: u32) -> &mut BitWriter {
+        for i in (0..n).rev() {
+            if self.pos % 8 == 0 {
+                self.data.push(0);
+            }
+            if v >> i & 1 != 0 {
+                *self.data.last_mut().unwrap() |= 0x80 >> (self.pos % 8);
+            }
+            self.pos += 1;
+        }
+        self
+    }
+    fn bit(&mut self, v: bool) -> &mut BitWriter { self.put(v as u32, 1) }
+}
+
+/// The frame marker and profile, with `show_existing_frame` unset.
+fn start(w: &mut BitWriter, profile: u32) {
+    w.put(2, 2).put(profile & 1, 1).put(profile >> 1, 1);
+    if profile == 3 {
+        // Reserved.
+        w.put(0, 1);
+    }
+    w.put(0, 1);
+}
+
+fn size(w: &mut BitWriter, width: u32, height: u32) {
+    w.put(width - 1, 16).put(height - 1, 16);
+}
+
+/// From `refresh_frame_context` to `base_q_idx`.
+fn finish(w: &mut BitWriter, error_resilient: bool) {
+    if !error_resilient {
+        // refresh_frame_context, frame_parallel_decoding_mode
+        w.put(1, 1).put(0, 1);
+    }
+    // frame_context_idx, loop_filter_level, sharpness_level
+    w.put(1, 2).put(10, 6).put(2, 3);
+    // Mode and reference deltas enabled and updated, with one delta of -5.
+    w.put(1, 1).put(1, 1);
+    w.put(1, 1).put(5, 6).put(1, 1);
+    w.put(0, 5);
+    // base_q_idx
+    w.put(60, 8);
+}
+
+fn keyframe() -> BitWriter {
+    let mut w = BitWriter::new();
+    start(&mut w, 0);
+    // Keyframe, shown, not error resilient.
+    w.put(0, 1).put(1, 1).put(0, 1);
+    w.put(0x498342, 24);
+    // BT.709, limited range.
+    w.put(2, 3).put(0, 1);
+    size(&mut w, 352, 288);
+    w.put(1, 1);
+    size(&mut w, 176, 144);
+    finish(&mut w, false);
+    w
+}
+
+#[test]
+fn keyframe_header() {
+    let h = header::parse(&keyframe().data).unwrap();
+    assert_eq!(h, Header {
+        profile: 0,
+        frame_type: FrameType::Key,
+        show_frame: true,
+        color_config: Some(ColorConfig {
+            bit_depth: BitDepth::Eight,
+            color_space: ColorSpace::BT709,
+            range: Range::Limited,
+            subsampling_x: true,
+            subsampling_y: true,
+        }),
+        frame_size: Some((352, 288)),
+        render_size: Some((176, 144)),
+        refresh_frame_flags: 0xff,
+        refresh_frame_context: true,
+        frame_context_idx: 1,
+        loop_filter_level: 10,
+        sharpness_level: 2,
+        base_q_idx: 60,
+        ..Default::default()
+    });
+    assert!(h.is_keyframe() && h.is_intra());
+}
+
+#[test]
+fn intra_only_header() {
+    let mut w = BitWriter::new();
+    start(&mut w, 0);
+    // Inter, hidden, not error resilient, intra only, reset_frame_context.
+    w.put(1, 1).put(0, 1).put(0, 1).put(1, 1).put(2, 2);
+    w.put(0x498342, 24);
+    // Profile 0 has no color config here, then refresh_frame_flags.
+    w.put(0x24, 8);
+    size(&mut w, 64, 48);
+    w.put(0, 1);
+    finish(&mut w, false);
+
+    let h = header::parse(&w.data).unwrap();
+    assert_eq!(h.frame_type, FrameType::Inter);
+    assert!(!h.show_frame && h.intra_only && h.is_intra());
+    assert_eq!(h.reset_frame_context, 2);
+    assert_eq!(h.color_config, Some(Default::default()));
+    assert_eq!(h.refresh_frame_flags, 0x24);
+    assert_eq!((h.frame_size, h.render_size), (Some((64, 48)), Some((64, 48))));
+    assert_eq!(h.base_q_idx, 60);
+
+    // Later profiles have one.
+    let mut w = BitWriter::new();
+    start(&mut w, 2);
+    w.put(1, 1).put(0, 1).put(0, 1).put(1, 1).put(0, 2);
+    w.put(0x498342, 24);
+    // 10 bit, BT.2020, full range.
+    w.put(0, 1).put(5, 3).put(1, 1);
+    w.put(0x01, 8);
+    size(&mut w, 64, 48);
+    w.put(0, 1);
+    finish(&mut w, false);
+
+    let h = header::parse(&w.data).unwrap();
+    assert_eq!(h.color_config, Some(ColorConfig {
+        bit_depth: BitDepth::Ten,
+        color_space: ColorSpace::BT2020,
+        range: Range::Full,
+        subsampling_x: true,
+        subsampling_y: true,
+    }));
+}
+
+#[test]
+fn inter_header() {
+    let mut w = BitWriter::new();
+    start(&mut w, 0);
+    // Inter, shown, error resilient.
+    w.put(1, 1).put(1, 1).put(1, 1);
+    w.put(0x81, 8);
+    // ref_frame_idx and ref_frame_sign_bias.
+    w.put(0, 3).bit(false).put(3, 3).bit(false).put(7, 3).bit(true);
+    // The size is the golden frame's.
+    w.put(0, 1).put(1, 1);
+    w.put(0, 1);
+    // allow_high_precision_mv, then not switchable, EIGHTTAP_SHARP.
+    w.put(1, 1).put(0, 1).put(2, 2);
+    finish(&mut w, true);
+
+    let h = header::parse(&w.data).unwrap();
+    assert_eq!(h, Header {
+        frame_type: FrameType::Inter,
+        show_frame: true,
+        error_resilient_mode: true,
+        refresh_frame_flags: 0x81,
+        ref_frame_idx: [0, 3, 7],
+        ref_frame_sign_bias: [false, false, true],
+        size_from_ref: Some(1),
+        allow_high_precision_mv: true,
+        interpolation_filter: Some(InterpolationFilter::EightTapSharp),
+        frame_parallel_decoding_mode: true,
+        frame_context_idx: 1,
+        loop_filter_level: 10,
+        sharpness_level: 2,
+        base_q_idx: 60,
+        ..Default::default()
+    });
+    assert!(!h.is_intra());
+
+    // An explicit size and a switchable filter.
+    let mut w = BitWriter::new();
+    start(&mut w, 0);
+    w.put(1, 1).put(1, 1).put(0, 1).put(0, 2);
+    w.put(0x01, 8).put(0, 12);
+    w.put(0, 3);
+    size(&mut w, 640, 360);
+    w.put(0, 1);
+    w.put(0, 1).put(1, 1);
+    finish(&mut w, false);
+
+    let h = header::parse(&w.data).unwrap();
+    assert_eq!(h.size_from_ref, None);
+    assert_eq!((h.frame_size, h.render_size), (Some((640, 360)), Some((640, 360))));
+    assert_eq!(h.interpolation_filter, Some(InterpolationFilter::Switchable));
+}
+
+#[test]
+fn show_existing_frame() {
+    let mut w = BitWriter::new();
+    w.put(2, 2).put(0, 2).put(1, 1).put(5, 3);
+    let h = header::parse(&w.data).unwrap();
+    assert_eq!(h.show_existing_frame, Some(5));
+}
+
+/// A keyframe in `profile`, with the given color config bits.
+fn keyframe_with(profile: u32, color: &[(u32, u32)]) -> Vec<u8> {
+    let mut w = BitWriter::new();
+    start(&mut w, profile);
+    w.put(0, 1).put(1, 1).put(0, 1);
+    w.put(0x498342, 24);
+    for &(v, n) in color {
+        w.put(v, n);
+    }
+    size(&mut w, 16, 16);
+    w.put(0, 1);
+    finish(&mut w, false);
+    w.data
+}
+
+fn color(bit_depth: BitDepth, color_space: ColorSpace, range: Range,
+         x: bool, y: bool) -> ColorConfig {
+    ColorConfig {
+        bit_depth: bit_depth,
+        color_space: color_space,
+        range: range,
+        subsampling_x: x,
+        subsampling_y: y,
+    }
+}
+
+#[test]
+fn profiles() {
+    let cases: [(u32, &[(u32, u32)], ColorConfig); 6] = [
+        // BT.601, full range.
+        (0, &[(1, 3), (1, 1)],
+         color(BitDepth::Eight, ColorSpace::BT601, Range::Full, true, true)),
+        // 4:2:2, then the reserved bit.
+        (1, &[(3, 3), (0, 1), (1, 1), (0, 1), (0, 1)],
+         color(BitDepth::Eight, ColorSpace::SMPTE170, Range::Limited, true, false)),
+        // RGB is 4:4:4, then the reserved bit.
+        (1, &[(7, 3), (0, 1)],
+         color(BitDepth::Eight, ColorSpace::SRGB, Range::Full, false, false)),
+        (2, &[(1, 1), (2, 3), (0, 1)],
+         color(BitDepth::Twelve, ColorSpace::BT709, Range::Limited, true, true)),
+        // 4:4:0
+        (3, &[(0, 1), (4, 3), (1, 1), (0, 1), (1, 1), (0, 1)],
+         color(BitDepth::Ten, ColorSpace::SMPTE240, Range::Full, false, true)),
+        (3, &[(1, 1), (7, 3), (0, 1)],
+         color(BitDepth::Twelve, ColorSpace::SRGB, Range::Full, false, false)),
+    ];
+    for &(profile, bits, color) in cases.iter() {
+        let h = header::parse(&keyframe_with(profile, bits)).unwrap();
+        assert_eq!(h.profile as u32, profile);
+        assert_eq!(h.color_config, Some(color), "profile {}", profile);
+        assert_eq!(h.frame_size, Some((16, 16)));
+        assert_eq!(h.base_q_idx, 60);
+    }
+}
+
+#[test]
+fn invalid() {
+    let err = |data: &[u8]| header::parse(data).unwrap_err().kind();
+
+    // RGB isn't allowed in profile 0.
+    assert_eq!(err(&keyframe_with(0, &[(7, 3)])), ErrorKind::UnsupportedBitstream);
+    // The reserved bit after the subsampling.
+    assert_eq!(err(&keyframe_with(1, &[(1, 3), (0, 1), (1, 1), (1, 1), (1, 1)])),
+               ErrorKind::CorruptFrame);
+
+    let mut data = keyframe().data;
+    data[0] &= 0x3f;
+    assert_eq!(err(&data), ErrorKind::CorruptFrame);
+    let mut data = keyframe().data;
+    data[2] ^= 0x10;
+    assert_eq!(err(&data), ErrorKind::CorruptFrame);
+}
+
+#[test]
+fn truncated() {
+    let full = keyframe().data;
+    assert!(header::parse(&full).is_ok());
+    for len in 0..full.len() {
+        assert_eq!(header::parse(&full[..len]).unwrap_err().kind(), ErrorKind::CorruptFrame,
+                   "{} bytes", len);
+    }
+}
@@ -0,0 +1,402 @@
+extern crate vpx;
+extern crate vpx_sys as ffi;
+
+use std::io::Cursor;
+
+use vpx::{BitDepth, Codec, ColorSpace, Frame, FRAME_IS_DROPPABLE, FRAME_IS_FRAGMENT,
+          FRAME_IS_INVISIBLE, FRAME_IS_KEY};
+use vpx::convert::Range;
+use vpx::encoder::PacketWriter;
+use vpx::webm::{ebml, Demuxer, Muxer, Track};
+use vpx::webm::demux::VideoTrack;
+
+const EBML: u32 = 0x1A45DFA3;
+const DOC_TYPE: u32 = 0x4282;
+const SEGMENT: u32 = 0x18538067;
+const SEEK_HEAD: u32 = 0x114D9B74;
+const SEEK: u32 = 0x4DBB;
+const SEEK_ID: u32 = 0x53AB;
+const SEEK_POSITION: u32 = 0x53AC;
+const VOID: u32 = 0xEC;
+const INFO: u32 = 0x1549A966;
+const DURATION: u32 = 0x4489;
+const TRACKS: u32 = 0x1654AE6B;
+const TRACK_ENTRY: u32 = 0xAE;
+const TRACK_NUMBER: u32 = 0xD7;
+const TRACK_TYPE: u32 = 0x83;
+const CODEC_ID: u32 = 0x86;
+const VIDEO: u32 = 0xE0;
+const PIXEL_WIDTH: u32 = 0xB0;
+const PIXEL_HEIGHT: u32 = 0xBA;
+const COLOUR: u32 = 0x55B0;
+const MATRIX_COEFFICIENTS: u32 = 0x55B1;
+const BITS_PER_CHANNEL: u32 = 0x55B2;
+const RANGE: u32 = 0x55B9;
+const TRANSFER_CHARACTERISTICS: u32 = 0x55BA;
+const PRIMARIES: u32 = 0x55BB;
+const CLUSTER: u32 = 0x1F43B675;
+const TIMECODE: u32 = 0xE7;
+const SIMPLE_BLOCK: u32 = 0xA3;
+const BLOCK_GROUP: u32 = 0xA0;
+const BLOCK: u32 = 0xA1;
+const REFERENCE_BLOCK: u32 = 0xFB;
+const CUES: u32 = 0x1C53BB6B;
+const CUE_POINT: u32 = 0xBB;
+const CUE_TIME: u32 = 0xB3;
+const CUE_TRACK_POSITIONS: u32 = 0xB7;
+const CUE_CLUSTER_POSITION: u32 = 0xF1;
+
+/// The muxer reserves this much for the `SeekHead`.
+const SEEK_HEAD_RESERVE: usize = 80;
+
+struct Cfg(ffi::vpx_codec_enc_cfg_t);
+impl AsRef<ffi::vpx_codec_enc_cfg_t> for Cfg {
+    fn as_ref(&self) -> &ffi::vpx_codec_enc_cfg_t { &self.0 }
+}
+
+fn cfg() -> Cfg {
+    let mut cfg: ffi::vpx_codec_enc_cfg_t = Default::default();
+    cfg.g_w = 320;
+    cfg.g_h = 240;
+    // Milliseconds.
+    cfg.g_timebase = ffi::vpx_rational_t { num: 1, den: 1000 };
+    cfg.g_bit_depth = BitDepth::Ten.into();
+    Cfg(cfg)
+}
+
+fn write(muxer: &mut Muxer<Cursor<Vec<u8>>>, data: &[u8], pts: i64, flags: u32) {
+    let raw = ffi::Struct_Unnamed6 {
+        buf: data.as_ptr() as *mut _,
+        sz: data.len() as _,
+        pts: pts,
+        duration: 10,
+        flags: flags,
+        partition_id: 0,
+    };
+    muxer.write_frame(&Frame::from(&raw)).unwrap();
+}
+
+/// An element: its ID, the offset of its header in the data it was parsed
+/// from, and its contents.
+struct Element<'a> {
+    id: u32,
+    offset: usize,
+    data: &'a [u8],
+}
+
+fn elements<'a>(data: &'a [u8]) -> Vec<Element<'a>> {
+    let mut elements = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let (id, id_len) = ebml::parse_vint(&data[pos..], true).unwrap();
+        let (size, size_len) = ebml::parse_vint(&data[pos + id_len..], false).unwrap();
+        let start = pos + id_len + size_len;
+        let end = start + size as usize;
+        elements.push(Element {
+            id: id as u32,
+            offset: pos,
+            data: &data[start..end],
+        });
+        pos = end;
+    }
+    elements
+}
+
+fn child(data: &[u8], id: u32) -> &[u8] {
+    elements(data).into_iter().find(|e| e.id == id).unwrap().data
+}
+
+fn mux() -> Vec<u8> {
+    let track = Track::new(Codec::VP9, &cfg())
+        .color_space(ColorSpace::BT709)
+        .range(Range::Limited);
+    let mut muxer = Muxer::new(Cursor::new(Vec::new()), track).unwrap();
+    write(&mut muxer, &[1, 1], 0, FRAME_IS_KEY);
+    write(&mut muxer, &[2], 33, FRAME_IS_DROPPABLE);
+    // Output in partitions.
+    write(&mut muxer, &[3, 3], 66, FRAME_IS_FRAGMENT);
+    write(&mut muxer, &[3], 66, 0);
+    // Keyframes start a cluster, with a cue.
+    write(&mut muxer, &[4], 100, FRAME_IS_KEY);
+    write(&mut muxer, &[5], 133, FRAME_IS_INVISIBLE);
+    // Too far from the cluster's timecode for a block's 16 bits.
+    write(&mut muxer, &[6], 40000, 0);
+    muxer.finish().unwrap().into_inner()
+}
+
+#[test]
+fn mux_demux() {
+    let file = mux();
+    let mut demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+    assert_eq!(demuxer.tracks(), &[VideoTrack {
+        number: 1,
+        codec: Codec::VP9,
+        width: 320,
+        height: 240,
+    }]);
+
+    let frames: Vec<_> = demuxer.by_ref().map(|f| f.unwrap()).collect();
+    let got: Vec<_> = frames.iter()
+        .map(|f| (f.track, f.timestamp / 1_000_000, f.keyframe, f.invisible, &f.data[..]))
+        .collect();
+    let want: Vec<(u64, i64, bool, bool, &[u8])> = vec![
+        (1, 0, true, false, &[1, 1]),
+        (1, 33, false, false, &[2]),
+        (1, 66, false, false, &[3, 3, 3]),
+        (1, 100, true, false, &[4]),
+        (1, 133, false, true, &[5]),
+        (1, 40000, false, false, &[6]),
+    ];
+    assert_eq!(got, want);
+    assert!(demuxer.read_frame().unwrap().is_none());
+}
+
+#[test]
+fn structure() {
+    let file = mux();
+    // The segment's size was patched in, so it covers the rest of the file.
+    let top = elements(&file[..]);
+    assert_eq!(top.len(), 2);
+    assert_eq!(top[1].id, SEGMENT);
+    let segment = top[1].data;
+
+    let children = elements(segment);
+    // The seek head, padded to the reserved space with a void.
+    assert_eq!(children[0].id, SEEK_HEAD);
+    assert_eq!(children[1].id, VOID);
+    assert_eq!(children[2].offset, SEEK_HEAD_RESERVE);
+
+    let mut seeks = Vec::new();
+    for seek in elements(children[0].data) {
+        assert_eq!(seek.id, SEEK);
+        let id = ebml::parse_uint(child(seek.data, SEEK_ID)) as u32;
+        let pos = ebml::parse_uint(child(seek.data, SEEK_POSITION)) as usize;
+        // Positions are relative to the segment's data.
+        assert_eq!(ebml::parse_vint(&segment[pos..], true).unwrap().0 as u32, id);
+        seeks.push(id);
+    }
+    assert_eq!(seeks, vec![INFO, TRACKS, CUES]);
+
+    let info = children.iter().find(|e| e.id == INFO).unwrap();
+    // The end of the last frame, in milliseconds.
+    assert_eq!(ebml::parse_float(child(info.data, DURATION)), Some(40010.0));
+
+    let tracks = children.iter().find(|e| e.id == TRACKS).unwrap();
+    let colour = child(child(child(tracks.data, TRACK_ENTRY), VIDEO), COLOUR);
+    let values: Vec<_> = [MATRIX_COEFFICIENTS, TRANSFER_CHARACTERISTICS, PRIMARIES,
+                          BITS_PER_CHANNEL, RANGE].iter()
+        .map(|&id| ebml::parse_uint(child(colour, id)))
+        .collect();
+    assert_eq!(values, vec![1, 1, 1, 10, 1]);
+
+    let clusters: Vec<_> = children.iter().filter(|e| e.id == CLUSTER).collect();
+    let timecodes: Vec<_> = clusters.iter()
+        .map(|c| ebml::parse_uint(child(c.data, TIMECODE)))
+        .collect();
+    assert_eq!(timecodes, vec![0, 100, 40000]);
+
+    // Only the clusters starting with keyframes are cued.
+    let cues = children.iter().find(|e| e.id == CUES).unwrap();
+    let points: Vec<_> = elements(cues.data).iter()
+        .map(|point| {
+            assert_eq!(point.id, CUE_POINT);
+            let positions = child(point.data, CUE_TRACK_POSITIONS);
+            (ebml::parse_uint(child(point.data, CUE_TIME)),
+             ebml::parse_uint(child(positions, CUE_CLUSTER_POSITION)) as usize)
+        })
+        .collect();
+    assert_eq!(points, vec![(0, clusters[0].offset), (100, clusters[1].offset)]);
+}
+
+#[test]
+fn no_colour() {
+    let mut cfg = cfg();
+    cfg.0.g_bit_depth = 0;
+    let muxer = Muxer::new(Cursor::new(Vec::new()), Track::new(Codec::VP8, &cfg)).unwrap();
+    let file = muxer.finish().unwrap().into_inner();
+
+    let top = elements(&file[..]);
+    let children = elements(top[1].data);
+    let tracks = children.iter().find(|e| e.id == TRACKS).unwrap();
+    let video = child(child(tracks.data, TRACK_ENTRY), VIDEO);
+    assert!(elements(video).iter().all(|e| e.id != COLOUR));
+    // No frames, so no cues.
+    assert!(children.iter().all(|e| e.id != CUES && e.id != CLUSTER));
+
+    let demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+    assert_eq!(demuxer.tracks()[0].codec, Codec::VP8);
+}
+
+/// A file with a VP8 track 1 and an audio track 2, then `body` in a
+/// segment of unknown size.
+fn fixture(body: &[u8]) -> Vec<u8> {
+    let mut header = Vec::new();
+    ebml::put_str(&mut header, DOC_TYPE, "webm");
+    let mut file = Vec::new();
+    ebml::put_bytes(&mut file, EBML, &header);
+
+    let mut video = Vec::new();
+    ebml::put_uint(&mut video, PIXEL_WIDTH, 64);
+    ebml::put_uint(&mut video, PIXEL_HEIGHT, 48);
+    let mut entry = Vec::new();
+    ebml::put_uint(&mut entry, TRACK_NUMBER, 1);
+    ebml::put_uint(&mut entry, TRACK_TYPE, 1);
+    ebml::put_str(&mut entry, CODEC_ID, "V_VP8");
+    ebml::put_bytes(&mut entry, VIDEO, &video);
+    let mut tracks = Vec::new();
+    ebml::put_bytes(&mut tracks, TRACK_ENTRY, &entry);
+    let mut entry = Vec::new();
+    ebml::put_uint(&mut entry, TRACK_NUMBER, 2);
+    ebml::put_uint(&mut entry, TRACK_TYPE, 2);
+    ebml::put_str(&mut entry, CODEC_ID, "A_OPUS");
+    ebml::put_bytes(&mut tracks, TRACK_ENTRY, &entry);
+
+    ebml::put_id(&mut file, SEGMENT);
+    ebml::put_size_len(&mut file, ebml::UNKNOWN_SIZE, 8);
+    ebml::put_bytes(&mut file, TRACKS, &tracks);
+    file.extend_from_slice(body);
+    file
+}
+
+/// The contents of a `Block` or `SimpleBlock`.
+fn block(track: u8, timecode: i16, flags: u8, data: &[u8]) -> Vec<u8> {
+    let mut block = vec![0x80 | track, (timecode >> 8) as u8, timecode as u8, flags];
+    block.extend_from_slice(data);
+    block
+}
+
+fn demux(file: Vec<u8>) -> Vec<(i64, bool, bool, Vec<u8>)> {
+    let demuxer = Demuxer::new(Cursor::new(file)).unwrap();
+    demuxer.map(|f| f.unwrap())
+        .map(|f| (f.timestamp / 1_000_000, f.keyframe, f.invisible, f.data))
+        .collect()
+}
+
+#[test]
+fn unknown_size_clusters() {
+    let mut body = Vec::new();
+    // Ended by the next cluster.
+    ebml::put_id(&mut body, CLUSTER);
+    ebml::put_size_len(&mut body, ebml::UNKNOWN_SIZE, 8);
+    ebml::put_uint(&mut body, TIMECODE, 10);
+    ebml::put_bytes(&mut body, SIMPLE_BLOCK, &block(1, 0, 0x80, &[1]));
+    ebml::put_bytes(&mut body, SIMPLE_BLOCK, &block(2, 0, 0x80, &[0xaa]));
+    ebml::put_bytes(&mut body, SIMPLE_BLOCK, &block(1, 5, 0, &[2]));
+    // Ended by the cues.
+    ebml::put_id(&mut body, CLUSTER);
+    // A one byte unknown size.
+    ebml::put_size_len(&mut body, 0x7f, 1);
+    ebml::put_uint(&mut body, TIMECODE, 20);
+    ebml::put_bytes(&mut body, SIMPLE_BLOCK, &block(1, -5, 0x08, &[3]));
+    let mut cue = Vec::new();
+    ebml::put_uint(&mut cue, CUE_TIME, 10);
+    let mut cues = Vec::new();
+    ebml::put_bytes(&mut cues, CUE_POINT, &cue);
+    ebml::put_bytes(&mut body, CUES, &cues);
+    // A cluster of known size after them.
+    let mut cluster = Vec::new();
+    ebml::put_uint(&mut cluster, TIMECODE, 30);
+    ebml::put_bytes(&mut cluster, SIMPLE_BLOCK, &block(1, 0, 0x80, &[4]));
+    ebml::put_bytes(&mut body, CLUSTER, &cluster);
+
+    assert_eq!(demux(fixture(&body)), vec![
+        (10, true, false, vec![1]),
+        (15, false, false, vec![2]),
+        (15, false, true, vec![3]),
+        (30, true, false, vec![4]),
+    ]);
+}
+
+#[test]
+fn lacing() {
+    let mut cluster = Vec::new();
+    ebml::put_uint(&mut cluster, TIMECODE, 0);
+
+    // Xiph: 2, 300, then the rest.
+    let mut xiph = vec![2, 2, 255, 45];
+    xiph.extend_from_slice(&[1; 2]);
+    xiph.extend_from_slice(&[2; 300]);
+    xiph.extend_from_slice(&[3; 1]);
+    ebml::put_bytes(&mut cluster, SIMPLE_BLOCK, &block(1, 0, 0x80 | 0x02, &xiph));
+
+    // Fixed: three of 2 bytes each.
+    let fixed = [2, 4, 4, 5, 5, 6, 6];
+    ebml::put_bytes(&mut cluster, SIMPLE_BLOCK, &block(1, 1, 0x04, &fixed));
+
+    // EBML: 3, then +2 and -3 as signed differences, then the rest.
+    let mut laced = vec![3, 0x83, 0x80 | (63 + 2), 0x80 | (63 - 3)];
+    laced.extend_from_slice(&[7; 3]);
+    laced.extend_from_slice(&[8; 5]);
+    laced.extend_from_slice(&[9; 2]);
+    laced.extend_from_slice(&[10; 1]);
+    ebml::put_bytes(&mut cluster, SIMPLE_BLOCK, &block(1, 2, 0x06, &laced));
+
+    let mut body = Vec::new();
+    ebml::put_bytes(&mut body, CLUSTER, &cluster);
+    let frames = demux(fixture(&body));
+    let got: Vec<_> = frames.iter().map(|f| (f.0, f.1, f.3.clone())).collect();
+    assert_eq!(got, vec![
+        (0, true, vec![1; 2]),
+        (0, true, vec![2; 300]),
+        (0, true, vec![3; 1]),
+        (1, false, vec![4; 2]),
+        (1, false, vec![5; 2]),
+        (1, false, vec![6; 2]),
+        (2, false, vec![7; 3]),
+        (2, false, vec![8; 5]),
+        (2, false, vec![9; 2]),
+        (2, false, vec![10; 1]),
+    ]);
+}
+
+#[test]
+fn bad_lacing() {
+    // Fixed lacing of 5 bytes into two frames.
+    let mut cluster = Vec::new();
+    ebml::put_bytes(&mut cluster, SIMPLE_BLOCK, &block(1, 0, 0x04, &[1, 1, 2, 3, 4, 5]));
+    let mut body = Vec::new();
+    ebml::put_bytes(&mut body, CLUSTER, &cluster);
+    let mut demuxer = Demuxer::new(Cursor::new(fixture(&body))).unwrap();
+    assert!(demuxer.read_frame().is_err());
+
+    // A Xiph size running past the end.
+    let mut cluster = Vec::new();
+    ebml::put_bytes(&mut cluster, SIMPLE_BLOCK, &block(1, 0, 0x02, &[1, 255]));
+    let mut body = Vec::new();
+    ebml::put_bytes(&mut body, CLUSTER, &cluster);
+    let mut demuxer = Demuxer::new(Cursor::new(fixture(&body))).unwrap();
+    assert!(demuxer.read_frame().is_err());
+}
+
+#[test]
+fn block_groups() {
+    let mut cluster = Vec::new();
+    ebml::put_uint(&mut cluster, TIMECODE, 100);
+    // No references, so a keyframe.
+    let mut group = Vec::new();
+    ebml::put_bytes(&mut group, BLOCK, &block(1, 0, 0, &[1]));
+    ebml::put_bytes(&mut cluster, BLOCK_GROUP, &group);
+    // A `Block`'s flags have no keyframe bit, only the references count.
+    let mut group = Vec::new();
+    ebml::put_bytes(&mut group, BLOCK, &block(1, 10, 0x80 | 0x08, &[2]));
+    ebml::put_bytes(&mut group, REFERENCE_BLOCK, &[0xf6]);
+    ebml::put_bytes(&mut cluster, BLOCK_GROUP, &group);
+    // Another track's.
+    let mut group = Vec::new();
+    ebml::put_bytes(&mut group, BLOCK, &block(2, 20, 0, &[3]));
+    ebml::put_bytes(&mut cluster, BLOCK_GROUP, &group);
+    // Laced.
+    let mut group = Vec::new();
+    ebml::put_bytes(&mut group, BLOCK, &block(1, 30, 0x04, &[1, 4, 5]));
+    ebml::put_bytes(&mut group, REFERENCE_BLOCK, &[0xec]);
+    ebml::put_bytes(&mut cluster, BLOCK_GROUP, &group);
+
+    let mut body = Vec::new();
+    ebml::put_bytes(&mut body, CLUSTER, &cluster);
+    assert_eq!(demux(fixture(&body)), vec![
+        (100, true, false, vec![1]),
+        (110, false, true, vec![2]),
+        (130, false, false, vec![4]),
+        (130, false, false, vec![5]),
+    ]);
+}